@@ -29,8 +29,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Server 3 unique visitors: {}", server3.count());
 
     // Merge all servers to get total unique visitors
-    let mut total = server1.clone();
-    total.merge(&server2)?;
+    let mut total = server1.union(&server2)?;
     total.merge(&server3)?;
 
     let estimated_total = total.count();
@@ -1,9 +1,9 @@
 //! Compares different precision values and their accuracy/memory tradeoffs.
 
-use hyperloglog::HyperLogLog;
+use hyperloglog::{HyperLogLog, PrecisionInfo};
 
-fn test_precision(precision: u8, n_items: usize) -> Result<(), Box<dyn std::error::Error>> {
-    let mut hll = HyperLogLog::new(precision)?;
+fn test_precision(info: &PrecisionInfo, n_items: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let mut hll = HyperLogLog::new(info.precision)?;
 
     for i in 0..n_items {
         hll.add(&i);
@@ -11,11 +11,13 @@ fn test_precision(precision: u8, n_items: usize) -> Result<(), Box<dyn std::erro
 
     let estimated = hll.count();
     let error = ((estimated as f64 - n_items as f64) / n_items as f64).abs() * 100.0;
-    let memory = 1 << precision; // 2^precision bytes
 
     println!(
         "Precision {:2} | Memory: {:6} bytes | Estimated: {:8} | Error: {:5.2}%",
-        precision, memory, estimated, error
+        info.precision,
+        hll.memory_bytes(),
+        estimated,
+        error
     );
 
     Ok(())
@@ -23,17 +25,20 @@ fn test_precision(precision: u8, n_items: usize) -> Result<(), Box<dyn std::erro
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let n_items = 100_000;
+    let table: Vec<PrecisionInfo> = HyperLogLog::precision_table()
+        .into_iter()
+        .filter(|info| [10, 12, 14, 16].contains(&info.precision))
+        .collect();
 
     println!("Testing with {} unique items\n", n_items);
     println!("Theoretical error rates:");
-    println!("  Precision 10: ~1.625%");
-    println!("  Precision 12: ~0.813%");
-    println!("  Precision 14: ~0.406%");
-    println!("  Precision 16: ~0.203%");
+    for info in &table {
+        println!("  Precision {}: ~{:.3}%", info.precision, info.relative_error * 100.0);
+    }
     println!();
 
-    for precision in [10, 12, 14, 16] {
-        test_precision(precision, n_items)?;
+    for info in &table {
+        test_precision(info, n_items)?;
     }
 
     println!("\nConclusion:");
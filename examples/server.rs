@@ -3,7 +3,7 @@
 //! Run with: `cargo run --example server --features server`
 
 use hyperloglog::api::{create_router, AppState};
-use hyperloglog::storage::FileStorage;
+use hyperloglog::storage::{FileStorage, MemoryStorage};
 use hyperloglog::Storage;
 use std::sync::Arc;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -11,6 +11,15 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 #[cfg(feature = "elasticsearch-storage")]
 use hyperloglog::storage::ElasticsearchStorage;
 
+#[cfg(feature = "redis-storage")]
+use hyperloglog::storage::RedisStorage;
+
+#[cfg(feature = "sqlite-storage")]
+use hyperloglog::storage::SqliteStorage;
+
+#[cfg(feature = "s3-storage")]
+use hyperloglog::storage::S3Storage;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize tracing
@@ -35,12 +44,59 @@ async fn main() -> anyhow::Result<()> {
                 .unwrap_or_else(|_| "hyperloglog".to_string());
 
             tracing::info!("Using Elasticsearch storage at {} with index {}", es_url, index_name);
-            Arc::new(ElasticsearchStorage::with_url(&es_url, index_name)?)
+            // A comma-separated list spreads requests over several nodes
+            Arc::new(
+                ElasticsearchStorage::builder(index_name)
+                    .nodes(es_url.split(',').map(str::trim))
+                    .build()?,
+            )
         }
         #[cfg(not(feature = "elasticsearch-storage"))]
         "elasticsearch" => {
             panic!("Elasticsearch storage requested but feature not enabled. Rebuild with --features elasticsearch-storage");
         }
+        #[cfg(feature = "redis-storage")]
+        "redis" => {
+            let redis_url = std::env::var("REDIS_URL")
+                .unwrap_or_else(|_| "redis://localhost:6379".to_string());
+
+            tracing::info!("Using Redis storage at {}", redis_url);
+            Arc::new(RedisStorage::new(&redis_url).await?)
+        }
+        #[cfg(not(feature = "redis-storage"))]
+        "redis" => {
+            panic!("Redis storage requested but feature not enabled. Rebuild with --features redis-storage");
+        }
+        #[cfg(feature = "sqlite-storage")]
+        "sqlite" => {
+            let sqlite_path = std::env::var("SQLITE_PATH")
+                .unwrap_or_else(|_| "./hyperloglog.db".to_string());
+
+            tracing::info!("Using SQLite storage at {}", sqlite_path);
+            Arc::new(SqliteStorage::new(&sqlite_path).await?)
+        }
+        #[cfg(not(feature = "sqlite-storage"))]
+        "sqlite" => {
+            panic!("SQLite storage requested but feature not enabled. Rebuild with --features sqlite-storage");
+        }
+        #[cfg(feature = "s3-storage")]
+        "s3" => {
+            let bucket = std::env::var("S3_BUCKET")
+                .unwrap_or_else(|_| "hyperloglog".to_string());
+            let prefix = std::env::var("S3_PREFIX")
+                .unwrap_or_else(|_| "hll".to_string());
+
+            tracing::info!("Using S3 storage in bucket {} under {}/", bucket, prefix);
+            Arc::new(S3Storage::new(bucket, prefix).await?)
+        }
+        #[cfg(not(feature = "s3-storage"))]
+        "s3" => {
+            panic!("S3 storage requested but feature not enabled. Rebuild with --features s3-storage");
+        }
+        "memory" => {
+            tracing::info!("Using in-memory storage; sketches are lost on shutdown");
+            Arc::new(MemoryStorage::new())
+        }
         _ => {
             let base_path = std::env::var("FILE_STORAGE_PATH")
                 .unwrap_or_else(|_| "./data".to_string());
@@ -50,8 +106,51 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
+    let default_precision: Option<u8> = std::env::var("DEFAULT_PRECISION")
+        .ok()
+        .map(|precision| precision.parse())
+        .transpose()?;
+
     // Create application state
-    let state = AppState::new(storage);
+    let mut state = AppState::new(storage.clone());
+    if let Ok(max_len) = std::env::var("MAX_ELEMENT_LEN") {
+        let max_len = max_len.parse()?;
+        tracing::info!("Rejecting elements longer than {} bytes", max_len);
+        state = state.with_max_element_len(max_len);
+    }
+    if let Some(precision) = default_precision {
+        tracing::info!("Creating new keys at precision {}", precision);
+        state = state.with_default_precision(precision)?;
+    }
+    if let Ok(token) = std::env::var("AUTH_TOKEN") {
+        let protect_reads = std::env::var("AUTH_READS").is_ok_and(|value| value == "true");
+        tracing::info!("Requiring a bearer token for writes{}", if protect_reads { " and reads" } else { "" });
+        state = state.with_auth_token(token).require_auth_for_reads(protect_reads);
+    }
+
+    // Serve the same storage to Redis clients, locking keys with the HTTP API
+    #[cfg(feature = "resp-server")]
+    if let Ok(resp_addr) = std::env::var("RESP_BIND_ADDRESS") {
+        let listener = tokio::net::TcpListener::bind(&resp_addr).await?;
+        tracing::info!("Starting RESP server on {}", resp_addr);
+        let mut server = hyperloglog::resp::RespServer::new(storage).with_locks_from(&state);
+        if let Some(precision) = default_precision {
+            server = server.with_default_precision(precision)?;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = server.serve(listener).await {
+                tracing::error!("RESP server stopped: {}", e);
+            }
+        });
+    }
+
+    // Export key saturation for alerting, 100 keys per minute
+    #[cfg(feature = "metrics")]
+    hyperloglog::api::spawn_saturation_sampler(
+        state.clone(),
+        std::time::Duration::from_secs(60),
+        100,
+    );
 
     // Build router
     let app = create_router(state);
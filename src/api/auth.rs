@@ -0,0 +1,164 @@
+use super::handlers::ErrorResponse;
+use super::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+/// Bearer token a request must carry, and whether reads need it too
+#[derive(Debug, Clone)]
+pub(crate) struct AuthConfig {
+    pub(crate) token: String,
+    pub(crate) protect_reads: bool,
+}
+
+/// Middleware guarding the routes that modify stored keys
+pub async fn require_token_for_writes(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    match state.auth() {
+        Some(auth) if !authorized(request.headers(), &auth.token) => unauthorized(),
+        _ => next.run(request).await,
+    }
+}
+
+/// Middleware guarding the read-only routes, if configured to
+pub async fn require_token_for_reads(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    match state.auth() {
+        Some(auth) if auth.protect_reads && !authorized(request.headers(), &auth.token) => unauthorized(),
+        _ => next.run(request).await,
+    }
+}
+
+/// Whether the `Authorization` header is `Bearer <token>`
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|presented| constant_time_eq(presented.trim().as_bytes(), token.as_bytes()))
+}
+
+/// Compare without an early exit, so response times don't reveal how much
+/// of a guessed token is right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        Json(ErrorResponse {
+            error: "Missing or invalid bearer token".to_string(),
+        }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{create_router, AppState};
+    use crate::storage::MemoryStorage;
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{header, StatusCode},
+        Router,
+    };
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn status(app: &Router, method: &str, uri: &str, authorization: Option<&str>) -> StatusCode {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let body = if method == "POST" {
+            Body::from(r#"{"elements": ["a"]}"#)
+        } else {
+            Body::empty()
+        };
+        app.clone().oneshot(request.body(body).unwrap()).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_writes_require_token() {
+        let state = AppState::new(Arc::new(MemoryStorage::new())).with_auth_token("s3cret");
+        let app = create_router(state);
+
+        for authorization in [None, Some("Bearer wrong"), Some("Bearer s3cre"), Some("s3cret"), Some("Basic s3cret")] {
+            assert_eq!(
+                status(&app, "POST", "/pfadd/visitors", authorization).await,
+                StatusCode::UNAUTHORIZED,
+                "Accepted {:?}",
+                authorization
+            );
+            assert_eq!(
+                status(&app, "DELETE", "/delete/visitors", authorization).await,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        // Nothing was written
+        assert_eq!(status(&app, "GET", "/exists/visitors", None).await, StatusCode::OK);
+        assert_eq!(status(&app, "GET", "/info/visitors", None).await, StatusCode::NOT_FOUND);
+
+        let token = Some("Bearer s3cret");
+        assert_eq!(status(&app, "POST", "/pfadd/visitors", token).await, StatusCode::OK);
+        // Reads stay open by default
+        assert_eq!(status(&app, "GET", "/pfcount/visitors", None).await, StatusCode::OK);
+        assert_eq!(status(&app, "DELETE", "/delete/visitors", token).await, StatusCode::OK);
+
+        let request = Request::post("/pfmerge/union")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"source_keys": ["visitors"]}"#))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn test_reads_can_require_token() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()))
+            .with_auth_token("s3cret")
+            .require_auth_for_reads(true);
+        let app = create_router(state);
+
+        assert_eq!(status(&app, "GET", "/pfcount/visitors", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(&app, "GET", "/keys", Some("Bearer wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&app, "GET", "/pfcount/visitors", Some("Bearer s3cret")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_metrics_follow_read_auth() {
+        let state = AppState::new(Arc::new(MemoryStorage::new())).with_auth_token("s3cret");
+        let app = create_router(state.clone());
+        assert_eq!(status(&app, "GET", "/metrics", None).await, StatusCode::OK);
+
+        let app = create_router(state.require_auth_for_reads(true));
+        assert_eq!(status(&app, "GET", "/metrics", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(&app, "GET", "/metrics", Some("Bearer s3cret")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_token_configured() {
+        let app = create_router(AppState::new(Arc::new(MemoryStorage::new())));
+        assert_eq!(status(&app, "POST", "/pfadd/visitors", None).await, StatusCode::OK);
+        assert_eq!(status(&app, "DELETE", "/delete/visitors", None).await, StatusCode::OK);
+    }
+}
@@ -1,12 +1,22 @@
-use crate::{HyperLogLog, HllError};
+use crate::{EstimateQuality, HllSummary, HyperLogLog, HllError};
 use super::AppState;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Create an empty key at a chosen precision
+#[derive(Debug, Deserialize)]
+pub struct CreateRequest {
+    pub precision: u8,
+}
 
 /// Redis PFADD command - Add elements to HyperLogLog
 #[derive(Debug, Deserialize)]
@@ -14,12 +24,99 @@ pub struct PfAddRequest {
     pub elements: Vec<String>,
 }
 
+/// Outcome of a PFADD
+#[derive(Debug, Serialize)]
+pub struct PfAddResponse {
+    pub success: bool,
+    pub message: String,
+    /// Whether the key was created or a register changed, like the 1 or 0
+    /// Redis `PFADD` replies with
+    pub modified: bool,
+}
+
+/// Query parameters for PFADD
+#[derive(Debug, Default, Deserialize)]
+pub struct PfAddParams {
+    /// Expire the key this many seconds after the write
+    pub ttl: Option<u64>,
+}
+
+/// Response format of LIST
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeysFormat {
+    /// One JSON array, built after every key is listed
+    #[default]
+    Json,
+    /// One JSON string per line, streamed as keys are listed
+    Ndjson,
+}
+
+/// Query parameters for LIST
+#[derive(Debug, Default, Deserialize)]
+pub struct ListKeysParams {
+    #[serde(default)]
+    pub format: KeysFormat,
+}
+
+/// Keys buffered between the storage backend and a streaming LIST response
+const KEYS_STREAM_BUFFER: usize = 256;
+
 /// Redis PFCOUNT command - Get cardinality estimate
 #[derive(Debug, Serialize)]
 pub struct PfCountResponse {
     pub count: u64,
 }
 
+/// Fold every key starting with `prefix` down to `precision`
+#[derive(Debug, Deserialize)]
+pub struct FoldBatchRequest {
+    pub precision: u8,
+    #[serde(default)]
+    pub prefix: String,
+}
+
+/// Outcome of a batch fold
+#[derive(Debug, Serialize)]
+pub struct FoldBatchResponse {
+    /// Keys folded and stored back
+    pub folded: usize,
+    /// Keys already at or below the target precision
+    pub skipped: usize,
+    /// Keys that could not be loaded, folded or stored
+    pub failed: usize,
+}
+
+/// Metadata of a stored sketch, like Redis `INFO` for one key
+#[derive(Debug, Serialize)]
+pub struct InfoResponse {
+    pub precision: u8,
+    pub num_registers: usize,
+    pub nonzero_registers: usize,
+    pub estimated_count: u64,
+    /// Theoretical relative standard error, `1.04 / sqrt(num_registers)`
+    pub relative_error: f64,
+}
+
+/// Health of a single key, for dashboards
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub count: u64,
+    /// Fraction of registers that are nonzero
+    pub fill_ratio: f64,
+    pub quality: EstimateQuality,
+    pub recommended_precision: u8,
+    pub current_precision: u8,
+}
+
+/// Count the union of stored keys and an uploaded HyperLogLog
+#[derive(Debug, Deserialize)]
+pub struct PfCountWithRequest {
+    pub keys: Vec<String>,
+    /// Base64 of a HyperLogLog in the binary format (`HyperLogLog::to_bytes`)
+    pub extra_base64: Option<String>,
+}
+
 /// Redis PFMERGE command - Merge multiple HyperLogLogs
 #[derive(Debug, Deserialize)]
 pub struct PfMergeRequest {
@@ -43,9 +140,12 @@ impl IntoResponse for HllError {
     fn into_response(self) -> Response {
         let (status, message) = match self {
             HllError::NotFound(key) => (StatusCode::NOT_FOUND, format!("Key not found: {}", key)),
+            HllError::AlreadyExists(key) => (StatusCode::CONFLICT, format!("Key already exists: {}", key)),
             HllError::InvalidKey(key) => (StatusCode::BAD_REQUEST, format!("Invalid key: {}", key)),
-            HllError::InvalidPrecision(p) => {
-                (StatusCode::BAD_REQUEST, format!("Invalid precision: {}", p))
+            HllError::InvalidInput(msg) => (StatusCode::BAD_REQUEST, format!("Invalid input: {}", msg)),
+            e @ HllError::InvalidPrecision(_) => (StatusCode::BAD_REQUEST, e.to_string()),
+            HllError::InvalidLayout(msg) => {
+                (StatusCode::BAD_REQUEST, format!("Invalid register layout: {}", msg))
             }
             HllError::Storage(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             HllError::Serialization(e) => {
@@ -58,31 +158,84 @@ impl IntoResponse for HllError {
     }
 }
 
+/// CREATE - Store an empty HyperLogLog at the requested precision
+///
+/// Fails with 409 if the key already exists, so an existing sketch is never
+/// replaced. Later PFADDs keep the precision chosen here.
+pub async fn create(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Json(payload): Json<CreateRequest>,
+) -> Result<Json<SuccessResponse>, HllError> {
+    let hll = HyperLogLog::new(payload.precision)?;
+
+    let _lock = state.lock_key(&key).await;
+    if state.storage().exists(&key).await? {
+        return Err(HllError::AlreadyExists(key));
+    }
+    state.storage().store(&key, &hll).await?;
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        message: format!("Created {} with precision {}", key, payload.precision),
+    }))
+}
+
 /// PFADD - Add elements to a HyperLogLog
+///
+/// A missing key is created at the state's default precision (14 unless
+/// configured); create it first with CREATE for another precision. With
+/// `?ttl=<seconds>` the key expires that long after this write. Without
+/// it, any earlier expiry is cleared, so send the TTL with every PFADD to keep
+/// a key rolling. Concurrent PFADDs to one key are serialized within this
+/// process, so none of their elements are lost.
+///
+/// `modified` is true if the key was created or its estimate changed.
 pub async fn pfadd(
     State(state): State<AppState>,
     Path(key): Path<String>,
+    Query(params): Query<PfAddParams>,
     Json(payload): Json<PfAddRequest>,
-) -> Result<Json<SuccessResponse>, HllError> {
-    let mut hll = match state.storage().load(&key).await {
-        Ok(hll) => hll,
-        Err(HllError::NotFound(_)) => HyperLogLog::new(14)?,
+) -> Result<Json<PfAddResponse>, HllError> {
+    if params.ttl == Some(0) {
+        return Err(HllError::InvalidInput("ttl must be at least 1 second".to_string()));
+    }
+
+    if let Some(max_len) = state.max_element_len() {
+        if let Some(element) = payload.elements.iter().find(|e| e.len() > max_len) {
+            return Err(HllError::InvalidInput(format!(
+                "Element of {} bytes exceeds the limit of {}",
+                element.len(),
+                max_len
+            )));
+        }
+    }
+
+    let _lock = state.lock_key(&key).await;
+    let (mut hll, mut modified) = match state.storage().load(&key).await {
+        Ok(hll) => (hll, false),
+        Err(HllError::NotFound(_)) => (HyperLogLog::new(state.default_precision())?, true),
         Err(e) => return Err(e),
     };
 
     for element in &payload.elements {
-        hll.add_str(element);
+        modified |= hll.add_str(element);
     }
 
-    state.storage().store(&key, &hll).await?;
+    let ttl = params.ttl.map(Duration::from_secs);
+    state.storage().store_with_ttl(&key, &hll, ttl).await?;
 
-    Ok(Json(SuccessResponse {
+    Ok(Json(PfAddResponse {
         success: true,
         message: format!("Added {} elements", payload.elements.len()),
+        modified,
     }))
 }
 
 /// PFCOUNT - Get cardinality estimate from one or more HyperLogLogs
+///
+/// As in Redis, a missing key counts as an empty sketch, so counting only
+/// missing keys returns 0. Storage failures are still errors.
 pub async fn pfcount(
     State(state): State<AppState>,
     Path(keys): Path<String>,
@@ -93,19 +246,149 @@ pub async fn pfcount(
         return Ok(Json(PfCountResponse { count: 0 }));
     }
 
-    let mut merged = state.storage().load(key_list[0]).await?;
+    let hlls: Vec<HyperLogLog> = state
+        .storage()
+        .load_many_parallel(&key_list)
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+    if hlls.is_empty() {
+        return Ok(Json(PfCountResponse { count: 0 }));
+    }
 
-    for key in &key_list[1..] {
-        let hll = state.storage().load(key).await?;
-        merged.merge(&hll)?;
+    let count = HyperLogLog::merge_all(&hlls)?.count();
+
+    Ok(Json(PfCountResponse { count }))
+}
+
+/// PFCOUNT with an extra sketch - Count the union of stored keys and an
+/// uploaded HyperLogLog without storing anything
+///
+/// Missing keys count as empty sketches, as in [`pfcount`].
+pub async fn pfcount_with(
+    State(state): State<AppState>,
+    Json(payload): Json<PfCountWithRequest>,
+) -> Result<Json<PfCountResponse>, HllError> {
+    let mut hlls = Vec::with_capacity(payload.keys.len() + 1);
+    if let Some(encoded) = &payload.extra_base64 {
+        let bytes = BASE64
+            .decode(encoded)
+            .map_err(|e| HllError::InvalidInput(format!("extra_base64: {}", e)))?;
+        let extra = HyperLogLog::from_bytes(&bytes)
+            .map_err(|e| HllError::InvalidInput(format!("extra_base64: {}", e)))?;
+        hlls.push(extra);
     }
 
-    let count = merged.count();
+    let keys: Vec<&str> = payload.keys.iter().map(String::as_str).collect();
+    hlls.extend(state.storage().load_many_parallel(&keys).await?.into_iter().flatten());
+
+    let count = if hlls.is_empty() {
+        0
+    } else {
+        HyperLogLog::merge_all(&hlls)?.count()
+    };
 
     Ok(Json(PfCountResponse { count }))
 }
 
+/// HEALTH - Report estimate quality and recommended precision for a key
+pub async fn health(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<HealthResponse>, HllError> {
+    let hll = state.storage().load(&key).await?;
+
+    Ok(Json(HealthResponse {
+        count: hll.count(),
+        fill_ratio: hll.fill_ratio(),
+        quality: hll.estimate_quality(),
+        recommended_precision: hll.recommended_precision(),
+        current_precision: hll.precision(),
+    }))
+}
+
+/// SUMMARY - Headline numbers for a key, without its registers
+pub async fn summary(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<HllSummary>, HllError> {
+    let hll = state.storage().load(&key).await?;
+    Ok(Json(hll.summary()))
+}
+
+/// INFO - Precision, register fill and estimate of a key, for debugging
+pub async fn info(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Json<InfoResponse>, HllError> {
+    let hll = state.storage().load(&key).await?;
+    Ok(Json(InfoResponse {
+        precision: hll.precision(),
+        num_registers: hll.num_registers(),
+        nonzero_registers: hll.nonzero_registers(),
+        estimated_count: hll.count(),
+        relative_error: hll.relative_error(),
+    }))
+}
+
+/// FOLD_BATCH - Fold all keys matching a prefix down to a target precision
+///
+/// Keys are processed one at a time, so one bad key only counts as failed.
+/// A folded key keeps its expiry.
+pub async fn fold_batch(
+    State(state): State<AppState>,
+    Json(payload): Json<FoldBatchRequest>,
+) -> Result<Json<FoldBatchResponse>, HllError> {
+    // Reject an unusable target before touching any key
+    HyperLogLog::new(payload.precision)?;
+
+    let mut response = FoldBatchResponse {
+        folded: 0,
+        skipped: 0,
+        failed: 0,
+    };
+
+    for key in state.storage().list_keys().await? {
+        if !key.starts_with(&payload.prefix) {
+            continue;
+        }
+
+        let _lock = state.lock_key(&key).await;
+        let hll = match state.storage().load(&key).await {
+            Ok(hll) => hll,
+            Err(e) => {
+                tracing::warn!("fold_batch: cannot load {}: {}", key, e);
+                response.failed += 1;
+                continue;
+            }
+        };
+
+        if hll.precision() <= payload.precision {
+            response.skipped += 1;
+            continue;
+        }
+
+        let stored = match hll.fold_to(payload.precision) {
+            Ok(folded) => state.storage().store_keep_ttl(&key, &folded).await,
+            Err(e) => Err(e),
+        };
+        match stored {
+            Ok(()) => response.folded += 1,
+            Err(e) => {
+                tracing::warn!("fold_batch: cannot fold {}: {}", key, e);
+                response.failed += 1;
+            }
+        }
+    }
+
+    Ok(Json(response))
+}
+
 /// PFMERGE - Merge multiple HyperLogLogs into destination key
+///
+/// Holds the destination's lock, like PFADD, while it loads and stores. An
+/// existing destination keeps its expiry.
 pub async fn pfmerge(
     State(state): State<AppState>,
     Path(dest_key): Path<String>,
@@ -115,14 +398,14 @@ pub async fn pfmerge(
         return Err(HllError::InvalidKey("No source keys provided".to_string()));
     }
 
-    let mut merged = state.storage().load(&payload.source_keys[0]).await?;
-
-    for key in &payload.source_keys[1..] {
-        let hll = state.storage().load(key).await?;
-        merged.merge(&hll)?;
+    let _lock = state.lock_key(&dest_key).await;
+    let mut hlls = Vec::with_capacity(payload.source_keys.len());
+    for key in &payload.source_keys {
+        hlls.push(state.storage().load(key).await?);
     }
 
-    state.storage().store(&dest_key, &merged).await?;
+    let merged = HyperLogLog::merge_all(&hlls)?;
+    state.storage().store_keep_ttl(&dest_key, &merged).await?;
 
     Ok(Json(SuccessResponse {
         success: true,
@@ -153,9 +436,479 @@ pub async fn exists(
 }
 
 /// LIST - List all keys
+///
+/// With `?format=ndjson` the keys are streamed as newline-delimited JSON
+/// while the backend pages through them, so the full list is never held in
+/// memory. A storage error after the response has started aborts the body.
 pub async fn list_keys(
     State(state): State<AppState>,
-) -> Result<Json<Vec<String>>, HllError> {
-    let keys = state.storage().list_keys().await?;
-    Ok(Json(keys))
+    Query(params): Query<ListKeysParams>,
+) -> Result<Response, HllError> {
+    if params.format == KeysFormat::Json {
+        let keys = state.storage().list_keys().await?;
+        return Ok(Json(keys).into_response());
+    }
+
+    // The key stream borrows the storage, so drive it from a task that owns
+    // a handle and hand the lines over a bounded channel
+    let storage = state.shared_storage();
+    let (mut lines, body) = futures::channel::mpsc::channel(KEYS_STREAM_BUFFER);
+    tokio::spawn(async move {
+        let mut keys = storage.list_keys_stream();
+        while let Some(key) = keys.next().await {
+            let line = key.and_then(|key| Ok(format!("{}\n", serde_json::to_string(&key)?)));
+            let failed = line.is_err();
+            // A send error means the client went away
+            if lines.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(body)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{FileStorage, MemoryStorage};
+    use std::sync::Arc;
+
+    async fn test_state(name: &str) -> (AppState, std::path::PathBuf) {
+        let temp_dir = std::env::temp_dir().join(name);
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+        (AppState::new(Arc::new(storage)), temp_dir)
+    }
+
+    #[tokio::test]
+    async fn test_pfadd_rejects_long_element() {
+        let (state, temp_dir) = test_state("hll_test_pfadd_max_len").await;
+        let state = state.with_max_element_len(16);
+
+        let request = PfAddRequest {
+            elements: vec!["ok".to_string(), "x".repeat(17)],
+        };
+        let params = Query(PfAddParams::default());
+        let response = pfadd(State(state.clone()), Path("visitors".to_string()), params, Json(request))
+            .await
+            .unwrap_err()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // The whole request is rejected, nothing is stored
+        assert!(!state.storage().exists("visitors").await.unwrap());
+
+        let request = PfAddRequest {
+            elements: vec!["x".repeat(16)],
+        };
+        let params = Query(PfAddParams::default());
+        assert!(pfadd(State(state.clone()), Path("visitors".to_string()), params, Json(request))
+            .await
+            .is_ok());
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pfadd_with_ttl() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()));
+        let add = |ttl: Option<u64>| {
+            let request = PfAddRequest {
+                elements: vec!["a".to_string(), "b".to_string()],
+            };
+            pfadd(
+                State(state.clone()),
+                Path("daily".to_string()),
+                Query(PfAddParams { ttl }),
+                Json(request),
+            )
+        };
+
+        assert!(add(Some(86400)).await.is_ok());
+        tokio::time::advance(Duration::from_secs(86399)).await;
+        assert!(state.storage().exists("daily").await.unwrap());
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(!state.storage().exists("daily").await.unwrap());
+
+        let err = add(Some(0)).await.unwrap_err();
+        assert!(matches!(err, HllError::InvalidInput(_)));
+
+        // The longest TTL saturates rather than overflowing the expiry time
+        assert!(add(Some(u64::MAX)).await.is_ok());
+        tokio::time::advance(Duration::from_secs(86400 * 365)).await;
+        assert!(state.storage().exists("daily").await.unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rewrites_keep_ttl() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()));
+        let mut hll = HyperLogLog::new(14).unwrap();
+        hll.add_all(0..1000);
+        let ttl = Some(Duration::from_secs(100));
+        state.storage().store_with_ttl("daily", &hll, ttl).await.unwrap();
+        state.storage().store("other", &hll).await.unwrap();
+
+        let request = FoldBatchRequest {
+            prefix: "daily".to_string(),
+            precision: 10,
+        };
+        let folded = fold_batch(State(state.clone()), Json(request)).await.unwrap();
+        assert_eq!(folded.folded, 1);
+
+        let request = PfMergeRequest {
+            source_keys: vec!["other".to_string()],
+        };
+        assert!(pfmerge(State(state.clone()), Path("daily".to_string()), Json(request)).await.is_ok());
+
+        tokio::time::advance(Duration::from_secs(99)).await;
+        assert!(state.storage().exists("daily").await.unwrap());
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(!state.storage().exists("daily").await.unwrap());
+        assert!(state.storage().exists("other").await.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_pfadd_loses_nothing() {
+        let (state, temp_dir) = test_state("hll_test_concurrent_pfadd").await;
+
+        let mut baseline = HyperLogLog::new(14).unwrap();
+        let mut tasks = Vec::new();
+        for worker in 0..32 {
+            let elements: Vec<String> = (0..100).map(|i| format!("user:{}:{}", worker, i)).collect();
+            for element in &elements {
+                baseline.add_str(element);
+            }
+
+            let state = state.clone();
+            tasks.push(tokio::spawn(async move {
+                pfadd(
+                    State(state),
+                    Path("visitors".to_string()),
+                    Query(PfAddParams::default()),
+                    Json(PfAddRequest { elements }),
+                )
+                .await
+                .map(|_| ())
+            }));
+        }
+
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let stored = state.storage().load("visitors").await.unwrap();
+        assert_eq!(stored.to_bytes(), baseline.to_bytes(), "Concurrent PFADDs lost elements");
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_health_saturated_key() {
+        let (state, temp_dir) = test_state("hll_test_health").await;
+
+        let mut saturated = HyperLogLog::with_layout(10, 3).unwrap();
+        for i in 0..100000 {
+            saturated.add(&i);
+        }
+        state.storage().store("saturated", &saturated).await.unwrap();
+
+        let Json(health) = health(State(state.clone()), Path("saturated".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(health.quality, EstimateQuality::Saturated);
+        assert_eq!(health.current_precision, 10);
+        assert!(health.recommended_precision > health.current_precision);
+        assert_eq!(health.fill_ratio, 1.0);
+
+        let response = super::health(State(state), Path("missing".to_string()))
+            .await
+            .unwrap_err()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_invalid_precision_response() {
+        let (state, temp_dir) = test_state("hll_test_invalid_precision").await;
+
+        let request = FoldBatchRequest {
+            precision: 2,
+            prefix: String::new(),
+        };
+        let response = fold_batch(State(state), Json(request))
+            .await
+            .unwrap_err()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "Invalid precision 2 (must be 4..=16)");
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_summary() {
+        let (state, temp_dir) = test_state("hll_test_summary").await;
+
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..1000 {
+            hll.add(&i);
+        }
+        state.storage().store("visitors", &hll).await.unwrap();
+
+        let Json(response) = summary(State(state.clone()), Path("visitors".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(response, hll.summary());
+
+        let response = summary(State(state), Path("missing".to_string()))
+            .await
+            .unwrap_err()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_fold_batch() {
+        let (state, temp_dir) = test_state("hll_test_fold_batch").await;
+
+        for (key, precision) in [("daily:a", 14), ("daily:b", 10), ("daily:c", 14), ("weekly:a", 14)] {
+            let mut hll = HyperLogLog::new(precision).unwrap();
+            for i in 0..1000 {
+                hll.add(&i);
+            }
+            state.storage().store(key, &hll).await.unwrap();
+        }
+
+        let request = FoldBatchRequest {
+            precision: 12,
+            prefix: "daily:".to_string(),
+        };
+        let Json(response) = fold_batch(State(state.clone()), Json(request)).await.unwrap();
+        assert_eq!((response.folded, response.skipped, response.failed), (2, 1, 0));
+
+        for (key, precision) in [("daily:a", 12), ("daily:b", 10), ("daily:c", 12), ("weekly:a", 14)] {
+            let hll = state.storage().load(key).await.unwrap();
+            assert_eq!(hll.precision(), precision, "Wrong precision for {}", key);
+        }
+
+        let request = FoldBatchRequest {
+            precision: 2,
+            prefix: String::new(),
+        };
+        assert!(fold_batch(State(state), Json(request)).await.is_err());
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_pfcount_with_extra() {
+        let (state, temp_dir) = test_state("hll_test_pfcount_with").await;
+
+        let mut stored = HyperLogLog::new(14).unwrap();
+        let mut extra = HyperLogLog::new(14).unwrap();
+        for i in 0..1000 {
+            stored.add(&i);
+        }
+        for i in 500..1500 {
+            extra.add(&i);
+        }
+        state.storage().store("stored", &stored).await.unwrap();
+
+        let mut union = stored.clone();
+        union.merge(&extra).unwrap();
+
+        let request = PfCountWithRequest {
+            keys: vec!["stored".to_string()],
+            extra_base64: Some(BASE64.encode(extra.to_bytes())),
+        };
+        let Json(response) = pfcount_with(State(state.clone()), Json(request)).await.unwrap();
+        assert_eq!(response.count, union.count());
+
+        // Nothing new was stored
+        assert_eq!(state.storage().list_keys().await.unwrap(), vec!["stored"]);
+
+        let request = PfCountWithRequest {
+            keys: vec!["stored".to_string()],
+            extra_base64: Some("not base64!".to_string()),
+        };
+        let err = pfcount_with(State(state), Json(request)).await.unwrap_err();
+        assert!(matches!(err, HllError::InvalidInput(_)));
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_list_keys_ndjson() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()));
+        for key in ["plain", "with \"quotes\""] {
+            state.storage().store(key, &HyperLogLog::new(4).unwrap()).await.unwrap();
+        }
+
+        let params = Query(ListKeysParams { format: KeysFormat::Ndjson });
+        let response = list_keys(State(state.clone()), params).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/x-ndjson");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let mut keys: Vec<String> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["plain", "with \"quotes\""]);
+
+        // The default is still a single JSON array
+        let response = list_keys(State(state), Query(ListKeysParams::default())).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Vec<String>>(&body).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pfcount_missing_keys_count_as_empty() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()));
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..1000 {
+            hll.add(&i);
+        }
+        state.storage().store("present", &hll).await.unwrap();
+
+        let count = |keys: &str| {
+            let state = state.clone();
+            let keys = keys.to_string();
+            async move { pfcount(State(state), Path(keys)).await.unwrap().0.count }
+        };
+
+        assert_eq!(count("missing").await, 0);
+        assert_eq!(count("present,missing").await, hll.count());
+        assert_eq!(count("missing,present,other").await, hll.count());
+        assert_eq!(count("missing,other").await, 0);
+
+        let count_with = |keys: &[&str], extra: Option<&HyperLogLog>| {
+            let request = PfCountWithRequest {
+                keys: keys.iter().map(|key| key.to_string()).collect(),
+                extra_base64: extra.map(|extra| BASE64.encode(extra.to_bytes())),
+            };
+            let state = state.clone();
+            async move { pfcount_with(State(state), Json(request)).await.unwrap().0.count }
+        };
+
+        assert_eq!(count_with(&["missing"], None).await, 0);
+        assert_eq!(count_with(&["present", "missing"], None).await, hll.count());
+        assert_eq!(count_with(&["missing"], Some(&hll)).await, hll.count());
+    }
+
+    #[tokio::test]
+    async fn test_create() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()));
+        let create_key = |key: &str, precision: u8| {
+            let state = state.clone();
+            let key = key.to_string();
+            async move { create(State(state), Path(key), Json(CreateRequest { precision })).await }
+        };
+
+        assert!(create_key("visitors", 16).await.unwrap().success);
+        let hll = state.storage().load("visitors").await.unwrap();
+        assert_eq!(hll.precision(), 16);
+        assert_eq!(hll.count(), 0);
+
+        // PFADD keeps the precision instead of recreating at the default
+        let request = PfAddRequest {
+            elements: vec!["a".to_string(), "b".to_string()],
+        };
+        let params = Query(PfAddParams::default());
+        assert!(pfadd(State(state.clone()), Path("visitors".to_string()), params, Json(request))
+            .await
+            .is_ok());
+        let hll = state.storage().load("visitors").await.unwrap();
+        assert_eq!(hll.precision(), 16);
+        assert_eq!(hll.count(), 2);
+
+        // Creating again conflicts and leaves the sketch alone
+        let err = create_key("visitors", 10).await.unwrap_err();
+        assert!(matches!(err, HllError::AlreadyExists(_)));
+        assert_eq!(err.into_response().status(), StatusCode::CONFLICT);
+        assert_eq!(state.storage().load("visitors").await.unwrap().count(), 2);
+
+        let err = create_key("other", 17).await.unwrap_err();
+        assert!(matches!(err, HllError::InvalidPrecision(17)));
+        assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST);
+        assert!(!state.storage().exists("other").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_pfadd_reports_modified() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()));
+        let add = |elements: &[&str]| {
+            let state = state.clone();
+            let request = PfAddRequest {
+                elements: elements.iter().map(|e| e.to_string()).collect(),
+            };
+            async move {
+                let params = Query(PfAddParams::default());
+                pfadd(State(state), Path("visitors".to_string()), params, Json(request))
+                    .await
+                    .unwrap()
+                    .0
+                    .modified
+            }
+        };
+
+        assert!(add(&["user1"]).await);
+        assert!(!add(&["user1"]).await);
+        assert!(!add(&[]).await);
+        assert!(add(&["user1", "user2"]).await);
+        assert!(!add(&["user2", "user1"]).await);
+    }
+
+    #[tokio::test]
+    async fn test_pfadd_uses_default_precision() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()));
+        assert_eq!(state.default_precision(), AppState::DEFAULT_PRECISION);
+        assert!(matches!(
+            state.clone().with_default_precision(17),
+            Err(HllError::InvalidPrecision(17))
+        ));
+
+        let state = state.with_default_precision(12).unwrap();
+        let request = PfAddRequest {
+            elements: vec!["a".to_string()],
+        };
+        let params = Query(PfAddParams::default());
+        assert!(pfadd(State(state.clone()), Path("visitors".to_string()), params, Json(request))
+            .await
+            .is_ok());
+        assert_eq!(state.storage().load("visitors").await.unwrap().precision(), 12);
+    }
+
+    #[tokio::test]
+    async fn test_info() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()));
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..1000 {
+            hll.add(&i);
+        }
+        state.storage().store("visitors", &hll).await.unwrap();
+
+        let Json(response) = info(State(state.clone()), Path("visitors".to_string())).await.unwrap();
+        assert_eq!(response.precision, 12);
+        assert_eq!(response.num_registers, 4096);
+        assert!(
+            response.nonzero_registers > 800 && response.nonzero_registers <= 1000,
+            "Got {}",
+            response.nonzero_registers
+        );
+        assert!((response.estimated_count as f64 - 1000.0).abs() < 50.0, "Got {}", response.estimated_count);
+        assert!((response.relative_error - 1.04 / 64.0).abs() < 1e-12);
+
+        let err = info(State(state), Path("missing".to_string())).await.unwrap_err();
+        assert!(matches!(err, HllError::NotFound(_)));
+    }
 }
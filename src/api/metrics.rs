@@ -0,0 +1,544 @@
+use super::AppState;
+use crate::storage::{Storage, VerifyReport};
+use crate::{HllError, HyperLogLog, Result};
+use async_trait::async_trait;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::stream::BoxStream;
+use prometheus_client::encoding::{text::encode, EncodeLabelSet};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::exemplar::HistogramWithExemplars;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+/// Content type of the OpenMetrics text format, the only one that carries exemplars
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Labels identifying which command a latency sample belongs to
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CommandLabels {
+    command: &'static str,
+}
+
+/// Labels of a command counter: the command and whether it succeeded
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct OutcomeLabels {
+    command: &'static str,
+    status: &'static str,
+}
+
+/// Labels identifying which [`Storage`] method a latency sample belongs to
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct OperationLabels {
+    operation: &'static str,
+}
+
+/// Exemplar labels linking a sample to the trace of the request that produced it
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct TraceLabels {
+    trace_id: String,
+}
+
+/// Labels identifying the stored key a gauge describes
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct KeyLabels {
+    key: String,
+}
+
+type LatencyHistogram = Family<CommandLabels, HistogramWithExemplars<TraceLabels>>;
+type SaturationGauge = Family<KeyLabels, Gauge<f64, AtomicU64>>;
+type CommandCounter = Family<OutcomeLabels, Counter>;
+type StorageHistogram = Family<OperationLabels, Histogram>;
+
+/// Prometheus metrics exported by the server at `GET /metrics`
+///
+/// Command and storage metrics are recorded as requests are served.
+/// `hyperloglog_keys` and `hyperloglog_key_saturation_ratio` are only
+/// updated while [`spawn_saturation_sampler`] runs, since counting keys
+/// means listing the whole store; without it the key count stays 0.
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    request_duration: LatencyHistogram,
+    commands: CommandCounter,
+    storage_duration: StorageHistogram,
+    keys: Gauge,
+    key_saturation: SaturationGauge,
+    /// Keys with a `key_saturation` label, which the family cannot list
+    saturation_keys: Mutex<HashSet<String>>,
+}
+
+impl Metrics {
+    /// Create and register all server metrics
+    pub fn new() -> Self {
+        let mut registry = Registry::with_prefix("hyperloglog");
+
+        let request_duration = LatencyHistogram::new_with_constructor(new_latency_histogram);
+        registry.register(
+            "request_duration_seconds",
+            "Latency of HyperLogLog commands",
+            request_duration.clone(),
+        );
+
+        let commands = CommandCounter::default();
+        registry.register(
+            "commands",
+            "HyperLogLog commands served, by outcome",
+            commands.clone(),
+        );
+
+        let storage_duration = StorageHistogram::new_with_constructor(|| {
+            // 0.1ms up to ~1.6s
+            Histogram::new(exponential_buckets(0.0001, 2.0, 15))
+        });
+        registry.register(
+            "storage_operation_duration_seconds",
+            "Latency of storage backend operations",
+            storage_duration.clone(),
+        );
+
+        let keys = Gauge::default();
+        registry.register(
+            "keys",
+            "Number of stored keys, as of the last saturation sample",
+            keys.clone(),
+        );
+
+        let key_saturation = SaturationGauge::default();
+        registry.register(
+            "key_saturation_ratio",
+            "Saturation of sampled keys, from 0 to 1",
+            key_saturation.clone(),
+        );
+
+        Self {
+            registry,
+            request_duration,
+            commands,
+            storage_duration,
+            keys,
+            key_saturation,
+            saturation_keys: Mutex::default(),
+        }
+    }
+
+    /// Count one served command, failed if it did not answer with a 2xx
+    fn count_command(&self, command: &'static str, succeeded: bool) {
+        let status = if succeeded { "ok" } else { "error" };
+        self.commands.get_or_create(&OutcomeLabels { command, status }).inc();
+    }
+
+    /// Record the latency of one storage operation
+    fn observe_storage(&self, operation: &'static str, seconds: f64) {
+        self.storage_duration
+            .get_or_create(&OperationLabels { operation })
+            .observe(seconds);
+    }
+
+    /// Record the latest sampled saturation of `key`
+    fn set_saturation(&self, key: &str, saturation: f64) {
+        let mut saturation_keys = self.saturation_keys.lock().unwrap();
+        self.key_saturation
+            .get_or_create(&KeyLabels { key: key.to_string() })
+            .set(saturation);
+        saturation_keys.insert(key.to_string());
+    }
+
+    /// Stop exporting the saturation of a deleted key
+    fn remove_saturation(&self, key: &str) {
+        let mut saturation_keys = self.saturation_keys.lock().unwrap();
+        self.key_saturation.remove(&KeyLabels { key: key.to_string() });
+        saturation_keys.remove(key);
+    }
+
+    /// Stop exporting the saturation of keys missing from the sorted `keys`
+    fn retain_saturation(&self, keys: &[String]) {
+        let mut saturation_keys = self.saturation_keys.lock().unwrap();
+        saturation_keys.retain(|key| {
+            let listed = keys.binary_search(key).is_ok();
+            if !listed {
+                self.key_saturation.remove(&KeyLabels { key: key.clone() });
+            }
+            listed
+        });
+    }
+
+    /// Record the latency of one command, with the request's trace as exemplar
+    fn observe(&self, command: &'static str, seconds: f64, trace_id: Option<String>) {
+        self.request_duration
+            .get_or_create(&CommandLabels { command })
+            .observe(seconds, trace_id.map(|trace_id| TraceLabels { trace_id }));
+    }
+
+    /// Render all metrics in the OpenMetrics text format
+    pub fn encode(&self) -> String {
+        let mut buffer = String::new();
+        // Writing into a String cannot fail
+        let _ = encode(&mut buffer, &self.registry);
+        buffer
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn new_latency_histogram() -> HistogramWithExemplars<TraceLabels> {
+    // 0.5ms up to ~1s
+    HistogramWithExemplars::new(exponential_buckets(0.0005, 2.0, 12))
+}
+
+/// Extract the trace id from a W3C `traceparent` header
+/// (`version-traceid-parentid-flags`), if the request carries one
+fn trace_id(headers: &HeaderMap) -> Option<String> {
+    let traceparent = headers.get("traceparent")?.to_str().ok()?;
+    let trace_id = traceparent.split('-').nth(1)?;
+
+    let valid = trace_id.len() == 32
+        && trace_id.bytes().all(|b| b.is_ascii_hexdigit())
+        && trace_id.bytes().any(|b| b != b'0');
+    valid.then(|| trace_id.to_string())
+}
+
+/// Spawn a background task exporting the saturation of stored keys
+///
+/// Every `interval`, up to `sample_size` keys are loaded and their
+/// [`HyperLogLog::saturation`](crate::HyperLogLog::saturation) published as
+/// `hyperloglog_key_saturation_ratio`. Each round continues through the
+/// sorted key list where the previous one stopped, so large stores are
+/// covered over several rounds rather than scanned at once. Abort the
+/// returned handle to stop the task.
+pub fn spawn_saturation_sampler(
+    state: AppState,
+    interval: Duration,
+    sample_size: usize,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut cursor = 0;
+
+        loop {
+            ticker.tick().await;
+            match sample_saturation(&state, cursor, sample_size).await {
+                Ok(next) => cursor = next,
+                Err(e) => tracing::warn!("Saturation sampling failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Record the saturation of up to `sample_size` keys starting at `cursor`,
+/// returning the cursor for the next round
+async fn sample_saturation(state: &AppState, cursor: usize, sample_size: usize) -> Result<usize> {
+    let mut keys = state.storage().list_keys().await?;
+    state.metrics().keys.set(keys.len() as i64);
+    keys.sort();
+    state.metrics().retain_saturation(&keys);
+    if keys.is_empty() {
+        return Ok(0);
+    }
+
+    let start = cursor % keys.len();
+    for key in keys.iter().cycle().skip(start).take(sample_size.min(keys.len())) {
+        match state.storage().load(key).await {
+            Ok(hll) => state.metrics().set_saturation(key, hll.saturation()),
+            // Deleted since it was listed
+            Err(HllError::NotFound(_)) => state.metrics().remove_saturation(key),
+            Err(e) => tracing::warn!("Cannot sample saturation of {}: {}", key, e),
+        }
+    }
+
+    Ok(start + sample_size)
+}
+
+/// Middleware counting and timing PFADD, PFCOUNT and PFMERGE requests
+pub async fn track_latency(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let command = match request.extensions().get::<MatchedPath>().map(MatchedPath::as_str) {
+        Some("/pfadd/:key") => "pfadd",
+        Some("/pfcount/:keys") => "pfcount",
+        Some("/pfmerge/:dest_key") => "pfmerge",
+        _ => return next.run(request).await,
+    };
+
+    let trace_id = trace_id(request.headers());
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let metrics = state.metrics();
+    metrics.observe(command, start.elapsed().as_secs_f64(), trace_id);
+    metrics.count_command(command, response.status().is_success());
+
+    response
+}
+
+/// [`Storage`] wrapper recording the latency of every operation in
+/// `hyperloglog_storage_operation_duration_seconds`
+///
+/// Every method is forwarded, so backend overrides such as an atomic
+/// `merge_into` keep working. Failed operations are timed too.
+pub(crate) struct TimedStorage {
+    inner: Arc<dyn Storage>,
+    metrics: Arc<Metrics>,
+}
+
+impl TimedStorage {
+    pub(crate) fn new(inner: Arc<dyn Storage>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    async fn timed<T>(&self, operation: &'static str, future: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let result = future.await;
+        self.metrics
+            .observe_storage(operation, start.elapsed().as_secs_f64());
+        result
+    }
+}
+
+#[async_trait]
+impl Storage for TimedStorage {
+    async fn store(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        self.timed("store", self.inner.store(key, hll)).await
+    }
+
+    async fn load(&self, key: &str) -> Result<HyperLogLog> {
+        self.timed("load", self.inner.load(key)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.timed("delete", self.inner.delete(key)).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        self.timed("exists", self.inner.exists(key)).await
+    }
+
+    async fn list_keys(&self) -> Result<Vec<String>> {
+        self.timed("list_keys", self.inner.list_keys()).await
+    }
+
+    // Streams are consumed at the client's pace, so their latency says
+    // little about the backend
+    fn list_keys_stream(&self) -> BoxStream<'_, Result<String>> {
+        self.inner.list_keys_stream()
+    }
+
+    async fn store_with_ttl(&self, key: &str, hll: &HyperLogLog, ttl: Option<Duration>) -> Result<()> {
+        self.timed("store_with_ttl", self.inner.store_with_ttl(key, hll, ttl))
+            .await
+    }
+
+    async fn store_keep_ttl(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        self.timed("store_keep_ttl", self.inner.store_keep_ttl(key, hll)).await
+    }
+
+    async fn load_many_parallel(&self, keys: &[&str]) -> Result<Vec<Option<HyperLogLog>>> {
+        self.timed("load_many_parallel", self.inner.load_many_parallel(keys))
+            .await
+    }
+
+    async fn list_keys_by_precision(&self, precision: u8) -> Result<Vec<String>> {
+        self.timed("list_keys_by_precision", self.inner.list_keys_by_precision(precision))
+            .await
+    }
+
+    async fn merge_into(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        self.timed("merge_into", self.inner.merge_into(key, hll)).await
+    }
+
+    async fn merge_into_counting(&self, key: &str, hll: &HyperLogLog) -> Result<u64> {
+        self.timed("merge_into_counting", self.inner.merge_into_counting(key, hll))
+            .await
+    }
+
+    async fn rollup(&self, sources: &[&str], dest: &str, skip_missing: bool) -> Result<u64> {
+        self.timed("rollup", self.inner.rollup(sources, dest, skip_missing))
+            .await
+    }
+
+    async fn verify(&self) -> Result<VerifyReport> {
+        self.timed("verify", self.inner.verify()).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.timed("flush", self.inner.flush()).await
+    }
+}
+
+/// METRICS - Export server metrics for Prometheus
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)],
+        state.metrics().encode(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::create_router;
+    use crate::storage::{FileStorage, MemoryStorage};
+    use axum::body::Body;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_exemplar_from_traceparent() {
+        let temp_dir = std::env::temp_dir().join("hll_test_metrics_exemplar");
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+        let app = create_router(AppState::new(Arc::new(storage)));
+
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let request = Request::post("/pfadd/visitors")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("traceparent", format!("00-{}-00f067aa0ba902b7-01", trace_id))
+            .body(Body::from(r#"{"elements": ["a", "b"]}"#))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        let exemplar = format!("# {{trace_id=\"{}\"}}", trace_id);
+        assert!(
+            body.lines().any(|line| line
+                .starts_with("hyperloglog_request_duration_seconds_bucket{")
+                && line.contains("command=\"pfadd\"")
+                && line.contains(&exemplar)),
+            "No exemplar in metrics output:\n{}",
+            body
+        );
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_saturation_sampler() {
+        let temp_dir = std::env::temp_dir().join("hll_test_metrics_saturation");
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+        let state = AppState::new(Arc::new(storage));
+
+        let mut saturated = crate::HyperLogLog::with_layout(10, 3).unwrap();
+        for i in 0..100000 {
+            saturated.add(&i);
+        }
+        state.storage().store("saturated", &saturated).await.unwrap();
+
+        let handle = spawn_saturation_sampler(state.clone(), Duration::from_millis(10), 10);
+        let expected = "hyperloglog_key_saturation_ratio{key=\"saturated\"} 1.0";
+        let mut found = false;
+        for _ in 0..100 {
+            if state.metrics().encode().contains(expected) {
+                found = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        handle.abort();
+
+        assert!(found, "No saturation gauge in:\n{}", state.metrics().encode());
+        assert!(state.metrics().encode().lines().any(|line| line == "hyperloglog_keys 1"));
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_saturation_of_deleted_keys() {
+        let temp_dir = std::env::temp_dir().join("hll_test_metrics_deleted");
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+        let state = AppState::new(Arc::new(storage));
+
+        let hll = crate::HyperLogLog::new(10).unwrap();
+        for key in ["kept", "deleted"] {
+            state.storage().store(key, &hll).await.unwrap();
+        }
+        sample_saturation(&state, 0, 10).await.unwrap();
+        assert!(state.metrics().encode().contains("key=\"deleted\""));
+
+        state.storage().delete("deleted").await.unwrap();
+        sample_saturation(&state, 0, 10).await.unwrap();
+        let encoded = state.metrics().encode();
+        assert!(!encoded.contains("key=\"deleted\""), "Stale gauge in:\n{}", encoded);
+        assert!(encoded.contains("key=\"kept\""));
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_command_and_storage_metrics() {
+        let state = AppState::new(Arc::new(MemoryStorage::new()));
+        let app = create_router(state.clone());
+
+        let send = |request: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        let pfadd = || {
+            Request::post("/pfadd/visitors")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"elements": ["a", "b"]}"#))
+                .unwrap()
+        };
+        assert_eq!(send(pfadd()).await, StatusCode::OK);
+        assert_eq!(send(pfadd()).await, StatusCode::OK);
+        let request = Request::get("/pfcount/visitors").body(Body::empty()).unwrap();
+        assert_eq!(send(request).await, StatusCode::OK);
+        let request = Request::post("/pfmerge/union")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"source_keys": ["missing"]}"#))
+            .unwrap();
+        assert_eq!(send(request).await, StatusCode::NOT_FOUND);
+
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        for expected in [
+            "hyperloglog_commands_total{command=\"pfadd\",status=\"ok\"} 2",
+            "hyperloglog_commands_total{command=\"pfcount\",status=\"ok\"} 1",
+            "hyperloglog_commands_total{command=\"pfmerge\",status=\"error\"} 1",
+            // Two PFADDs and the failed PFMERGE source
+            "hyperloglog_storage_operation_duration_seconds_count{operation=\"load\"} 3",
+            "hyperloglog_storage_operation_duration_seconds_count{operation=\"store_with_ttl\"} 2",
+            "hyperloglog_storage_operation_duration_seconds_count{operation=\"load_many_parallel\"} 1",
+        ] {
+            assert!(body.lines().any(|line| line == expected), "No {} in:\n{}", expected, body);
+        }
+        assert!(!body.contains("command=\"pfcount\",status=\"error\""));
+    }
+
+    #[test]
+    fn test_trace_id_parsing() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(trace_id(&headers), None);
+
+        headers.insert("traceparent", "00-00000000000000000000000000000000-00f067aa0ba902b7-01".parse().unwrap());
+        assert_eq!(trace_id(&headers), None);
+
+        headers.insert("traceparent", "garbage".parse().unwrap());
+        assert_eq!(trace_id(&headers), None);
+    }
+}
@@ -1,25 +1,137 @@
+mod auth;
 mod handlers;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod routes;
 
+#[cfg(feature = "metrics")]
+pub use metrics::{spawn_saturation_sampler, Metrics};
+#[cfg(feature = "metrics")]
+use metrics::TimedStorage;
 pub use routes::create_router;
 
+use crate::hll::{MAX_PRECISION, MIN_PRECISION};
 use crate::storage::Storage;
+use crate::{HllError, Result};
+use auth::AuthConfig;
+use crate::locks::{KeyGuard, KeyLocks};
 use std::sync::Arc;
 
 /// Shared application state containing storage backend
 #[derive(Clone)]
 pub struct AppState {
     storage: Arc<dyn Storage>,
+    key_locks: Arc<KeyLocks>,
+    max_element_len: Option<usize>,
+    default_precision: u8,
+    auth: Option<AuthConfig>,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Metrics>,
 }
 
 impl AppState {
+    /// Precision of keys PFADD creates, unless configured otherwise
+    pub const DEFAULT_PRECISION: u8 = 14;
+
     /// Create new application state with given storage backend
+    ///
+    /// With the `metrics` feature, every storage operation is timed.
     pub fn new(storage: Arc<dyn Storage>) -> Self {
-        Self { storage }
+        #[cfg(feature = "metrics")]
+        let metrics = Arc::new(Metrics::new());
+        #[cfg(feature = "metrics")]
+        let storage: Arc<dyn Storage> = Arc::new(TimedStorage::new(storage, metrics.clone()));
+
+        Self {
+            storage,
+            key_locks: Arc::default(),
+            max_element_len: None,
+            default_precision: Self::DEFAULT_PRECISION,
+            auth: None,
+            #[cfg(feature = "metrics")]
+            metrics,
+        }
+    }
+
+    /// Reject PFADD elements longer than `max_len` bytes with 400 Bad Request
+    pub fn with_max_element_len(mut self, max_len: usize) -> Self {
+        self.max_element_len = Some(max_len);
+        self
+    }
+
+    /// Create missing keys at `precision` on PFADD instead of
+    /// [`AppState::DEFAULT_PRECISION`]
+    pub fn with_default_precision(mut self, precision: u8) -> Result<Self> {
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+            return Err(HllError::InvalidPrecision(precision));
+        }
+        self.default_precision = precision;
+        Ok(self)
+    }
+
+    /// Reject CREATE, PFADD, PFMERGE, fold_batch and DELETE with 401
+    /// Unauthorized unless they carry `Authorization: Bearer <token>`
+    ///
+    /// Read routes, including `/metrics`, stay open unless
+    /// [`AppState::require_auth_for_reads`] is set.
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(AuthConfig {
+            token: token.into(),
+            protect_reads: self.auth.is_some_and(|auth| auth.protect_reads),
+        });
+        self
+    }
+
+    /// Also require the bearer token on read routes; no effect without
+    /// [`AppState::with_auth_token`]
+    pub fn require_auth_for_reads(mut self, protect_reads: bool) -> Self {
+        if let Some(auth) = self.auth.as_mut() {
+            auth.protect_reads = protect_reads;
+        }
+        self
+    }
+
+    pub(crate) fn auth(&self) -> Option<&AuthConfig> {
+        self.auth.as_ref()
+    }
+
+    /// Longest element PFADD accepts, if limited
+    pub fn max_element_len(&self) -> Option<usize> {
+        self.max_element_len
+    }
+
+    /// Precision of keys PFADD creates
+    pub fn default_precision(&self) -> u8 {
+        self.default_precision
     }
 
     /// Get reference to storage backend
     pub fn storage(&self) -> &dyn Storage {
         self.storage.as_ref()
     }
+
+    /// Shared handle to the storage backend, for work that outlives a request
+    pub(crate) fn shared_storage(&self) -> Arc<dyn Storage> {
+        self.storage.clone()
+    }
+
+    /// Serialize a load-modify-store of `key` with other handlers
+    ///
+    /// Only requests served by this process are serialized; servers sharing
+    /// a backend need its atomic [`Storage::merge_into`] instead.
+    pub(crate) async fn lock_key(&self, key: &str) -> KeyGuard {
+        self.key_locks.lock(key).await
+    }
+
+    /// The per-key locks, for other front ends serving the same storage
+    #[cfg(feature = "resp-server")]
+    pub(crate) fn key_locks(&self) -> Arc<KeyLocks> {
+        self.key_locks.clone()
+    }
+
+    /// Get reference to the server metrics
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Metrics {
+        self.metrics.as_ref()
+    }
 }
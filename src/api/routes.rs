@@ -1,19 +1,48 @@
-use super::{handlers, AppState};
+use super::{auth, handlers, AppState};
 use axum::{
-    routing::{delete, get, post},
+    middleware::from_fn_with_state,
+    routing::{delete, get, post, put},
     Router,
 };
 
 /// Create the application router with all Redis-compatible endpoints
 pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        // Redis HyperLogLog commands
+    // Routes that modify stored keys
+    let writes = Router::new()
+        .route("/create/:key", put(handlers::create))
         .route("/pfadd/:key", post(handlers::pfadd))
-        .route("/pfcount/:keys", get(handlers::pfcount))
         .route("/pfmerge/:dest_key", post(handlers::pfmerge))
-        // Additional utility endpoints
+        .route("/fold_batch", post(handlers::fold_batch))
         .route("/delete/:key", delete(handlers::delete))
+        .route_layer(from_fn_with_state(state.clone(), auth::require_token_for_writes));
+
+    let reads = Router::new()
+        // Redis HyperLogLog commands
+        .route("/pfcount/:keys", get(handlers::pfcount))
+        .route("/pfcount_with", post(handlers::pfcount_with))
+        // Additional utility endpoints
         .route("/exists/:key", get(handlers::exists))
+        .route("/health/:key", get(handlers::health))
+        .route("/summary/:key", get(handlers::summary))
+        .route("/info/:key", get(handlers::info))
         .route("/keys", get(handlers::list_keys))
-        .with_state(state)
+        .route_layer(from_fn_with_state(state.clone(), auth::require_token_for_reads));
+
+    let router = writes.merge(reads);
+
+    #[cfg(feature = "metrics")]
+    let router = router
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            super::metrics::track_latency,
+        ))
+        // Saturation gauges are labelled with key names, so the scrape
+        // endpoint is a read like any other
+        .merge(
+            Router::new()
+                .route("/metrics", get(super::metrics::metrics))
+                .route_layer(from_fn_with_state(state.clone(), auth::require_token_for_reads)),
+        );
+
+    router.with_state(state)
 }
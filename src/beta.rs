@@ -0,0 +1,82 @@
+//! Fitted `beta(z)` polynomials for the LogLog-Beta estimator, used by
+//! [`HyperLogLog::count_beta`](crate::HyperLogLog::count_beta)
+//!
+//! Follows Qin, Kim and Tung, "LogLog-Beta and More: A New Algorithm for
+//! Cardinality Estimation Based on LogLog Counting" (2016). The paper only
+//! publishes coefficients for precision 14, so every precision was fitted the
+//! same way: simulated sketches with uniformly random 64-bit hashes, using
+//! this crate's `alpha_m` and register layout, sampled at cardinalities from 1
+//! to `m * (ln m + 4)` over 1000 sketches per precision, with a weighted
+//! least-squares fit minimizing the relative error of the estimate. At
+//! precision 14 the fit is as accurate as the published polynomial.
+
+/// Coefficients `b0..=b7` of
+/// `beta(z) = b0 * z + b1 * zl + b2 * zl^2 + ... + b7 * zl^7`, where `z` is the
+/// number of zero registers and `zl = ln(z + 1)`, for precisions 4 through 16
+pub(crate) const BETA_COEFFICIENTS: [[f64; 8]; 13] = [
+    // p = 4
+    [
+        -1.440712235e3, 1.432139698e3, 7.490730357e2, 1.993803697e2,
+        8.663877871e1, 4.928855107e0, 1.676478167e0, 7.440786079e-1,
+    ],
+    // p = 5
+    [
+        -1.561771829e3, 1.580600918e3, 6.978459237e2, 3.991923762e2,
+        -5.491262108e1, 7.071141539e1, -1.299042290e1, 2.165244662e0,
+    ],
+    // p = 6
+    [
+        -4.260034396e1, 4.210309188e1, 1.935460854e1, 1.182036424e1,
+        -2.977497698e0, 2.690843207e0, -5.297056784e-1, 7.380020271e-2,
+    ],
+    // p = 7
+    [
+        8.937986612e-1, -4.509673165e0, 8.831141686e0, -1.103552374e1,
+        6.241060215e0, -1.936906943e0, 2.980156865e-1, -1.919113553e-2,
+    ],
+    // p = 8
+    [
+        -8.217483832e0, 1.069395998e1, -5.877427685e0, 1.354717761e1,
+        -7.046291226e0, 2.462251510e0, -3.980667184e-1, 3.200753386e-2,
+    ],
+    // p = 9
+    [
+        2.148580041e-1, -2.385398567e0, 4.571704742e0, -4.625193283e0,
+        2.192639601e0, -5.855264312e-1, 7.976941685e-2, -4.852807036e-3,
+    ],
+    // p = 10
+    [
+        -4.835733345e-1, 1.162454086e0, -2.376826046e0, 2.388624113e0,
+        -1.028449345e0, 2.485694718e-1, -2.982744211e-2, 1.592911503e-3,
+    ],
+    // p = 11
+    [
+        -3.326821816e-1, -5.005855493e-1, 1.019216698e0, -9.175448985e-1,
+        5.017665952e-1, -1.244005194e-1, 1.539328369e-2, -7.032370811e-4,
+    ],
+    // p = 12
+    [
+        -3.840817452e-1, 5.280992506e-1, -1.318042603e0, 1.286487333e0,
+        -4.618151813e-1, 9.589902197e-2, -9.687644491e-3, 5.003770927e-4,
+    ],
+    // p = 13
+    [
+        -3.606844275e-1, 9.987732075e-2, 4.076275461e-1, -2.209529414e-1,
+        1.392547925e-1, -2.959366056e-2, 3.489854449e-3, -4.744667861e-5,
+    ],
+    // p = 14
+    [
+        -3.604265366e-1, -5.985115936e-1, 1.899121772e0, -1.458912881e0,
+        5.813351701e-1, -9.994975482e-2, 8.150238124e-3, -1.063263200e-4,
+    ],
+    // p = 15
+    [
+        -3.783567538e-1, -2.144764435e0, 2.810718899e0, -4.526149793e-1,
+        -2.547891485e-1, 1.244733104e-1, -1.787592213e-2, 1.060559537e-3,
+    ],
+    // p = 16
+    [
+        -3.763143771e-1, 2.140543243e0, -4.996011469e0, 4.417309497e0,
+        -1.735475161e0, 3.657358800e-1, -3.840906437e-2, 1.808577893e-3,
+    ],
+];
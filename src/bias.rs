@@ -0,0 +1,353 @@
+//! Empirical bias of the raw HyperLogLog estimate, used by
+//! [`HyperLogLog::count_hllpp`](crate::HyperLogLog::count_hllpp)
+//!
+//! Follows Heule, Nunkesser and Hall, "HyperLogLog in Practice" (2013). For
+//! each precision the tables sample cardinalities `n` from 0 to `5m` in 100
+//! steps and record the mean raw estimate at `n` and its mean bias (raw
+//! estimate minus `n`). The means were measured by simulation with uniformly
+//! random 64-bit hashes, using this crate's `alpha_m` and register layout,
+//! over at least 2000 sketches per precision.
+
+/// Cardinality below which linear counting beats the bias-corrected
+/// estimate, for precisions 4 through 16 (from the paper)
+pub(crate) const LINEAR_COUNTING_THRESHOLD: [f64; 13] = [
+    10.0, 20.0, 40.0, 80.0, 220.0, 400.0, 900.0, 1800.0, 3100.0, 6500.0, 11500.0, 20000.0, 50000.0,
+];
+
+/// Mean raw estimate at each sampled cardinality, ascending, for precisions
+/// 4 through 16
+pub(crate) const RAW_ESTIMATE_DATA: [&[f64]; 13] = [
+    // p = 4
+    &[
+        10.770, 11.239, 11.725, 12.225, 12.741, 13.273, 13.822, 14.385, 14.964, 15.559, 16.170,
+        16.796, 17.437, 18.094, 18.768, 19.456, 20.160, 20.876, 21.606, 22.352, 23.115, 23.887,
+        24.675, 25.472, 26.284, 27.106, 27.940, 28.783, 29.637, 30.501, 31.374, 32.256, 33.148,
+        34.047, 34.954, 35.868, 36.785, 37.710, 38.647, 39.585, 40.530, 41.476, 42.429, 43.389,
+        44.350, 45.316, 46.281, 47.251, 48.223, 49.195, 50.167, 51.148, 52.135, 53.120, 54.101,
+        55.081, 56.065, 57.050, 58.037, 59.026, 60.022, 61.015, 62.010, 63.009, 64.012, 65.015,
+        66.005, 67.002, 68.001, 68.999, 69.989, 70.987, 71.984, 72.984, 73.991, 74.989, 75.980,
+        76.981, 77.981, 78.980, 79.975,
+    ],
+    // p = 5
+    &[
+        22.308, 23.266, 23.757, 24.759, 25.271, 26.319, 27.397, 27.947, 29.069, 29.640, 30.805,
+        32.002, 32.612, 33.854, 34.485, 35.770, 37.088, 37.755, 39.113, 39.802, 41.200, 42.627,
+        43.350, 44.820, 45.564, 47.067, 48.598, 49.375, 50.943, 51.739, 53.342, 54.965, 55.782,
+        57.441, 58.277, 59.966, 61.665, 62.531, 64.263, 65.136, 66.886, 68.660, 69.551, 71.346,
+        72.244, 74.060, 75.887, 76.810, 78.651, 79.576, 81.450, 83.324, 84.271, 86.150, 87.100,
+        89.012, 90.922, 91.875, 93.791, 94.756, 96.694, 98.621, 99.589, 101.527, 102.500, 104.468,
+        106.430, 107.410, 109.362, 110.344, 112.314, 114.275, 115.266, 117.243, 118.233, 120.203,
+        122.180, 123.172, 125.144, 126.121, 128.096, 130.086, 131.081, 133.083, 134.081, 136.064,
+        138.071, 139.066, 141.050, 142.043, 144.031, 146.035, 147.036, 149.047, 150.041, 152.044,
+        154.044, 155.045, 157.041, 158.036, 160.046,
+    ],
+    // p = 6
+    &[
+        45.389, 46.836, 48.314, 50.337, 51.893, 53.480, 55.102, 56.753, 59.009, 60.743, 62.509,
+        64.309, 66.139, 68.629, 70.534, 72.472, 74.441, 76.445, 79.159, 81.235, 83.337, 85.470,
+        87.635, 90.564, 92.792, 95.042, 97.334, 99.647, 102.770, 105.140, 107.538, 109.953,
+        112.392, 115.695, 118.181, 120.699, 123.231, 125.794, 129.238, 131.837, 134.474, 137.092,
+        139.739, 143.314, 146.016, 148.742, 151.481, 154.226, 157.872, 160.627, 163.417, 166.211,
+        169.007, 172.770, 175.593, 178.444, 181.270, 184.133, 187.932, 190.817, 193.693, 196.606,
+        199.500, 203.360, 206.278, 209.198, 212.110, 215.041, 218.946, 221.879, 224.846, 227.787,
+        230.725, 234.657, 237.610, 240.581, 243.549, 246.517, 250.464, 253.422, 256.409, 259.372,
+        262.327, 266.306, 269.298, 272.287, 275.266, 278.244, 282.196, 285.177, 288.163, 291.118,
+        294.118, 298.085, 301.091, 304.080, 307.090, 310.076, 314.083, 317.058, 320.014,
+    ],
+    // p = 7
+    &[
+        91.555, 94.460, 97.929, 100.975, 104.612, 107.802, 111.056, 114.930, 118.324, 122.370,
+        125.915, 129.514, 133.802, 137.544, 142.004, 145.892, 149.837, 154.524, 158.611, 163.438,
+        167.659, 171.934, 176.979, 181.362, 186.547, 191.045, 195.594, 200.979, 205.640, 211.156,
+        215.942, 220.771, 226.446, 231.382, 237.193, 242.215, 247.262, 253.213, 258.365, 264.418,
+        269.633, 274.899, 281.084, 286.418, 292.696, 298.101, 303.528, 309.911, 315.395, 321.861,
+        327.393, 332.961, 339.480, 345.081, 351.665, 357.368, 363.045, 369.671, 375.421, 382.089,
+        387.819, 393.588, 400.296, 406.078, 412.830, 418.666, 424.508, 431.307, 437.149, 444.004,
+        449.885, 455.776, 462.665, 468.604, 475.505, 481.446, 487.297, 494.185, 500.125, 507.075,
+        513.008, 518.944, 525.891, 531.832, 538.830, 544.786, 550.706, 557.684, 563.666, 570.584,
+        576.603, 582.576, 589.573, 595.568, 602.517, 608.476, 614.479, 621.417, 627.478, 634.476,
+        640.416,
+    ],
+    // p = 8
+    &[
+        183.887, 190.200, 196.661, 202.760, 209.517, 216.433, 223.512, 230.718, 237.515, 245.022,
+        252.676, 260.488, 268.463, 275.937, 284.176, 292.579, 301.162, 309.853, 318.001, 326.991,
+        336.059, 345.311, 354.674, 363.451, 373.058, 382.803, 392.671, 402.679, 412.002, 422.268,
+        432.600, 443.012, 453.574, 463.395, 474.123, 484.964, 495.937, 506.969, 517.248, 528.505,
+        539.815, 551.185, 562.643, 573.279, 584.919, 596.605, 608.338, 620.157, 631.147, 643.119,
+        655.170, 667.199, 679.246, 690.444, 702.656, 714.910, 727.190, 739.509, 750.963, 763.359,
+        775.778, 788.202, 800.751, 812.284, 824.885, 837.448, 850.026, 862.680, 874.389, 887.041,
+        899.690, 912.444, 925.211, 937.000, 949.716, 962.425, 975.248, 988.110, 999.961, 1012.897,
+        1025.776, 1038.615, 1051.473, 1063.273, 1076.185, 1089.071, 1101.962, 1114.921, 1126.826,
+        1139.663, 1152.574, 1165.658, 1178.610, 1190.619, 1203.517, 1216.537, 1229.528, 1242.509,
+        1254.423, 1267.405, 1280.455,
+    ],
+    // p = 9
+    &[
+        368.551, 381.190, 393.638, 406.887, 419.907, 433.745, 447.894, 461.777, 476.493, 490.968,
+        506.319, 521.938, 537.266, 553.499, 569.393, 586.182, 603.272, 619.965, 637.602, 654.841,
+        672.998, 691.443, 709.471, 728.475, 747.037, 766.485, 786.147, 805.355, 825.597, 845.250,
+        865.855, 886.677, 906.911, 928.141, 948.739, 970.412, 992.257, 1013.373, 1035.645,
+        1057.184, 1079.782, 1102.440, 1124.448, 1147.372, 1169.761, 1193.081, 1216.525, 1239.189,
+        1262.911, 1285.927, 1309.822, 1333.851, 1357.054, 1381.408, 1404.806, 1429.181, 1453.826,
+        1477.526, 1502.082, 1525.940, 1550.918, 1575.886, 1599.814, 1624.680, 1648.890, 1674.070,
+        1699.262, 1723.415, 1748.898, 1773.213, 1798.706, 1824.235, 1848.537, 1874.029, 1898.668,
+        1924.138, 1949.679, 1974.487, 2000.212, 2024.802, 2050.566, 2076.486, 2101.128, 2127.007,
+        2151.714, 2177.431, 2203.445, 2228.379, 2254.038, 2278.938, 2304.991, 2330.786, 2355.665,
+        2381.328, 2406.081, 2432.131, 2458.166, 2483.204, 2508.962, 2533.931, 2559.869,
+    ],
+    // p = 10
+    &[
+        737.83, 762.64, 788.07, 814.55, 841.11, 868.28, 896.05, 924.42, 953.94, 983.44, 1013.54,
+        1044.20, 1075.42, 1107.83, 1140.19, 1173.09, 1206.57, 1240.61, 1275.97, 1311.19, 1346.98,
+        1383.16, 1419.78, 1457.76, 1495.48, 1533.71, 1572.36, 1611.68, 1652.07, 1692.07, 1732.56,
+        1773.43, 1814.81, 1857.36, 1899.52, 1942.13, 1985.15, 2028.38, 2073.14, 2117.11, 2161.34,
+        2205.97, 2250.96, 2297.09, 2342.37, 2388.07, 2434.10, 2480.51, 2527.79, 2574.82, 2621.75,
+        2669.08, 2716.60, 2765.21, 2813.09, 2861.08, 2909.54, 2958.11, 3007.45, 3056.13, 3104.95,
+        3153.90, 3202.85, 3252.94, 3302.14, 3351.63, 3400.85, 3450.40, 3500.68, 3550.32, 3600.11,
+        3650.00, 3699.98, 3750.83, 3801.21, 3851.68, 3901.95, 3952.20, 4003.32, 4053.65, 4103.54,
+        4153.75, 4204.43, 4255.87, 4306.36, 4357.22, 4407.70, 4458.40, 4510.02, 4561.13, 4611.55,
+        4662.28, 4712.96, 4764.42, 4815.09, 4866.30, 4916.87, 4968.02, 5020.14, 5071.24, 5122.18,
+    ],
+    // p = 11
+    &[
+        1476.44, 1526.08, 1577.41, 1629.38, 1683.09, 1737.45, 1792.97, 1850.16, 1908.01, 1967.65,
+        2027.86, 2089.37, 2152.51, 2216.21, 2281.68, 2347.67, 2414.65, 2483.34, 2552.82, 2623.87,
+        2695.36, 2767.50, 2841.68, 2916.11, 2992.41, 3068.48, 3145.80, 3224.75, 3304.09, 3385.01,
+        3466.13, 3547.98, 3631.37, 3715.44, 3800.40, 3885.27, 3971.03, 4058.85, 4146.22, 4234.61,
+        4323.45, 4412.53, 4503.28, 4593.99, 4685.48, 4776.87, 4869.14, 4962.60, 5055.61, 5150.46,
+        5244.60, 5339.30, 5435.26, 5530.71, 5627.43, 5723.50, 5819.69, 5917.23, 6013.74, 6111.60,
+        6209.60, 6307.44, 6406.57, 6505.35, 6604.77, 6703.82, 6802.41, 6902.69, 7001.61, 7102.43,
+        7201.61, 7301.43, 7402.57, 7502.85, 7603.75, 7704.56, 7804.66, 7905.89, 8006.48, 8108.15,
+        8208.54, 8309.77, 8411.33, 8512.65, 8614.50, 8715.26, 8817.01, 8919.89, 9021.37, 9123.32,
+        9225.16, 9326.33, 9428.88, 9529.79, 9631.83, 9733.86, 9835.24, 9937.60, 10039.56, 10142.13,
+        10243.95,
+    ],
+    // p = 12
+    &[
+        2953.67, 3053.37, 3155.49, 3259.34, 3366.05, 3475.19, 3586.83, 3700.78, 3816.54, 3935.13,
+        4056.09, 4179.61, 4305.24, 4432.41, 4562.53, 4695.31, 4830.45, 4967.70, 5105.94, 5247.09,
+        5390.54, 5536.40, 5684.16, 5832.93, 5985.10, 6138.72, 6294.56, 6452.08, 6610.16, 6770.99,
+        6933.82, 7098.44, 7265.13, 7431.74, 7601.26, 7772.25, 7944.85, 8120.00, 8294.39, 8471.12,
+        8648.99, 8828.09, 9008.51, 9189.52, 9373.19, 9557.48, 9742.74, 9928.95, 10114.59, 10303.05,
+        10491.40, 10679.96, 10870.48, 11061.23, 11254.07, 11446.16, 11639.93, 11834.26, 12027.77,
+        12222.13, 12418.12, 12614.63, 12811.95, 13008.90, 13207.01, 13405.88, 13603.80, 13803.06,
+        14001.71, 14200.03, 14400.15, 14602.49, 14803.09, 15002.94, 15205.47, 15407.73, 15611.40,
+        15813.73, 16015.56, 16218.18, 16421.98, 16625.93, 16827.97, 17029.46, 17234.61, 17437.25,
+        17641.37, 17845.28, 18050.27, 18255.72, 18458.94, 18662.08, 18864.43, 19068.59, 19273.19,
+        19475.90, 19682.02, 19888.01, 20091.90, 20296.91, 20500.80,
+    ],
+    // p = 13
+    &[
+        5908.1, 6107.6, 6311.3, 6520.3, 6733.5, 6952.0, 7175.2, 7402.4, 7635.2, 7871.5, 8113.6,
+        8359.9, 8609.9, 8866.1, 9126.0, 9390.8, 9660.0, 9933.5, 10211.7, 10493.8, 10780.4, 11071.2,
+        11365.9, 11666.0, 11968.0, 12275.1, 12585.6, 12899.7, 13218.6, 13540.1, 13865.5, 14194.1,
+        14525.7, 14862.0, 15199.7, 15541.9, 15886.4, 16233.3, 16584.4, 16936.5, 17290.8, 17649.3,
+        18009.5, 18371.6, 18736.2, 19104.6, 19475.2, 19845.1, 20217.9, 20592.3, 20970.3, 21349.5,
+        21729.1, 22112.4, 22496.1, 22881.1, 23266.7, 23653.4, 24041.9, 24432.6, 24823.9, 25216.3,
+        25609.5, 26005.3, 26399.9, 26798.2, 27197.1, 27592.9, 27991.9, 28391.8, 28793.1, 29195.3,
+        29597.4, 29998.6, 30401.5, 30805.4, 31207.9, 31609.5, 32014.4, 32417.8, 32823.3, 33227.9,
+        33634.6, 34040.0, 34443.8, 34853.1, 35260.8, 35666.5, 36074.0, 36480.7, 36886.9, 37297.3,
+        37706.2, 38114.3, 38519.6, 38927.3, 39336.7, 39743.5, 40151.6, 40560.9, 40970.0,
+    ],
+    // p = 14
+    &[
+        11817.0, 12215.5, 12623.7, 13041.5, 13468.1, 13904.3, 14349.9, 14805.0, 15269.9, 15743.6,
+        16227.0, 16719.8, 17221.8, 17734.0, 18254.0, 18783.6, 19321.6, 19869.4, 20426.6, 20990.3,
+        21563.2, 22144.2, 22733.4, 23331.4, 23937.2, 24550.4, 25172.0, 25801.7, 26437.7, 27082.1,
+        27732.6, 28390.2, 29052.0, 29723.3, 30399.1, 31083.8, 31773.7, 32468.8, 33170.3, 33876.1,
+        34587.2, 35304.5, 36026.9, 36754.0, 37482.6, 38217.7, 38955.3, 39698.0, 40446.4, 41200.2,
+        41952.7, 42710.6, 43470.5, 44237.0, 45006.3, 45776.5, 46551.1, 47326.9, 48106.6, 48884.8,
+        49668.5, 50454.7, 51242.3, 52032.4, 52823.0, 53615.7, 54408.7, 55204.2, 56003.9, 56802.1,
+        57600.2, 58400.4, 59203.0, 60007.8, 60810.9, 61614.5, 62419.3, 63229.4, 64039.2, 64848.2,
+        65658.6, 66468.1, 67280.4, 68094.3, 68905.3, 69717.8, 70530.3, 71345.3, 72160.5, 72974.3,
+        73790.2, 74601.8, 75419.8, 76236.1, 77051.6, 77867.0, 78684.6, 79502.8, 80321.6, 81139.2,
+        81957.9,
+    ],
+    // p = 15
+    &[
+        23634.8, 24431.7, 25248.0, 26082.8, 26937.0, 27809.3, 28700.8, 29611.9, 30541.2, 31489.7,
+        32456.3, 33441.2, 34446.1, 35468.3, 36509.7, 37568.4, 38644.5, 39739.2, 40850.0, 41979.0,
+        43124.4, 44287.6, 45467.8, 46664.0, 47875.3, 49103.2, 50345.4, 51604.0, 52876.2, 54165.7,
+        55466.9, 56780.0, 58107.1, 59449.0, 60802.7, 62168.4, 63545.3, 64934.6, 66336.0, 67748.9,
+        69172.6, 70604.5, 72047.3, 73497.8, 74959.9, 76429.0, 77907.7, 79392.3, 80885.3, 82389.8,
+        83899.1, 85415.9, 86939.6, 88467.6, 90003.1, 91543.1, 93088.8, 94641.4, 96194.5, 97758.5,
+        99325.5, 100895.9, 102473.3, 104047.9, 105633.9, 107216.6, 108803.0, 110396.4, 111994.7,
+        113592.7, 115192.6, 116792.5, 118394.4, 120003.9, 121615.1, 123225.1, 124837.6, 126454.6,
+        128069.8, 129686.1, 131302.3, 132922.6, 134540.1, 136162.0, 137785.2, 139405.3, 141031.6,
+        142657.2, 144289.1, 145919.8, 147546.6, 149177.0, 150805.9, 152432.5, 154065.2, 155697.3,
+        157328.0, 158961.7, 160593.6, 162225.6, 163860.4,
+    ],
+    // p = 16
+    &[
+        47270.3, 48864.8, 50497.2, 52166.7, 53874.5, 55619.5, 57403.4, 59225.2, 61084.4, 62981.0,
+        64914.7, 66885.2, 68893.3, 70938.2, 73019.6, 75136.1, 77290.6, 79479.6, 81702.3, 83961.6,
+        86253.4, 88578.8, 90938.5, 93327.3, 95749.9, 98206.0, 100691.8, 103207.2, 105749.0,
+        108325.5, 110928.1, 113559.0, 116217.1, 118896.5, 121603.2, 124336.7, 127089.9, 129868.7,
+        132667.3, 135494.5, 138335.3, 141200.7, 144083.5, 146988.7, 149912.3, 152854.2, 155810.1,
+        158787.7, 161774.4, 164779.5, 167796.8, 170830.7, 173877.9, 176934.4, 180001.7, 183083.4,
+        186175.0, 189280.9, 192390.7, 195516.8, 198642.5, 201781.5, 204930.5, 208088.9, 211259.3,
+        214430.9, 217603.4, 220785.4, 223976.3, 227171.0, 230369.1, 233572.8, 236778.2, 239999.8,
+        243217.7, 246436.7, 249663.8, 252893.5, 256119.2, 259357.5, 262601.7, 265843.0, 269085.7,
+        272332.7, 275579.6, 278833.3, 282091.0, 285342.6, 288596.4, 291850.7, 295113.1, 298379.0,
+        301641.7, 304903.6, 308159.5, 311421.8, 314682.2, 317947.4, 321213.6, 324489.2, 327761.6,
+    ],
+];
+
+/// Mean bias of the raw estimate matching each [`RAW_ESTIMATE_DATA`] entry
+pub(crate) const BIAS_DATA: [&[f64]; 13] = [
+    // p = 4
+    &[
+        10.770, 10.239, 9.725, 9.225, 8.741, 8.273, 7.822, 7.385, 6.964, 6.559, 6.170, 5.796,
+        5.437, 5.094, 4.768, 4.456, 4.160, 3.876, 3.606, 3.352, 3.115, 2.887, 2.675, 2.472, 2.284,
+        2.106, 1.940, 1.783, 1.637, 1.501, 1.374, 1.256, 1.148, 1.047, 0.954, 0.868, 0.785, 0.710,
+        0.647, 0.585, 0.530, 0.476, 0.429, 0.389, 0.350, 0.316, 0.281, 0.251, 0.223, 0.195, 0.167,
+        0.148, 0.135, 0.120, 0.101, 0.081, 0.065, 0.050, 0.037, 0.026, 0.022, 0.015, 0.010, 0.009,
+        0.012, 0.015, 0.005, 0.002, 0.001, -0.001, -0.011, -0.013, -0.016, -0.016, -0.009, -0.011,
+        -0.020, -0.019, -0.019, -0.020, -0.025,
+    ],
+    // p = 5
+    &[
+        22.308, 21.266, 20.757, 19.759, 19.271, 18.319, 17.397, 16.947, 16.069, 15.640, 14.805,
+        14.002, 13.612, 12.854, 12.485, 11.770, 11.088, 10.755, 10.113, 9.802, 9.200, 8.627, 8.350,
+        7.820, 7.564, 7.067, 6.598, 6.375, 5.943, 5.739, 5.342, 4.965, 4.782, 4.441, 4.277, 3.966,
+        3.665, 3.531, 3.263, 3.136, 2.886, 2.660, 2.551, 2.346, 2.244, 2.060, 1.887, 1.810, 1.651,
+        1.576, 1.450, 1.324, 1.271, 1.150, 1.100, 1.012, 0.922, 0.875, 0.791, 0.756, 0.694, 0.621,
+        0.589, 0.527, 0.500, 0.468, 0.430, 0.410, 0.362, 0.344, 0.314, 0.275, 0.266, 0.243, 0.233,
+        0.203, 0.180, 0.172, 0.144, 0.121, 0.096, 0.086, 0.081, 0.083, 0.081, 0.064, 0.071, 0.066,
+        0.050, 0.043, 0.031, 0.035, 0.036, 0.047, 0.041, 0.044, 0.044, 0.045, 0.041, 0.036, 0.046,
+    ],
+    // p = 6
+    &[
+        45.389, 43.836, 42.314, 40.337, 38.893, 37.480, 36.102, 34.753, 33.009, 31.743, 30.509,
+        29.309, 28.139, 26.629, 25.534, 24.472, 23.441, 22.445, 21.159, 20.235, 19.337, 18.470,
+        17.635, 16.564, 15.792, 15.042, 14.334, 13.647, 12.770, 12.140, 11.538, 10.953, 10.392,
+        9.695, 9.181, 8.699, 8.231, 7.794, 7.238, 6.837, 6.474, 6.092, 5.739, 5.314, 5.016, 4.742,
+        4.481, 4.226, 3.872, 3.627, 3.417, 3.211, 3.007, 2.770, 2.593, 2.444, 2.270, 2.133, 1.932,
+        1.817, 1.693, 1.606, 1.500, 1.360, 1.278, 1.198, 1.110, 1.041, 0.946, 0.879, 0.846, 0.787,
+        0.725, 0.657, 0.610, 0.581, 0.549, 0.517, 0.464, 0.422, 0.409, 0.372, 0.327, 0.306, 0.298,
+        0.287, 0.266, 0.244, 0.196, 0.177, 0.163, 0.118, 0.118, 0.085, 0.091, 0.080, 0.090, 0.076,
+        0.083, 0.058, 0.014,
+    ],
+    // p = 7
+    &[
+        91.555, 88.460, 84.929, 81.975, 78.612, 75.802, 73.056, 69.930, 67.324, 64.370, 61.915,
+        59.514, 56.802, 54.544, 52.004, 49.892, 47.837, 45.524, 43.611, 41.438, 39.659, 37.934,
+        35.979, 34.362, 32.547, 31.045, 29.594, 27.979, 26.640, 25.156, 23.942, 22.771, 21.446,
+        20.382, 19.193, 18.215, 17.262, 16.213, 15.365, 14.418, 13.633, 12.899, 12.084, 11.418,
+        10.696, 10.101, 9.528, 8.911, 8.395, 7.861, 7.393, 6.961, 6.480, 6.081, 5.665, 5.368,
+        5.045, 4.671, 4.421, 4.089, 3.819, 3.588, 3.296, 3.078, 2.830, 2.666, 2.508, 2.307, 2.149,
+        2.004, 1.885, 1.776, 1.665, 1.604, 1.505, 1.446, 1.297, 1.185, 1.125, 1.075, 1.008, 0.944,
+        0.891, 0.832, 0.830, 0.786, 0.706, 0.684, 0.666, 0.584, 0.603, 0.576, 0.573, 0.568, 0.517,
+        0.476, 0.479, 0.417, 0.478, 0.476, 0.416,
+    ],
+    // p = 8
+    &[
+        183.887, 177.200, 170.661, 164.760, 158.517, 152.433, 146.512, 140.718, 135.515, 130.022,
+        124.676, 119.488, 114.463, 109.937, 105.176, 100.579, 96.162, 91.853, 88.001, 83.991,
+        80.059, 76.311, 72.674, 69.451, 66.058, 62.803, 59.671, 56.679, 54.002, 51.268, 48.600,
+        46.012, 43.574, 41.395, 39.123, 36.964, 34.937, 32.969, 31.248, 29.505, 27.815, 26.185,
+        24.643, 23.279, 21.919, 20.605, 19.338, 18.157, 17.147, 16.119, 15.170, 14.199, 13.246,
+        12.444, 11.656, 10.910, 10.190, 9.509, 8.963, 8.359, 7.778, 7.202, 6.751, 6.284, 5.885,
+        5.448, 5.026, 4.680, 4.389, 4.041, 3.690, 3.444, 3.211, 3.000, 2.716, 2.425, 2.248, 2.110,
+        1.961, 1.897, 1.776, 1.615, 1.473, 1.273, 1.185, 1.071, 0.962, 0.921, 0.826, 0.663, 0.574,
+        0.658, 0.610, 0.619, 0.517, 0.537, 0.528, 0.509, 0.423, 0.405, 0.455,
+    ],
+    // p = 9
+    &[
+        368.551, 355.190, 342.638, 329.887, 317.907, 305.745, 293.894, 282.777, 271.493, 260.968,
+        250.319, 239.938, 230.266, 220.499, 211.393, 202.182, 193.272, 184.965, 176.602, 168.841,
+        160.998, 153.443, 146.471, 139.475, 133.037, 126.485, 120.147, 114.355, 108.597, 103.250,
+        97.855, 92.677, 87.911, 83.141, 78.739, 74.412, 70.257, 66.373, 62.645, 59.184, 55.782,
+        52.440, 49.448, 46.372, 43.761, 41.081, 38.525, 36.189, 33.911, 31.927, 29.822, 27.851,
+        26.054, 24.408, 22.806, 21.181, 19.826, 18.526, 17.082, 15.940, 14.918, 13.886, 12.814,
+        11.680, 10.890, 10.070, 9.262, 8.415, 7.898, 7.213, 6.706, 6.235, 5.537, 5.029, 4.668,
+        4.138, 3.679, 3.487, 3.212, 2.802, 2.566, 2.486, 2.128, 2.007, 1.714, 1.431, 1.445, 1.379,
+        1.038, 0.938, 0.991, 0.786, 0.665, 0.328, 0.081, 0.131, 0.166, 0.204, -0.038, -0.069,
+        -0.131,
+    ],
+    // p = 10
+    &[
+        737.83, 711.64, 686.07, 660.55, 636.11, 612.28, 589.05, 566.42, 543.94, 522.44, 501.54,
+        481.20, 461.42, 441.83, 423.19, 405.09, 387.57, 370.61, 353.97, 338.19, 322.98, 308.16,
+        293.78, 279.76, 266.48, 253.71, 241.36, 229.68, 218.07, 207.07, 196.56, 186.43, 176.81,
+        167.36, 158.52, 150.13, 142.15, 134.38, 127.14, 120.11, 113.34, 106.97, 100.96, 95.09,
+        89.37, 84.07, 79.10, 74.51, 69.79, 65.82, 61.75, 58.08, 54.60, 51.21, 48.09, 45.08, 42.54,
+        40.11, 37.45, 35.13, 32.95, 30.90, 28.85, 26.94, 25.14, 23.63, 21.85, 20.40, 18.68, 17.32,
+        16.11, 15.00, 13.98, 12.83, 12.21, 11.68, 10.95, 10.20, 9.32, 8.65, 7.54, 6.75, 6.43, 5.87,
+        5.36, 5.22, 4.70, 4.40, 4.02, 4.13, 3.55, 3.28, 2.96, 2.42, 2.09, 2.30, 1.87, 2.02, 2.14,
+        2.24, 2.18,
+    ],
+    // p = 11
+    &[
+        1476.44, 1424.08, 1372.41, 1322.38, 1273.09, 1225.45, 1178.97, 1133.16, 1089.01, 1045.65,
+        1003.86, 963.37, 923.51, 885.21, 847.68, 811.67, 776.65, 742.34, 709.82, 677.87, 647.36,
+        617.50, 588.68, 561.11, 534.41, 508.48, 483.80, 459.75, 437.09, 415.01, 394.13, 373.98,
+        354.37, 336.44, 318.40, 301.27, 285.03, 269.85, 255.22, 240.61, 227.45, 214.53, 202.28,
+        190.99, 179.48, 168.87, 159.14, 149.60, 140.61, 132.46, 124.60, 117.30, 110.26, 103.71,
+        97.43, 91.50, 85.69, 80.23, 74.74, 69.60, 65.60, 61.44, 57.57, 54.35, 50.77, 47.82, 44.41,
+        41.69, 38.61, 36.43, 33.61, 31.43, 29.57, 27.85, 25.75, 24.56, 22.66, 20.89, 19.48, 18.15,
+        16.54, 15.77, 14.33, 13.65, 12.50, 11.26, 11.01, 10.89, 10.37, 9.32, 9.16, 8.33, 7.88,
+        6.79, 5.83, 5.86, 5.24, 4.60, 4.56, 4.13, 3.95,
+    ],
+    // p = 12
+    &[
+        2953.67, 2848.37, 2745.49, 2645.34, 2547.05, 2451.19, 2357.83, 2266.78, 2178.54, 2092.13,
+        2008.09, 1926.61, 1847.24, 1770.41, 1695.53, 1623.31, 1553.45, 1485.70, 1419.94, 1356.09,
+        1294.54, 1235.40, 1178.16, 1122.93, 1070.10, 1018.72, 969.56, 922.08, 876.16, 831.99,
+        789.82, 749.44, 711.13, 673.74, 638.26, 604.25, 571.85, 542.00, 512.39, 484.12, 456.99,
+        431.09, 406.51, 383.52, 362.19, 341.48, 321.74, 302.95, 284.59, 268.05, 251.40, 234.96,
+        220.48, 207.23, 195.07, 182.16, 170.93, 160.26, 149.77, 139.13, 130.12, 121.63, 113.95,
+        106.90, 100.01, 93.88, 86.80, 81.06, 75.71, 69.03, 64.15, 61.49, 57.09, 52.94, 50.47,
+        47.73, 46.40, 43.73, 41.56, 39.18, 37.98, 36.93, 33.97, 31.46, 31.61, 29.25, 28.37, 27.28,
+        28.27, 28.72, 26.94, 25.08, 22.43, 22.59, 22.19, 19.90, 21.02, 22.01, 21.90, 21.91, 20.80,
+    ],
+    // p = 13
+    &[
+        5908.1, 5697.6, 5492.3, 5291.3, 5095.5, 4904.0, 4717.2, 4535.4, 4358.2, 4185.5, 4017.6,
+        3853.9, 3694.9, 3541.1, 3392.0, 3246.8, 3106.0, 2970.5, 2838.7, 2711.8, 2588.4, 2469.2,
+        2354.9, 2245.0, 2138.0, 2035.1, 1935.6, 1840.7, 1749.6, 1662.1, 1577.5, 1496.1, 1418.7,
+        1345.0, 1273.7, 1205.9, 1140.4, 1078.3, 1019.4, 962.5, 906.8, 855.3, 806.5, 758.6, 714.2,
+        672.6, 633.2, 594.1, 556.9, 522.3, 490.3, 459.5, 430.1, 403.4, 378.1, 353.1, 328.7, 306.4,
+        284.9, 266.6, 247.9, 230.3, 214.5, 200.3, 185.9, 174.2, 163.1, 149.9, 138.9, 129.8, 121.1,
+        113.3, 106.4, 97.6, 91.5, 85.4, 77.9, 70.5, 65.4, 59.8, 55.3, 49.9, 47.6, 43.0, 37.8, 37.1,
+        34.8, 31.5, 29.0, 26.7, 22.9, 23.3, 23.2, 21.3, 17.6, 15.3, 14.7, 12.5, 10.6, 10.9, 10.0,
+    ],
+    // p = 14
+    &[
+        11817.0, 11396.5, 10985.7, 10583.5, 10191.1, 9808.3, 9434.9, 9071.0, 8715.9, 8370.6,
+        8035.0, 7708.8, 7391.8, 7084.0, 6785.0, 6495.6, 6214.6, 5943.4, 5680.6, 5425.3, 5179.2,
+        4941.2, 4711.4, 4489.4, 4276.2, 4070.4, 3873.0, 3683.7, 3499.7, 3325.1, 3156.6, 2995.2,
+        2838.0, 2689.3, 2546.1, 2411.8, 2282.7, 2158.8, 2040.3, 1927.1, 1819.2, 1717.5, 1620.9,
+        1528.0, 1437.6, 1353.7, 1272.3, 1196.0, 1124.4, 1059.2, 992.7, 931.6, 872.5, 819.0, 769.3,
+        720.5, 676.1, 632.9, 592.6, 551.8, 516.5, 483.7, 452.3, 422.4, 394.0, 367.7, 341.7, 318.2,
+        297.9, 277.1, 256.2, 237.4, 221.0, 205.8, 189.9, 174.5, 160.3, 151.4, 141.2, 131.2, 122.6,
+        113.1, 106.4, 100.3, 92.3, 85.8, 79.3, 75.3, 70.5, 65.3, 62.2, 54.8, 53.8, 50.1, 46.6,
+        43.0, 41.6, 40.8, 39.6, 38.2, 37.9,
+    ],
+    // p = 15
+    &[
+        23634.8, 22793.7, 21971.0, 21167.8, 20383.0, 19617.3, 18870.8, 18142.9, 17434.2, 16743.7,
+        16072.3, 15419.2, 14785.1, 14169.3, 13571.7, 12992.4, 12430.5, 11886.2, 11359.0, 10849.0,
+        10356.4, 9881.6, 9422.8, 8981.0, 8553.3, 8143.2, 7747.4, 7367.0, 7001.2, 6651.7, 6314.9,
+        5990.0, 5678.1, 5382.0, 5096.7, 4824.4, 4563.3, 4313.6, 4077.0, 3850.9, 3636.6, 3430.5,
+        3234.3, 3046.8, 2869.9, 2701.0, 2541.7, 2387.3, 2242.3, 2107.8, 1979.1, 1857.9, 1742.6,
+        1632.6, 1529.1, 1431.1, 1338.8, 1252.4, 1167.5, 1092.5, 1021.5, 953.9, 892.3, 828.9, 775.9,
+        720.6, 669.0, 623.4, 583.7, 542.7, 504.6, 466.5, 429.4, 400.9, 373.1, 345.1, 319.6, 297.6,
+        274.8, 252.1, 230.3, 212.6, 191.1, 175.0, 159.2, 141.3, 129.6, 116.2, 110.1, 101.8, 90.6,
+        83.0, 72.9, 61.5, 55.2, 49.3, 42.0, 36.7, 30.6, 23.6, 20.4,
+    ],
+    // p = 16
+    &[
+        47270.3, 45587.8, 43943.2, 42336.7, 40767.5, 39235.5, 37742.4, 36287.2, 34870.4, 33490.0,
+        32146.7, 30840.2, 29571.3, 28340.2, 27144.6, 25984.1, 24861.6, 23773.6, 22720.3, 21702.6,
+        20717.4, 19765.8, 18848.5, 17961.3, 17106.9, 16286.0, 15494.8, 14733.2, 13999.0, 13298.5,
+        12624.1, 11978.0, 11359.1, 10762.5, 10192.2, 9648.7, 9124.9, 8626.7, 8149.3, 7699.5,
+        7263.3, 6851.7, 6457.5, 6086.7, 5733.3, 5398.2, 5077.1, 4777.7, 4488.4, 4216.5, 3956.8,
+        3713.7, 3483.9, 3264.4, 3054.7, 2859.4, 2674.0, 2502.9, 2336.7, 2185.8, 2034.5, 1896.5,
+        1768.5, 1650.9, 1544.3, 1438.9, 1334.4, 1239.4, 1154.3, 1072.0, 993.1, 919.8, 848.2, 793.8,
+        734.7, 676.7, 626.8, 579.5, 529.2, 490.5, 457.7, 422.0, 387.7, 358.7, 328.6, 305.3, 286.0,
+        260.6, 238.4, 215.7, 201.1, 190.0, 175.7, 161.6, 140.5, 125.8, 109.2, 97.4, 87.6, 86.2,
+        81.6,
+    ],
+];
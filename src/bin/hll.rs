@@ -0,0 +1,107 @@
+//! Offline sketch operations for shell pipelines
+//!
+//! Sketch files hold the compact binary format of
+//! [`HyperLogLog::to_bytes`], the same bytes the binary storage format
+//! writes.
+//!
+//! ```text
+//! hll create --precision 14 visitors.hll
+//! hll add visitors.hll < ids.txt
+//! hll count visitors.hll
+//! hll merge monday.hll tuesday.hll -o week.hll
+//! ```
+
+use clap::{Parser, Subcommand};
+use hyperloglog::{HyperLogLog, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "hll", version, about = "Create, fill, count and merge HyperLogLog sketch files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write an empty sketch to a new file
+    Create {
+        /// Bits of the hash used to pick a register, 4 to 16
+        #[arg(short, long, default_value_t = 14)]
+        precision: u8,
+        /// File to create; an existing file is left alone
+        file: PathBuf,
+    },
+    /// Add every line of standard input to a sketch file
+    Add {
+        file: PathBuf,
+    },
+    /// Print the estimated number of distinct elements, of the union if
+    /// several files are given
+    Count {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Write the union of sketch files to another file
+    Merge {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// File to write the union to, replacing it if it exists
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("hll: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Create { precision, file } => {
+            let hll = HyperLogLog::new(precision)?;
+            let mut out = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&file)
+                .map_err(|e| with_path(&file, e))?;
+            out.write_all(&hll.to_bytes()).map_err(|e| with_path(&file, e))?;
+        }
+        Command::Add { file } => {
+            let mut hll = load(&file)?;
+            hll.add_reader(io::stdin().lock())?;
+            fs::write(&file, hll.to_bytes()).map_err(|e| with_path(&file, e))?;
+        }
+        Command::Count { files } => {
+            println!("{}", union(&files)?.count());
+        }
+        Command::Merge { files, output } => {
+            fs::write(&output, union(&files)?.to_bytes()).map_err(|e| with_path(&output, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn load(path: &Path) -> Result<HyperLogLog> {
+    HyperLogLog::from_bytes(&fs::read(path).map_err(|e| with_path(path, e))?)
+}
+
+/// Name the file in an I/O error, which the OS message leaves out
+fn with_path(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+/// Union of the sketches in `paths`, which must share a layout
+fn union(paths: &[PathBuf]) -> Result<HyperLogLog> {
+    let hlls = paths.iter().map(|path| load(path)).collect::<Result<Vec<_>>>()?;
+    HyperLogLog::merge_all(&hlls)
+}
@@ -0,0 +1,145 @@
+//! A sketch many threads can add to at once, without a lock
+
+use crate::{HllError, HyperLogLog};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// HyperLogLog with atomic registers, for counting from many threads
+///
+/// Adding takes `&self` and raises one register with an atomic `fetch_max`,
+/// so threads never wait on each other the way they would on a
+/// `Mutex<HyperLogLog>`. Elements hash exactly as they would in a
+/// [`HyperLogLog`] with the same settings, so a [snapshot](Self::to_hll)
+/// equals a sketch that had every element added on one thread.
+///
+/// Registers are updated independently: a snapshot or count taken while
+/// other threads are adding sees some of their elements and not others,
+/// but never a partially written register.
+#[derive(Debug)]
+pub struct ConcurrentHyperLogLog {
+    /// Empty sketch holding the precision, layout, hasher and settings
+    template: HyperLogLog,
+    registers: Box<[AtomicU8]>,
+}
+
+impl ConcurrentHyperLogLog {
+    /// Create an empty sketch with `2^precision` registers
+    pub fn new(precision: u8) -> Result<Self, HllError> {
+        Ok(Self::from_hll(&HyperLogLog::new(precision)?))
+    }
+
+    /// Start from the registers and settings of `hll`
+    ///
+    /// The seed, hasher, layout, normalizer and element length limit carry
+    /// over, so adding here matches adding to `hll`.
+    pub fn from_hll(hll: &HyperLogLog) -> Self {
+        let registers = hll.registers().iter().map(|&val| AtomicU8::new(val)).collect();
+        let mut template = hll.clone();
+        template.clear();
+
+        Self { template, registers }
+    }
+
+    /// Add an element, as [`HyperLogLog::add`]; true if a register changed
+    pub fn add<T: Hash>(&self, element: &T) -> bool {
+        self.add_hash(self.template.hash_element(element))
+    }
+
+    /// Add a string element, as [`HyperLogLog::add_str`]
+    pub fn add_str(&self, element: &str) -> bool {
+        self.template.hash_str(element).is_some_and(|hash| self.add_hash(hash))
+    }
+
+    /// Add a raw byte string, as [`HyperLogLog::add_bytes`]
+    pub fn add_bytes(&self, bytes: &[u8]) -> bool {
+        self.template.hash_raw_bytes(bytes).is_some_and(|hash| self.add_hash(hash))
+    }
+
+    /// Add an element by its precomputed hash, as [`HyperLogLog::add_hash`]
+    pub fn add_hash(&self, hash: u64) -> bool {
+        let (idx, rank) = self.template.index_and_rank(hash);
+        // Registers are independent, so no ordering with other memory is needed
+        self.registers[idx].fetch_max(rank, Ordering::Relaxed) < rank
+    }
+
+    /// Estimated number of distinct elements added so far
+    pub fn count(&self) -> u64 {
+        self.to_hll().count()
+    }
+
+    /// Copy the registers into a plain, dense [`HyperLogLog`], e.g. to
+    /// serialize or merge it
+    pub fn to_hll(&self) -> HyperLogLog {
+        let registers = self.registers.iter().map(|val| val.load(Ordering::Relaxed)).collect();
+        let mut hll = self.template.clone();
+        hll.replace_registers(registers);
+        hll
+    }
+
+    /// Number of bits used for the register index
+    pub fn precision(&self) -> u8 {
+        self.template.precision()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HasherKind;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_matches_single_threaded() {
+        let concurrent = Arc::new(ConcurrentHyperLogLog::new(12).unwrap());
+
+        // Eight threads over overlapping ranges: 0..75_000 in total
+        let handles: Vec<_> = (0..8u64)
+            .map(|t| {
+                let concurrent = concurrent.clone();
+                thread::spawn(move || {
+                    for i in t * 5_000..t * 5_000 + 40_000 {
+                        concurrent.add(&i);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut baseline = HyperLogLog::new(12).unwrap();
+        baseline.add_all(0..75_000u64);
+
+        assert_eq!(concurrent.to_hll(), baseline);
+        assert_eq!(concurrent.count(), baseline.count());
+    }
+
+    #[test]
+    fn test_concurrent_keeps_settings() {
+        let hll = HyperLogLog::with_hasher(10, HasherKind::SipHash13)
+            .unwrap()
+            .with_normalizer(|s| s.to_lowercase());
+        let mut expected = hll.clone();
+        let concurrent = ConcurrentHyperLogLog::from_hll(&hll);
+        assert_eq!(concurrent.precision(), 10);
+
+        for element in ["Alice", "BOB", "alice"] {
+            assert_eq!(concurrent.add_str(element), expected.add_str(element));
+        }
+        assert!(concurrent.add_bytes(b"raw"));
+        expected.add_bytes(b"raw");
+        assert!(concurrent.add(&42));
+        assert!(!concurrent.add(&42));
+        expected.add(&42);
+
+        let snapshot = concurrent.to_hll();
+        assert_eq!(snapshot, expected);
+        assert_eq!(snapshot.hasher(), HasherKind::SipHash13);
+
+        // Starting from a sparse sketch keeps its registers
+        let mut sparse = HyperLogLog::new_sparse(10).unwrap();
+        sparse.add_all(0..100);
+        assert_eq!(ConcurrentHyperLogLog::from_hll(&sparse).to_hll(), sparse);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::hll::{MAX_PRECISION, MIN_PRECISION};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,11 +15,20 @@ pub enum HllError {
     #[error("Invalid key: {0}")]
     InvalidKey(String),
 
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
     #[error("HyperLogLog not found: {0}")]
     NotFound(String),
 
-    #[error("Invalid precision: {0}")]
+    #[error("HyperLogLog already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Invalid precision {0} (must be {min}..={max})", min = MIN_PRECISION, max = MAX_PRECISION)]
     InvalidPrecision(u8),
+
+    #[error("Invalid register layout: {0}")]
+    InvalidLayout(String),
 }
 
 pub type Result<T> = std::result::Result<T, HllError>;
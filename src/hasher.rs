@@ -0,0 +1,166 @@
+//! Hash functions a [`HyperLogLog`](crate::HyperLogLog) can hash elements with
+
+use serde::{Deserialize, Serialize};
+use siphasher::sip::SipHasher13;
+use std::hash::Hasher;
+use twox_hash::XxHash64;
+
+/// A seeded 64-bit hash function over raw bytes
+///
+/// Implemented by the built-in hashers, which
+/// [`HyperLogLog::add_bytes`](crate::HyperLogLog::add_bytes) uses. A sketch
+/// records which built-in it uses as a [`HasherKind`].
+pub trait HllHasher {
+    /// Hash `bytes` to 64 bits
+    fn hash64(&self, bytes: &[u8]) -> u64;
+}
+
+/// xxHash64, the default: fast and well distributed, but not keyed, so an
+/// attacker who knows the seed can craft colliding elements
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XxHasher {
+    seed: u64,
+}
+
+impl XxHasher {
+    /// xxHash64 with the given seed
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl HllHasher for XxHasher {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        let mut hasher = XxHash64::with_seed(self.seed);
+        hasher.write(bytes);
+        hasher.finish()
+    }
+}
+
+/// SipHash-1-3 keyed with `(seed, 0)`
+///
+/// Slower than xxHash64, but with a secret seed an attacker cannot aim
+/// elements at chosen registers to inflate or skew the estimate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SipHasher {
+    seed: u64,
+}
+
+impl SipHasher {
+    /// SipHash-1-3 keyed with `(seed, 0)`
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl HllHasher for SipHasher {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        let mut hasher = SipHasher13::new_with_keys(self.seed, 0);
+        hasher.write(bytes);
+        hasher.finish()
+    }
+}
+
+/// Which built-in hash function a sketch uses
+///
+/// Part of the sketch's identity: it is serialized with the registers and
+/// sketches with different kinds refuse to merge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HasherKind {
+    /// [`XxHasher`]
+    #[default]
+    XxHash64,
+    /// [`SipHasher`]
+    SipHash13,
+}
+
+impl HasherKind {
+    /// Tag stored in the binary format
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            HasherKind::XxHash64 => 0,
+            HasherKind::SipHash13 => 1,
+        }
+    }
+
+    /// Inverse of [`HasherKind::to_byte`]
+    pub(crate) fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(HasherKind::XxHash64),
+            1 => Some(HasherKind::SipHash13),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        *self == HasherKind::default()
+    }
+
+    /// Hash raw bytes under `seed`
+    pub(crate) fn hash_bytes(self, seed: u64, bytes: &[u8]) -> u64 {
+        match self {
+            HasherKind::XxHash64 => XxHasher::new(seed).hash64(bytes),
+            HasherKind::SipHash13 => SipHasher::new(seed).hash64(bytes),
+        }
+    }
+
+    /// Streaming hasher for `Hash` values under `seed`
+    pub(crate) fn build(self, seed: u64) -> ElementHasher {
+        match self {
+            HasherKind::XxHash64 => ElementHasher::XxHash64(XxHash64::with_seed(seed)),
+            HasherKind::SipHash13 => ElementHasher::SipHash13(SipHasher13::new_with_keys(seed, 0)),
+        }
+    }
+}
+
+/// [`Hasher`] for one of the built-in kinds, without boxing
+pub(crate) enum ElementHasher {
+    XxHash64(XxHash64),
+    SipHash13(SipHasher13),
+}
+
+impl Hasher for ElementHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            ElementHasher::XxHash64(hasher) => hasher.write(bytes),
+            ElementHasher::SipHash13(hasher) => hasher.write(bytes),
+        }
+    }
+
+    // Forward the integer writes too, since the wrapped hashers may encode
+    // integers differently from their default byte-slice fallback
+    fn write_u8(&mut self, i: u8) {
+        match self {
+            ElementHasher::XxHash64(hasher) => hasher.write_u8(i),
+            ElementHasher::SipHash13(hasher) => hasher.write_u8(i),
+        }
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        match self {
+            ElementHasher::XxHash64(hasher) => hasher.write_u32(i),
+            ElementHasher::SipHash13(hasher) => hasher.write_u32(i),
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        match self {
+            ElementHasher::XxHash64(hasher) => hasher.write_u64(i),
+            ElementHasher::SipHash13(hasher) => hasher.write_u64(i),
+        }
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        match self {
+            ElementHasher::XxHash64(hasher) => hasher.write_usize(i),
+            ElementHasher::SipHash13(hasher) => hasher.write_usize(i),
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        match self {
+            ElementHasher::XxHash64(hasher) => hasher.finish(),
+            ElementHasher::SipHash13(hasher) => hasher.finish(),
+        }
+    }
+}
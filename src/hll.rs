@@ -1,348 +1,4326 @@
+use crate::beta::BETA_COEFFICIENTS;
+use crate::bias::{BIAS_DATA, LINEAR_COUNTING_THRESHOLD, RAW_ESTIMATE_DATA};
+use crate::error::HllError;
+use crate::hasher::HasherKind;
+use serde::ser::{SerializeStruct, Serializer};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use twox_hash::XxHash64;
 
+/// Smallest supported precision
+pub(crate) const MIN_PRECISION: u8 = 4;
+
+/// Largest supported precision
+pub(crate) const MAX_PRECISION: u8 = 16;
+
+/// Precision [`HyperLogLogBuilder`] uses unless told otherwise
+const DEFAULT_PRECISION: u8 = 14;
+
+/// Exact `alpha_m` for precisions 4 through 9, evaluated numerically from
+/// `1 / (m * integral_0^inf log2((2 + u) / (1 + u))^m du)`
+///
+/// The closed-form approximation `0.7213 / (1 + 1.079 / m)` is weakest at
+/// these sizes, e.g. 0.4% high at p=4.
+const SMALL_PRECISION_ALPHA: [f64; 6] = [
+    0.673_102_023_9,
+    0.697_122_633_8,
+    0.709_208_452_9,
+    0.715_271_190_0,
+    0.718_307_638_2,
+    0.719_827_147_8,
+];
+
+/// Number of nearest [`RAW_ESTIMATE_DATA`] points averaged to interpolate the bias
+const BIAS_NEIGHBORS: usize = 6;
+
+/// Leading bytes of the binary encoding produced by [`HyperLogLog::to_bytes`]
+const MAGIC: &[u8; 3] = b"HLL";
+
+/// Binary format version written by this build
+const FORMAT_VERSION: u8 = 4;
+
+/// Version of the serde representation (JSON, MessagePack, bincode) written
+/// by this build; data without a version predates it and is version 1
+pub(crate) const SERDE_FORMAT_VERSION: u8 = 1;
+
+/// Magic, format version, precision, rank bits, register width, hash seed
+/// and hasher kind
+const HEADER_LEN: usize = MAGIC.len() + 13;
+
+/// Version 3 had no hasher kind
+const V3_HEADER_LEN: usize = MAGIC.len() + 12;
+
+/// Version 2 had no hash seed
+const V2_HEADER_LEN: usize = MAGIC.len() + 4;
+
+/// Version 1 had no register width and stored one byte per register
+const V1_HEADER_LEN: usize = MAGIC.len() + 3;
+
+/// Leading bytes of a Redis HyperLogLog string
+const REDIS_MAGIC: &[u8; 4] = b"HYLL";
+
+/// Redis header: magic, encoding, three unused bytes and the cached
+/// cardinality as a little-endian `u64`
+const REDIS_HEADER_LEN: usize = 16;
+
+/// Redis encoding byte of the dense representation
+const REDIS_DENSE: u8 = 0;
+
+/// Redis encoding byte of the sparse representation
+const REDIS_SPARSE: u8 = 1;
+
+/// Redis always uses 2^14 registers
+const REDIS_PRECISION: u8 = 14;
+
+/// Bits per register in the Redis dense encoding
+const REDIS_REGISTER_WIDTH: u8 = 6;
+
+/// Register widths, in bits, the binary format can pack registers into
+const REGISTER_WIDTHS: [u8; 3] = [4, 6, 8];
+
+/// Width byte marking a binary sparse sketch: `(u32 index, u8 value)` pairs
+/// instead of packed registers
+const SPARSE_WIDTH: u8 = 0;
+
+/// Bytes per register in the binary sparse encoding
+const SPARSE_ENCODED_LEN: usize = 5;
+
+/// Bits per register of a [`HyperLogLog::new_packed`] sketch, enough for
+/// the largest register any precision produces (`65 - 4`)
+const PACKED_WIDTH: u8 = 6;
+
+/// Memory taken by one `(index, value)` pair of a sparse sketch
+const SPARSE_ENTRY_BYTES: usize = std::mem::size_of::<(u32, u8)>();
+
 /// HyperLogLog implementation for cardinality estimation
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Serializes with serde as a struct led by a format `version`, so future
+/// layout changes can be told apart from data written now.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "HyperLogLogRepr")]
 pub struct HyperLogLog {
     /// Precision parameter (typically 4-16)
     precision: u8,
-    /// Number of registers (2^precision)
+    /// Number of registers (2^precision); not serialized, since the
+    /// precision determines it
     m: usize,
-    /// Registers storing max leading zeros
+    /// Registers storing max leading zeros; empty while the sketch is sparse
+    /// or packed
+    registers: Vec<u8>,
+    /// Nonzero registers as `(index, value)` pairs sorted by index, while
+    /// the sketch uses the sparse representation
+    sparse: Option<Vec<(u32, u8)>>,
+    /// Registers bit-packed at [`PACKED_WIDTH`] bits each, for sketches
+    /// created with [`HyperLogLog::new_packed`]
+    packed: Option<Vec<u8>>,
+    /// Hash bits following the index that are scanned for leading zeros
+    rank_bits: u8,
+    /// Seed of the element hash
+    seed: u64,
+    /// Hash function elements are hashed with
+    hasher: HasherKind,
+    /// Transformation applied to string elements before hashing; not
+    /// serialized
+    normalizer: Option<Normalizer>,
+    /// Multiplier applied to every estimate returned by `count`; not
+    /// serialized
+    calibration: f64,
+    /// Longest string, in bytes, that `add_str` accepts; not serialized
+    max_element_len: Option<usize>,
+    /// Zero count and `2^-register` sum, kept in step with the registers
+    stats: RegisterStats,
+    /// Estimate returned by the last `count`, until the next mutation
+    cached_count: CachedCount,
+}
+
+/// Writes `version` first, then the fields `HyperLogLogRepr` reads back
+///
+/// The sparse and packed registers are left out unless in use, and the
+/// hasher while it is the default, to keep older readers working.
+impl Serialize for HyperLogLog {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let optional = [self.sparse.is_some(), self.packed.is_some(), !self.hasher.is_default()];
+        let len = 5 + optional.iter().filter(|&&present| present).count();
+
+        let mut state = serializer.serialize_struct("HyperLogLog", len)?;
+        state.serialize_field("version", &SERDE_FORMAT_VERSION)?;
+        state.serialize_field("precision", &self.precision)?;
+        state.serialize_field("registers", &RegisterBytes(&self.registers))?;
+        match &self.sparse {
+            Some(pairs) => state.serialize_field("sparse", pairs)?,
+            None => state.skip_field("sparse")?,
+        }
+        match &self.packed {
+            Some(packed) => state.serialize_field("packed", packed)?,
+            None => state.skip_field("packed")?,
+        }
+        state.serialize_field("rank_bits", &self.rank_bits)?;
+        state.serialize_field("seed", &self.seed)?;
+        if self.hasher.is_default() {
+            state.skip_field("hasher")?;
+        } else {
+            state.serialize_field("hasher", &self.hasher)?;
+        }
+        state.end()
+    }
+}
+
+/// Builder for a [`HyperLogLog`], collecting options before validating them
+///
+/// Defaults to precision 14, seed 0 and xxHash64, the same sketch as
+/// `HyperLogLog::new(14)`.
+///
+/// ```
+/// use hyperloglog::HyperLogLogBuilder;
+///
+/// let hll = HyperLogLogBuilder::default().precision(12).seed(7).build().unwrap();
+/// assert_eq!(hll.precision(), 12);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HyperLogLogBuilder {
+    precision: u8,
+    seed: u64,
+    hasher: HasherKind,
+}
+
+impl Default for HyperLogLogBuilder {
+    fn default() -> Self {
+        Self {
+            precision: DEFAULT_PRECISION,
+            seed: 0,
+            hasher: HasherKind::default(),
+        }
+    }
+}
+
+impl HyperLogLogBuilder {
+    /// Number of index bits, between 4 and 16
+    pub fn precision(mut self, precision: u8) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Seed of the element hash, see [`HyperLogLog::with_seed`]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Hash function for elements, see [`HyperLogLog::with_hasher`]
+    pub fn hasher(mut self, hasher: HasherKind) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    /// Build the sketch, failing if the precision is out of range
+    pub fn build(self) -> Result<HyperLogLog, HllError> {
+        let mut hll = HyperLogLog::with_seed(self.precision, self.seed)?;
+        hll.hasher = self.hasher;
+        Ok(hll)
+    }
+}
+
+/// Cost and accuracy of one precision, as listed by [`HyperLogLog::precision_table`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PrecisionInfo {
+    /// Precision (index bits)
+    pub precision: u8,
+    /// Number of registers (2^precision)
+    pub registers: usize,
+    /// Memory used by the registers, in bytes
+    pub memory_bytes: usize,
+    /// Theoretical standard error, `1.04 / sqrt(registers)`
+    pub relative_error: f64,
+}
+
+/// Headline numbers of a HyperLogLog, without its registers
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HllSummary {
+    /// Number of bits used for the register index
+    pub precision: u8,
+    /// Estimated cardinality, as returned by [`HyperLogLog::count`]
+    pub count: u64,
+    /// Fraction of registers that are nonzero
+    pub fill_ratio: f64,
+    /// Heap memory held by the registers, as [`HyperLogLog::memory_bytes`]
+    pub memory_bytes: usize,
+    /// Regime of the estimate, as [`HyperLogLog::estimate_quality`]
+    pub quality: EstimateQuality,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of `fold_to` calls on this thread, for tests of fold-avoiding code
+    static FOLDS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Bytes needed to hold `count` registers of `width` bits
+fn packed_len(count: usize, width: u8) -> usize {
+    (count * width as usize).div_ceil(8)
+}
+
+/// Bit-pack registers least significant bit first; each value must fit in `width` bits
+fn pack_registers(registers: &[u8], width: u8) -> Vec<u8> {
+    let mut packed = vec![0u8; packed_len(registers.len(), width)];
+    for (i, &val) in registers.iter().enumerate() {
+        set_packed(&mut packed, width, i, val);
+    }
+    packed
+}
+
+/// Inverse of [`pack_registers`]
+fn unpack_registers(packed: &[u8], width: u8, count: usize) -> Vec<u8> {
+    (0..count).map(|i| get_packed(packed, width, i)).collect()
+}
+
+/// Register `idx` of a [`pack_registers`] buffer
+fn get_packed(packed: &[u8], width: u8, idx: usize) -> u8 {
+    let bit = idx * width as usize;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let mut word = u16::from(packed[byte]);
+    if let Some(&next) = packed.get(byte + 1) {
+        word |= u16::from(next) << 8;
+    }
+    ((word >> shift) & ((1 << width) - 1)) as u8
+}
+
+/// Overwrite register `idx` of a [`pack_registers`] buffer; `val` must fit
+/// in `width` bits
+fn set_packed(packed: &mut [u8], width: u8, idx: usize, val: u8) {
+    let bit = idx * width as usize;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let mask = ((1u16 << width) - 1) << shift;
+    let shifted = u16::from(val) << shift;
+    packed[byte] = (packed[byte] & !(mask as u8)) | shifted as u8;
+    if shift + width as usize > 8 {
+        packed[byte + 1] = (packed[byte + 1] & !((mask >> 8) as u8)) | (shifted >> 8) as u8;
+    }
+}
+
+/// Estimate the cardinality of a register array with the default layout
+///
+/// The computational core of [`HyperLogLog::count`], for register arrays
+/// read from elsewhere: `registers` must hold `2^precision` values of at
+/// most `65 - precision`. No calibration is applied.
+pub fn estimate_from_registers(precision: u8, registers: &[u8]) -> Result<u64, HllError> {
+    if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+        return Err(HllError::InvalidPrecision(precision));
+    }
+
+    let m = 1usize << precision;
+    if registers.len() != m {
+        return Err(HllError::InvalidInput(format!(
+            "Expected {} registers for precision {}, found {}",
+            m,
+            precision,
+            registers.len()
+        )));
+    }
+
+    let max_register = 65 - precision;
+    if let Some(&val) = registers.iter().find(|&&val| val > max_register) {
+        return Err(HllError::InvalidInput(format!(
+            "Register value {} exceeds the maximum of {}",
+            val, max_register
+        )));
+    }
+
+    Ok(estimate_registers(registers, true).0 as u64)
+}
+
+/// Estimate cardinality along with the regime the estimator used
+///
+/// `registers.len()` must be a supported power of two. Linear counting is
+/// only used in the small range when `small_range_correction` is set.
+fn estimate_registers(registers: &[u8], small_range_correction: bool) -> (f64, EstimateQuality) {
+    estimate_from_stats(registers.len(), RegisterStats::of(registers), small_range_correction)
+}
+
+/// [`estimate_registers`] from the totals of `m` registers
+fn estimate_from_stats(m: usize, stats: RegisterStats, small_range_correction: bool) -> (f64, EstimateQuality) {
+    let zeros = stats.zeros;
+
+    // Nothing added yet: exactly zero, rather than whatever the float math
+    // below rounds to
+    if small_range_correction && zeros == m {
+        return (0.0, EstimateQuality::SmallRangeCorrected);
+    }
+
+    let raw_estimate = raw_estimate(m, stats.sum());
+    let m = m as f64;
+
+    // Small range correction: linear counting while some registers are empty
+    if small_range_correction && zeros != 0 && raw_estimate <= 2.5 * m {
+        let estimate = m * (m / zeros as f64).ln();
+        return (estimate, EstimateQuality::SmallRangeCorrected);
+    }
+
+    // No large-range correction: the classic one models collisions of a
+    // 32-bit hash, and with 64-bit hashes they stay negligible far beyond
+    // any cardinality a sketch can count (HyperLogLog++ drops it too)
+    (raw_estimate, EstimateQuality::Good)
+}
+
+/// HyperLogLog++ estimate: the raw estimate minus its empirical bias, or
+/// linear counting while that is below the precision's threshold
+///
+/// Like [`estimate_registers`] there is no large-range correction.
+fn estimate_registers_hllpp(registers: &[u8]) -> f64 {
+    let m = registers.len() as f64;
+    let table = (registers.len().trailing_zeros() - MIN_PRECISION as u32) as usize;
+
+    let raw_estimate = raw_estimate_registers(registers);
+    let corrected = if raw_estimate <= 5.0 * m {
+        raw_estimate - estimate_bias(table, raw_estimate)
+    } else {
+        raw_estimate
+    };
+
+    let zeros = registers.iter().filter(|&&x| x == 0).count();
+    if zeros != 0 {
+        let linear = m * (m / zeros as f64).ln();
+        if linear <= LINEAR_COUNTING_THRESHOLD[table] {
+            return linear;
+        }
+    }
+
+    corrected
+}
+
+/// LogLog-Beta estimate `alpha * m * (m - z) / (beta(z) + sum(2^-register))`
+///
+/// `z` is the number of zero registers. One formula covers the whole range:
+/// the fitted `beta(z)` stands in for both linear counting and the bias
+/// correction, and vanishes once no register is zero.
+fn estimate_registers_beta(registers: &[u8]) -> f64 {
+    let m = registers.len() as f64;
+    let table = (registers.len().trailing_zeros() - MIN_PRECISION as u32) as usize;
+
+    let zeros = registers.iter().filter(|&&x| x == 0).count() as f64;
+    let zl = zeros.ln_1p();
+    let [b0, rest @ ..] = BETA_COEFFICIENTS[table];
+    let beta = b0 * zeros + rest.iter().rev().fold(0.0, |acc, &b| (acc + b) * zl);
+
+    alpha_m(registers.len()) * m * (m - zeros) / (beta + register_sum(registers))
+}
+
+/// Bias of `raw_estimate`, averaged over the [`BIAS_NEIGHBORS`] closest
+/// entries of the bias tables at index `table`
+fn estimate_bias(table: usize, raw_estimate: f64) -> f64 {
+    let raw_data = RAW_ESTIMATE_DATA[table];
+    let bias_data = BIAS_DATA[table];
+
+    // Grow a window around the insertion point towards the closer side
+    let mut start = raw_data.partition_point(|&x| x < raw_estimate);
+    let mut end = start;
+    while end - start < BIAS_NEIGHBORS {
+        let take_left = match (start.checked_sub(1), raw_data.get(end)) {
+            (Some(left), Some(&right)) => raw_estimate - raw_data[left] <= right - raw_estimate,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if take_left {
+            start -= 1;
+        } else {
+            end += 1;
+        }
+    }
+
+    bias_data[start..end].iter().sum::<f64>() / BIAS_NEIGHBORS as f64
+}
+
+/// Uncorrected HyperLogLog estimate `alpha * m^2 / sum(2^-register)`
+fn raw_estimate_registers(registers: &[u8]) -> f64 {
+    raw_estimate(registers.len(), register_sum(registers))
+}
+
+/// `alpha * m^2 / sum` for `m` registers whose `2^-register` terms add up
+/// to `sum`
+fn raw_estimate(m: usize, sum: f64) -> f64 {
+    alpha_m(m) * m as f64 * m as f64 / sum
+}
+
+/// Harmonic sum of `2^-register` over all registers
+///
+/// Summed exactly in fixed point, see [`RegisterStats`]: at high precision
+/// a plain float sum drops the tiny terms of large registers once the
+/// running total has grown.
+fn register_sum(registers: &[u8]) -> f64 {
+    RegisterStats::of(registers).sum()
+}
+
+/// Fractional bits of the fixed-point register sum; `2^-register` is exact
+/// for every register value a layout allows (at most 61)
+const SUM_FRACTION_BITS: u32 = 64;
+
+/// `2^-val` in fixed point with [`SUM_FRACTION_BITS`] fractional bits
+fn scaled_term(val: u8) -> u128 {
+    (1u128 << SUM_FRACTION_BITS).checked_shr(u32::from(val)).unwrap_or(0)
+}
+
+/// Running totals of a sketch's registers: how many are zero, and the sum
+/// of `2^-register` in fixed point
+///
+/// Every term is a power of two, so the fixed-point sum is exact and can be
+/// updated one register at a time without drifting; 2^16 registers of at
+/// most `2^64` each fit easily in a `u128`. The sketch keeps these in step
+/// with every register write, so estimating never rescans the registers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct RegisterStats {
+    zeros: usize,
+    scaled_sum: u128,
+}
+
+impl RegisterStats {
+    /// Totals of `m` zero registers
+    fn empty(m: usize) -> Self {
+        Self {
+            zeros: m,
+            scaled_sum: m as u128 * scaled_term(0),
+        }
+    }
+
+    /// Totals of `registers`, from scratch
+    fn of(registers: &[u8]) -> Self {
+        Self {
+            zeros: registers.iter().filter(|&&val| val == 0).count(),
+            scaled_sum: registers.iter().map(|&val| scaled_term(val)).sum(),
+        }
+    }
+
+    /// Account for one register changing from `old` to `new`
+    fn replace(&mut self, old: u8, new: u8) {
+        self.zeros -= usize::from(old == 0);
+        self.zeros += usize::from(new == 0);
+        self.scaled_sum = self.scaled_sum - scaled_term(old) + scaled_term(new);
+    }
+
+    /// Sum of `2^-register`, rounded once to the nearest float
+    fn sum(&self) -> f64 {
+        self.scaled_sum as f64 / (SUM_FRACTION_BITS as f64).exp2()
+    }
+}
+
+/// Alpha constant for `m` registers
+///
+/// Small precisions use [`SMALL_PRECISION_ALPHA`]; larger ones the
+/// closed-form approximation, which is accurate to 1e-5 from p=7 on.
+fn alpha_m(m: usize) -> f64 {
+    let precision = m.trailing_zeros() as usize;
+    match precision
+        .checked_sub(MIN_PRECISION as usize)
+        .and_then(|i| SMALL_PRECISION_ALPHA.get(i))
+    {
+        Some(&alpha) => alpha,
+        None => 0.7213 / (1.0 + 1.079 / m as f64),
+    }
+}
+
+/// Theoretical standard error of a HyperLogLog with the given precision
+fn standard_error(precision: u8) -> f64 {
+    1.04 / f64::from(precision).exp2().sqrt()
+}
+
+/// Smallest supported precision whose standard error is at most `max_error`
+fn min_precision_for_error(max_error: f64) -> Option<u8> {
+    (MIN_PRECISION..=MAX_PRECISION).find(|&p| standard_error(p) <= max_error)
+}
+
+/// Which regime produced a cardinality estimate, and so how far to trust it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EstimateQuality {
+    /// Raw estimate in the range where HyperLogLog is most accurate
+    Good,
+    /// Linear counting was used because many registers are still empty
+    SmallRangeCorrected,
+    /// Registers are at their ceiling; increase the precision
+    Saturated,
+}
+
+/// String normalization applied by [`HyperLogLog::add_str`]
+#[derive(Clone)]
+struct Normalizer(Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl fmt::Debug for Normalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Normalizer")
+    }
+}
+
+/// Result of the last [`HyperLogLog::count`], kept until the registers or
+/// calibration change
+///
+/// An atomic rather than a `Cell` so sketches stay `Sync`. Concurrent
+/// readers of an unchanged sketch compute the same value, so relaxed
+/// ordering is enough.
+struct CachedCount(AtomicU64);
+
+impl CachedCount {
+    /// Stored in place of a count while none is cached; a count of exactly
+    /// `u64::MAX` is simply recomputed every time
+    const EMPTY: u64 = u64::MAX;
+
+    fn get(&self) -> Option<u64> {
+        Some(self.0.load(Ordering::Relaxed)).filter(|&count| count != Self::EMPTY)
+    }
+
+    fn set(&self, count: u64) {
+        self.0.store(count, Ordering::Relaxed);
+    }
+
+    fn invalidate(&mut self) {
+        *self.0.get_mut() = Self::EMPTY;
+    }
+}
+
+impl Default for CachedCount {
+    fn default() -> Self {
+        Self(AtomicU64::new(Self::EMPTY))
+    }
+}
+
+impl Clone for CachedCount {
+    fn clone(&self) -> Self {
+        Self(AtomicU64::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+impl fmt::Debug for CachedCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+/// Serialized form of [`HyperLogLog`], validated before use
+///
+/// Also serialized directly, with every field present, for formats such as
+/// bincode that cannot skip fields.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct HyperLogLogRepr {
+    /// Absent in data written before the representation was versioned
+    #[serde(default = "serde_format_v1")]
+    version: u8,
+    precision: u8,
+    /// Redundant with the precision and no longer written; checked against
+    /// it when present
+    #[serde(default)]
+    m: Option<usize>,
+    #[serde(with = "register_bytes")]
     registers: Vec<u8>,
+    #[serde(default)]
+    sparse: Option<Vec<(u32, u8)>>,
+    #[serde(default)]
+    packed: Option<Vec<u8>>,
+    /// Absent in data written before custom layouts existed
+    #[serde(default)]
+    rank_bits: Option<u8>,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    hasher: HasherKind,
+}
+
+#[cfg(feature = "bincode")]
+impl From<&HyperLogLog> for HyperLogLogRepr {
+    fn from(hll: &HyperLogLog) -> Self {
+        HyperLogLogRepr {
+            version: SERDE_FORMAT_VERSION,
+            precision: hll.precision,
+            m: None,
+            registers: hll.registers.clone(),
+            sparse: hll.sparse.clone(),
+            packed: hll.packed.clone(),
+            rank_bits: Some(hll.rank_bits),
+            seed: hll.seed,
+            hasher: hll.hasher,
+        }
+    }
+}
+
+/// Serde encoding of the register array: a base64 string in human-readable
+/// formats such as JSON, raw bytes in binary ones
+///
+/// Both also accept the array of numbers written before, so older data
+/// keeps loading. bincode encodes raw bytes exactly like that array.
+mod register_bytes {
+    use super::MAX_PRECISION;
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use serde::de::{self, Deserializer, SeqAccess, Visitor};
+    use serde::Serializer;
+    use std::fmt;
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&BASE64.encode(bytes))
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(RegisterBytesVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(RegisterBytesVisitor)
+        }
+    }
+
+    struct RegisterBytesVisitor;
+
+    impl<'de> Visitor<'de> for RegisterBytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("registers as base64, bytes or an array of numbers")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+            let mut bytes = BASE64
+                .decode(v)
+                .map_err(|e| E::custom(format!("invalid base64 registers: {}", e)))?;
+            // The decoder over-allocates, and the capacity is reported by
+            // `memory_bytes`
+            bytes.shrink_to_fit();
+            Ok(bytes)
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            // The hint comes from the input, so don't trust it with a huge allocation
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1 << MAX_PRECISION));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+
+}
+
+/// Serializes borrowed registers through [`register_bytes`]
+struct RegisterBytes<'a>(&'a [u8]);
+
+impl Serialize for RegisterBytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        register_bytes::serialize(self.0, serializer)
+    }
+}
+
+fn serde_format_v1() -> u8 {
+    1
+}
+
+impl TryFrom<HyperLogLogRepr> for HyperLogLog {
+    type Error = HllError;
+
+    fn try_from(repr: HyperLogLogRepr) -> Result<Self, Self::Error> {
+        if repr.version != SERDE_FORMAT_VERSION {
+            return Err(HllError::Storage(format!(
+                "Unsupported serialization format version {} (expected {})",
+                repr.version, SERDE_FORMAT_VERSION
+            )));
+        }
+
+        if let Some(m) = repr.m {
+            if 1usize.checked_shl(u32::from(repr.precision)) != Some(m) {
+                return Err(HllError::Storage(format!(
+                    "Precision {} does not match m={}",
+                    repr.precision, m
+                )));
+            }
+        }
+
+        let rank_bits = repr.rank_bits.unwrap_or(64u8.saturating_sub(repr.precision));
+
+        if let Some(pairs) = repr.sparse {
+            if !repr.registers.is_empty() {
+                return Err(HllError::Storage(
+                    "Sparse HyperLogLog cannot also have dense registers".to_string(),
+                ));
+            }
+
+            let mut hll = HyperLogLog::from_sparse_parts(repr.precision, rank_bits, pairs)?;
+            hll.seed = repr.seed;
+            hll.hasher = repr.hasher;
+            return Ok(hll);
+        }
+
+        if let Some(packed) = repr.packed {
+            if !(MIN_PRECISION..=MAX_PRECISION).contains(&repr.precision) {
+                return Err(HllError::InvalidPrecision(repr.precision));
+            }
+
+            let m = 1usize << repr.precision;
+            if !repr.registers.is_empty() || packed.len() != packed_len(m, PACKED_WIDTH) {
+                return Err(HllError::Storage(format!(
+                    "Expected {} bytes of packed registers for precision {}, found {}",
+                    packed_len(m, PACKED_WIDTH),
+                    repr.precision,
+                    packed.len()
+                )));
+            }
+
+            let registers = unpack_registers(&packed, PACKED_WIDTH, m);
+            let mut hll = HyperLogLog::from_parts(repr.precision, rank_bits, registers)?;
+            hll.pack();
+            hll.seed = repr.seed;
+            hll.hasher = repr.hasher;
+            return Ok(hll);
+        }
+
+        // Also checks the register count against the precision
+        let mut hll = HyperLogLog::from_parts(repr.precision, rank_bits, repr.registers)?;
+        hll.seed = repr.seed;
+        hll.hasher = repr.hasher;
+        Ok(hll)
+    }
 }
 
-impl HyperLogLog {
-    /// Create a new HyperLogLog with given precision
-    /// Precision must be between 4 and 16
-    pub fn new(precision: u8) -> Result<Self, crate::error::HllError> {
-        if !(4..=16).contains(&precision) {
-            return Err(crate::error::HllError::InvalidPrecision(precision));
-        }
+impl HyperLogLog {
+    /// Create a new HyperLogLog with given precision
+    /// Precision must be between 4 and 16
+    pub fn new(precision: u8) -> Result<Self, HllError> {
+        Self::with_layout(precision, 64u8.saturating_sub(precision))
+    }
+
+    /// Create a HyperLogLog at the smallest precision whose standard error
+    /// (`1.04 / sqrt(m)`) is at most `max_error`
+    ///
+    /// Targets looser than precision 4 delivers get precision 4. Unlike
+    /// [`HyperLogLog::precision_for_error`], a target even precision 16
+    /// cannot meet is an error rather than clamped.
+    ///
+    /// ```
+    /// use hyperloglog::HyperLogLog;
+    ///
+    /// let hll = HyperLogLog::for_error_rate(0.01).unwrap();
+    /// assert_eq!(hll.precision(), 14);
+    /// ```
+    pub fn for_error_rate(max_error: f64) -> Result<Self, HllError> {
+        let precision = min_precision_for_error(max_error).ok_or_else(|| {
+            HllError::InvalidInput(format!(
+                "No precision reaches an error of {}; the smallest is {:.4} at precision {}",
+                max_error,
+                standard_error(MAX_PRECISION),
+                MAX_PRECISION
+            ))
+        })?;
+        Self::new(precision)
+    }
+
+    /// Start configuring a HyperLogLog, see [`HyperLogLogBuilder`]
+    pub fn builder() -> HyperLogLogBuilder {
+        HyperLogLogBuilder::default()
+    }
+
+    /// Create a HyperLogLog that starts in the sparse representation
+    ///
+    /// Only nonzero registers are kept, as `(index, value)` pairs sorted by
+    /// index, until they would take more memory than the dense register
+    /// array; the sketch then converts itself. Suits large numbers of
+    /// sketches that mostly stay small, such as per-tenant counters at high
+    /// precision. Counting, merging and serialization give the same results
+    /// as for [`HyperLogLog::new`].
+    pub fn new_sparse(precision: u8) -> Result<Self, HllError> {
+        let mut hll = Self::new(precision)?;
+        hll.registers = Vec::new();
+        hll.sparse = Some(Vec::new());
+        Ok(hll)
+    }
+
+    /// Create a HyperLogLog whose registers are bit-packed at 6 bits each
+    ///
+    /// Takes 25% less memory than [`HyperLogLog::new`] (12KB instead of 16KB
+    /// at precision 14) for slightly slower updates, and gives the same
+    /// counts and merges. JSON keeps the packed form; the binary format is
+    /// packed anyway but [`HyperLogLog::from_bytes`] returns a dense sketch.
+    pub fn new_packed(precision: u8) -> Result<Self, HllError> {
+        let mut hll = Self::new(precision)?;
+        hll.pack();
+        Ok(hll)
+    }
+
+    /// Create a HyperLogLog that hashes elements with the given seed
+    ///
+    /// Sketches only merge with sketches built with the same seed, which
+    /// catches accidental mixing of independent pipelines. [`HyperLogLog::new`]
+    /// uses seed 0.
+    pub fn with_seed(precision: u8, seed: u64) -> Result<Self, HllError> {
+        let mut hll = Self::new(precision)?;
+        hll.seed = seed;
+        Ok(hll)
+    }
+
+    /// Create a HyperLogLog that hashes elements with `hasher` instead of
+    /// xxHash64
+    ///
+    /// [`HasherKind::SipHash13`] resists hash flooding: with a secret seed,
+    /// nobody can choose elements that land in particular registers. The
+    /// kind is serialized with the sketch, and sketches with different kinds
+    /// refuse to merge.
+    pub fn with_hasher(precision: u8, hasher: HasherKind) -> Result<Self, HllError> {
+        let mut hll = Self::new(precision)?;
+        hll.hasher = hasher;
+        Ok(hll)
+    }
+
+    /// Create a HyperLogLog with an explicit split of the 64-bit hash
+    ///
+    /// The first `index_bits` bits select the register (this is the
+    /// precision) and the following `rank_bits` bits are scanned for leading
+    /// zeros. [`HyperLogLog::new`] uses every remaining bit for the rank.
+    ///
+    /// This is an expert-only knob for accuracy experiments: sketches only
+    /// merge with sketches that use the same split.
+    pub fn with_layout(index_bits: u8, rank_bits: u8) -> Result<Self, HllError> {
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&index_bits) {
+            return Err(HllError::InvalidPrecision(index_bits));
+        }
+
+        if rank_bits == 0 || rank_bits > 64 - index_bits {
+            return Err(HllError::InvalidLayout(format!(
+                "{} index bits leave room for 1..={} rank bits, got {}",
+                index_bits,
+                64 - index_bits,
+                rank_bits
+            )));
+        }
+
+        let m = 1 << index_bits;
+        Ok(HyperLogLog {
+            precision: index_bits,
+            m,
+            registers: vec![0; m],
+            sparse: None,
+            packed: None,
+            rank_bits,
+            seed: 0,
+            hasher: HasherKind::default(),
+            normalizer: None,
+            calibration: 1.0,
+            max_element_len: None,
+            stats: RegisterStats::empty(m),
+            cached_count: CachedCount::default(),
+        })
+    }
+
+    /// Normalize string elements with `normalizer` before they are hashed
+    ///
+    /// Lets [`HyperLogLog::add_str`] apply identity rules such as trimming
+    /// and case folding, so `"  Foo@X.com "` and `"foo@x.com"` count once.
+    /// Only `add_str` is affected; [`HyperLogLog::add`] hashes elements as-is.
+    ///
+    /// The normalizer is not serialized. Merging assumes both sketches were
+    /// built with the same normalization, and a loaded sketch needs its
+    /// normalizer set again before more strings are added.
+    pub fn with_normalizer<F>(mut self, normalizer: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.normalizer = Some(Normalizer(Arc::new(normalizer)));
+        self
+    }
+
+    /// Create a HyperLogLog whose register `i` is initialized to `f(i)`
+    ///
+    /// Intended for testing and estimator research, e.g. starting from a
+    /// nonzero baseline. Every value must be at most the largest value `add`
+    /// can produce for this precision (`65 - precision`).
+    pub fn with_registers_fn(precision: u8, f: impl Fn(usize) -> u8) -> Result<Self, HllError> {
+        let empty = HyperLogLog::new(precision)?;
+        let registers = (0..empty.m).map(f).collect();
+        HyperLogLog::from_parts(precision, empty.rank_bits, registers)
+    }
+
+    /// Create a HyperLogLog with the default layout from its register array
+    ///
+    /// `registers` must hold `2^precision` values of at most `65 - precision`.
+    /// For golden tests and for importing sketches computed elsewhere, e.g.
+    /// by another HyperLogLog implementation with the same layout.
+    pub fn from_registers(precision: u8, registers: Vec<u8>) -> Result<Self, HllError> {
+        let empty = HyperLogLog::new(precision)?;
+        HyperLogLog::from_parts(precision, empty.rank_bits, registers)
+    }
+
+    /// Build a HyperLogLog from decoded parts, validating them
+    fn from_parts(precision: u8, rank_bits: u8, registers: Vec<u8>) -> Result<Self, HllError> {
+        let mut hll = HyperLogLog::with_layout(precision, rank_bits)?;
+
+        if registers.len() != hll.m {
+            return Err(HllError::Storage(format!(
+                "Expected {} registers for precision {}, found {}",
+                hll.m,
+                precision,
+                registers.len()
+            )));
+        }
+
+        let max_register = rank_bits + 1;
+        if let Some(&val) = registers.iter().find(|&&val| val > max_register) {
+            return Err(HllError::Storage(format!(
+                "Register value {} exceeds the maximum of {}",
+                val, max_register
+            )));
+        }
+
+        hll.registers = registers;
+        hll.recompute_stats();
+        Ok(hll)
+    }
+
+    /// Build a sparse HyperLogLog from decoded `(index, value)` pairs,
+    /// validating them; converts to dense if there are too many
+    fn from_sparse_parts(precision: u8, rank_bits: u8, pairs: Vec<(u32, u8)>) -> Result<Self, HllError> {
+        let mut hll = HyperLogLog::with_layout(precision, rank_bits)?;
+
+        if pairs.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(HllError::Storage("Sparse registers are not sorted by index".to_string()));
+        }
+
+        let max_register = rank_bits + 1;
+        for &(idx, val) in &pairs {
+            if idx as usize >= hll.m || val == 0 || val > max_register {
+                return Err(HllError::Storage(format!(
+                    "Invalid sparse register {} = {} for precision {}",
+                    idx, val, precision
+                )));
+            }
+        }
+
+        hll.registers = Vec::new();
+        hll.sparse = Some(pairs);
+        hll.recompute_stats();
+        if hll.sparse.as_ref().is_some_and(|pairs| pairs.len() > hll.sparse_limit()) {
+            hll.densify();
+        }
+        Ok(hll)
+    }
+
+    /// Whether the sketch still uses the sparse representation
+    pub fn is_sparse(&self) -> bool {
+        self.sparse.is_some()
+    }
+
+    /// Whether the registers are bit-packed, see [`HyperLogLog::new_packed`]
+    pub fn is_packed(&self) -> bool {
+        self.packed.is_some()
+    }
+
+    /// Convert to the dense register array; a no-op if already dense
+    pub fn densify(&mut self) {
+        if self.sparse.is_some() || self.packed.is_some() {
+            self.registers = self.dense_registers().into_owned();
+            self.sparse = None;
+            self.packed = None;
+        }
+    }
+
+    /// Replace every register with `registers`, `m` values within the
+    /// layout's range, switching to the dense representation
+    pub(crate) fn replace_registers(&mut self, registers: Vec<u8>) {
+        debug_assert_eq!(registers.len(), self.m);
+        self.sparse = None;
+        self.packed = None;
+        self.registers = registers;
+        self.recompute_stats();
+        self.cached_count.invalidate();
+    }
+
+    /// Switch to the packed representation
+    fn pack(&mut self) {
+        self.packed = Some(pack_registers(&self.dense_registers(), PACKED_WIDTH));
+        self.registers = Vec::new();
+        self.sparse = None;
+    }
+
+    /// Most sparse pairs a sketch holds before they outgrow the dense array
+    fn sparse_limit(&self) -> usize {
+        self.m / SPARSE_ENTRY_BYTES
+    }
+
+    /// The register array, built from the pairs or packed bits if needed
+    fn dense_registers(&self) -> Cow<'_, [u8]> {
+        if let Some(pairs) = &self.sparse {
+            let mut registers = vec![0; self.m];
+            for &(idx, val) in pairs {
+                registers[idx as usize] = val;
+            }
+            return Cow::Owned(registers);
+        }
+
+        match &self.packed {
+            Some(packed) => Cow::Owned(unpack_registers(packed, PACKED_WIDTH, self.m)),
+            None => Cow::Borrowed(&self.registers),
+        }
+    }
+
+    /// Value of register `idx`, in any representation
+    fn get_register(&self, idx: usize) -> u8 {
+        if let Some(pairs) = &self.sparse {
+            return match pairs.binary_search_by_key(&(idx as u32), |&(i, _)| i) {
+                Ok(pos) => pairs[pos].1,
+                Err(_) => 0,
+            };
+        }
+
+        match &self.packed {
+            Some(packed) => get_packed(packed, PACKED_WIDTH, idx),
+            None => self.registers[idx],
+        }
+    }
+
+    /// Overwrite register `idx`, in any representation
+    ///
+    /// A sparse sketch converts to dense once it outgrows the dense array.
+    /// The caller keeps [`RegisterStats`] in step.
+    fn set_register(&mut self, idx: usize, val: u8) {
+        self.cached_count.invalidate();
+        let limit = self.sparse_limit();
+        if let Some(pairs) = &mut self.sparse {
+            match pairs.binary_search_by_key(&(idx as u32), |&(i, _)| i) {
+                Ok(pos) => pairs[pos].1 = val,
+                Err(pos) => {
+                    pairs.insert(pos, (idx as u32, val));
+                    if pairs.len() > limit {
+                        self.densify();
+                    }
+                }
+            }
+            return;
+        }
+
+        match &mut self.packed {
+            Some(packed) => set_packed(packed, PACKED_WIDTH, idx, val),
+            None => self.registers[idx] = val,
+        }
+    }
+
+    /// Raise register `idx` to `val` if it is lower; true if it was
+    fn update_register(&mut self, idx: usize, val: u8) -> bool {
+        let old = self.get_register(idx);
+        if val > old {
+            self.stats.replace(old, val);
+            self.set_register(idx, val);
+        }
+        val > old
+    }
+
+    /// Rebuild [`RegisterStats`] after registers were replaced wholesale
+    fn recompute_stats(&mut self) {
+        self.stats = RegisterStats::of(&self.dense_registers());
+    }
+
+    /// Largest register value
+    fn max_register(&self) -> u8 {
+        match (&self.sparse, &self.packed) {
+            (Some(pairs), _) => pairs.iter().map(|&(_, val)| val).max(),
+            (None, Some(_)) => self.dense_registers().iter().copied().max(),
+            (None, None) => self.registers.iter().copied().max(),
+        }
+        .unwrap_or(0)
+    }
+
+    /// Encode into the compact binary format
+    ///
+    /// The header is the `HLL` magic, a format version byte, the precision,
+    /// the rank bits, the register width, the little-endian hash seed and the
+    /// hasher kind, followed by the registers bit-packed at that width. The
+    /// width is the narrowest of 4, 6 or 8 bits that holds the largest
+    /// register, so low-cardinality sketches take half the space. Sparse
+    /// sketches use width 0 and list each nonzero register as a
+    /// little-endian `u32` index followed by its value.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let width = match (&self.sparse, &self.packed) {
+            (Some(_), _) => SPARSE_WIDTH,
+            (None, Some(_)) => PACKED_WIDTH,
+            (None, None) => REGISTER_WIDTHS
+                .into_iter()
+                .find(|&width| u16::from(self.max_register()) < 1 << width)
+                .unwrap_or(8),
+        };
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + packed_len(self.m, width));
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.push(self.precision);
+        bytes.push(self.rank_bits);
+        bytes.push(width);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.push(self.hasher.to_byte());
+        match (&self.sparse, &self.packed) {
+            (Some(pairs), _) => {
+                for &(idx, val) in pairs {
+                    bytes.extend_from_slice(&idx.to_le_bytes());
+                    bytes.push(val);
+                }
+            }
+            (None, Some(packed)) => bytes.extend_from_slice(packed),
+            (None, None) => bytes.extend(pack_registers(&self.registers, width)),
+        }
+        bytes
+    }
+
+    /// Check the magic, version and length of a binary header; the format
+    /// version and the header length it implies
+    fn binary_header(bytes: &[u8]) -> Result<(u8, usize), HllError> {
+        if bytes.len() <= MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(HllError::Storage("Not a binary HyperLogLog".to_string()));
+        }
+
+        let version = bytes[MAGIC.len()];
+        let header_len = match version {
+            1 => V1_HEADER_LEN,
+            2 => V2_HEADER_LEN,
+            3 => V3_HEADER_LEN,
+            FORMAT_VERSION => HEADER_LEN,
+            _ => {
+                return Err(HllError::Storage(format!(
+                    "Unsupported format version {}",
+                    version
+                )))
+            }
+        };
+
+        if bytes.len() < header_len {
+            return Err(HllError::Storage("Truncated binary HyperLogLog".to_string()));
+        }
+        Ok((version, header_len))
+    }
+
+    /// Precision of binary data from [`HyperLogLog::to_bytes`], read from the
+    /// header without decoding the registers
+    #[cfg(feature = "file-storage")]
+    pub(crate) fn peek_precision(bytes: &[u8]) -> Result<u8, HllError> {
+        Self::binary_header(bytes)?;
+        let precision = bytes[MAGIC.len() + 1];
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+            return Err(HllError::InvalidPrecision(precision));
+        }
+        Ok(precision)
+    }
+
+    /// Decode the binary format produced by [`HyperLogLog::to_bytes`]
+    ///
+    /// Older versions are still accepted: version 1 stored one byte per
+    /// register, neither version 1 nor 2 stored a seed, which reads as 0, and
+    /// versions before 4 stored no hasher kind, which reads as xxHash64.
+    /// Data written by a newer, incompatible format version is rejected
+    /// rather than misread.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HllError> {
+        let (version, header_len) = Self::binary_header(bytes)?;
+        let precision = bytes[MAGIC.len() + 1];
+        let rank_bits = bytes[MAGIC.len() + 2];
+        let packed = &bytes[header_len..];
+
+        if version == 1 {
+            return HyperLogLog::from_parts(precision, rank_bits, packed.to_vec());
+        }
+
+        let width = bytes[MAGIC.len() + 3];
+        let sparse = version >= 3 && width == SPARSE_WIDTH;
+        if !sparse && !REGISTER_WIDTHS.contains(&width) {
+            return Err(HllError::Storage(format!("Unsupported register width {}", width)));
+        }
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+            return Err(HllError::InvalidPrecision(precision));
+        }
+
+        let mut seed = 0;
+        if version >= 3 {
+            let seed_bytes = &bytes[MAGIC.len() + 4..V3_HEADER_LEN];
+            seed = u64::from_le_bytes(seed_bytes.try_into().expect("8 seed bytes"));
+        }
+
+        let mut hasher = HasherKind::default();
+        if version >= 4 {
+            let tag = bytes[V3_HEADER_LEN];
+            hasher = HasherKind::from_byte(tag)
+                .ok_or_else(|| HllError::Storage(format!("Unknown hasher kind {}", tag)))?;
+        }
+
+        if sparse {
+            if !packed.len().is_multiple_of(SPARSE_ENCODED_LEN) {
+                return Err(HllError::Storage("Truncated sparse registers".to_string()));
+            }
+
+            let pairs = packed
+                .chunks_exact(SPARSE_ENCODED_LEN)
+                .map(|chunk| (u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]), chunk[4]))
+                .collect();
+            let mut hll = HyperLogLog::from_sparse_parts(precision, rank_bits, pairs)?;
+            hll.seed = seed;
+            hll.hasher = hasher;
+            return Ok(hll);
+        }
+
+        let m = 1usize << precision;
+        if packed.len() != packed_len(m, width) {
+            return Err(HllError::Storage(format!(
+                "Expected {} bytes of {}-bit registers for precision {}, found {}",
+                packed_len(m, width),
+                width,
+                precision,
+                packed.len()
+            )));
+        }
+
+        let mut hll = HyperLogLog::from_parts(precision, rank_bits, unpack_registers(packed, width, m))?;
+        hll.seed = seed;
+        hll.hasher = hasher;
+        Ok(hll)
+    }
+
+    /// Encode in the dense format of Redis, readable by `PFCOUNT` once
+    /// written to a key with `SET`
+    ///
+    /// Redis sketches are fixed at precision 14 with 50 rank bits, the
+    /// default layout for that precision; other sketches are rejected. The
+    /// cached cardinality is marked stale so Redis recomputes it. Redis
+    /// hashes elements with MurmurHash64A rather than xxHash64, so counts
+    /// carry over but the same element added on both sides counts twice
+    /// after a merge.
+    pub fn to_redis_dense(&self) -> Result<Vec<u8>, HllError> {
+        if self.precision != REDIS_PRECISION {
+            return Err(HllError::InvalidInput(format!(
+                "Redis HyperLogLogs have precision {}, not {}",
+                REDIS_PRECISION, self.precision
+            )));
+        }
+        if self.rank_bits != 64 - REDIS_PRECISION {
+            return Err(HllError::InvalidLayout(format!(
+                "Redis sketches use {} rank bits, not {}",
+                64 - REDIS_PRECISION,
+                self.rank_bits
+            )));
+        }
+
+        let mut bytes = Vec::with_capacity(REDIS_HEADER_LEN + packed_len(self.m, REDIS_REGISTER_WIDTH));
+        bytes.extend_from_slice(REDIS_MAGIC);
+        bytes.extend_from_slice(&[REDIS_DENSE, 0, 0, 0]);
+        // The most significant bit of the cached cardinality flags it stale
+        bytes.extend_from_slice(&(1u64 << 63).to_le_bytes());
+        bytes.extend(pack_registers(&self.dense_registers(), REDIS_REGISTER_WIDTH));
+        Ok(bytes)
+    }
+
+    /// Decode a Redis HyperLogLog string in the dense encoding, as returned
+    /// by `GET` on a key written with `PFADD`
+    ///
+    /// Redis keeps small sketches in a sparse encoding, which is rejected;
+    /// Redis converts a key to dense once it holds a few thousand elements.
+    /// The cached cardinality is ignored. See
+    /// [`HyperLogLog::to_redis_dense`] for the hashing caveat.
+    pub fn from_redis_dense(bytes: &[u8]) -> Result<Self, HllError> {
+        if bytes.len() < REDIS_HEADER_LEN || &bytes[..REDIS_MAGIC.len()] != REDIS_MAGIC {
+            return Err(HllError::Storage("Not a Redis HyperLogLog".to_string()));
+        }
+
+        match bytes[REDIS_MAGIC.len()] {
+            REDIS_DENSE => {}
+            REDIS_SPARSE => {
+                return Err(HllError::Storage(
+                    "Redis sparse encoding is not supported".to_string(),
+                ))
+            }
+            encoding => {
+                return Err(HllError::Storage(format!("Unknown Redis encoding {}", encoding)))
+            }
+        }
+
+        let m = 1usize << REDIS_PRECISION;
+        let packed = &bytes[REDIS_HEADER_LEN..];
+        if packed.len() != packed_len(m, REDIS_REGISTER_WIDTH) {
+            return Err(HllError::Storage(format!(
+                "Expected {} bytes of Redis registers, found {}",
+                packed_len(m, REDIS_REGISTER_WIDTH),
+                packed.len()
+            )));
+        }
+
+        HyperLogLog::from_registers(REDIS_PRECISION, unpack_registers(packed, REDIS_REGISTER_WIDTH, m))
+    }
+
+    /// Scale every estimate returned by [`HyperLogLog::count`] by `factor`
+    ///
+    /// The factor is meant to be learned offline from your own data, to
+    /// remove bias specific to a workload. The default of `1.0` leaves
+    /// `count()` unchanged. Like the normalizer, it is not serialized.
+    pub fn with_calibration(mut self, factor: f64) -> Self {
+        self.calibration = factor;
+        self.cached_count.invalidate();
+        self
+    }
+
+    /// Calibration factor applied by [`HyperLogLog::count`]
+    pub fn calibration(&self) -> f64 {
+        self.calibration
+    }
+
+    /// Make [`HyperLogLog::add_str`] and [`HyperLogLog::add_bytes`] skip
+    /// elements longer than `max_len` bytes
+    ///
+    /// A huge element usually means misuse, such as adding a whole document
+    /// instead of its ID. The limit is checked before normalization, is off
+    /// by default and, like the normalizer, is not serialized.
+    pub fn with_max_element_len(mut self, max_len: usize) -> Self {
+        self.max_element_len = Some(max_len);
+        self
+    }
+
+    /// Longest element [`HyperLogLog::add_str`] and
+    /// [`HyperLogLog::add_bytes`] accept, if limited
+    pub fn max_element_len(&self) -> Option<usize> {
+        self.max_element_len
+    }
+
+    /// Add an element to the HyperLogLog
+    ///
+    /// Returns true if a register changed, like the 1 Redis `PFADD` replies
+    /// with. False means the estimate is unchanged, though not that the
+    /// element was seen before.
+    pub fn add<T: Hash>(&mut self, element: &T) -> bool {
+        self.add_hash(self.hash_element(element))
+    }
+
+    /// Whether `element` may have been added with [`HyperLogLog::add`]
+    ///
+    /// True if the register the element maps to already holds at least the
+    /// rank the element would store there. An added element always answers
+    /// true, but so does any element whose rank is at most its register's,
+    /// and once registers fill up that is most of them: this is far weaker
+    /// than a Bloom filter of the same size and only useful as a cheap
+    /// pre-check. False means the element was definitely never added.
+    pub fn maybe_contains<T: Hash>(&self, element: &T) -> bool {
+        let (idx, rank) = self.index_and_rank(self.hash_element(element));
+        self.get_register(idx) >= rank
+    }
+
+    /// Add every item of `items`, with the same result as calling
+    /// [`HyperLogLog::add`] on each
+    ///
+    /// Dense sketches update their registers directly, skipping the
+    /// representation dispatch `add` goes through per element.
+    pub fn add_all<T, I>(&mut self, items: I)
+    where
+        T: Hash,
+        I: IntoIterator<Item = T>,
+    {
+        if self.sparse.is_some() || self.packed.is_some() {
+            for item in items {
+                self.add(&item);
+            }
+            return;
+        }
+
+        self.cached_count.invalidate();
+        for item in items {
+            let (idx, rank) = self.index_and_rank(self.hash_element(&item));
+            let register = &mut self.registers[idx];
+            if rank > *register {
+                self.stats.replace(*register, rank);
+                *register = rank;
+            }
+        }
+    }
+
+    /// Create a sketch at `precision` holding every item of `iter`
+    ///
+    /// A plain `FromIterator` impl would have to pick the precision itself,
+    /// so this takes it explicitly. Items are added with
+    /// [`HyperLogLog::add`].
+    pub fn from_iter_with_precision<T, I>(precision: u8, iter: I) -> Result<Self, HllError>
+    where
+        T: Hash,
+        I: IntoIterator<Item = T>,
+    {
+        let mut hll = Self::new(precision)?;
+        hll.extend(iter);
+        Ok(hll)
+    }
+
+    /// Add a raw byte string, hashed as-is with the sketch's hasher and seed
+    ///
+    /// Unlike [`HyperLogLog::add`], no `Hash` framing such as a length
+    /// prefix or terminator is mixed in, so any implementation of the same
+    /// hash function hashing the same bytes agrees. As a consequence
+    /// `add_bytes(b"foo")` and `add_str("foo")` generally update different
+    /// registers. Elements over the
+    /// [maximum length](HyperLogLog::with_max_element_len) are skipped.
+    /// Returns true if a register changed.
+    pub fn add_bytes(&mut self, bytes: &[u8]) -> bool {
+        self.hash_raw_bytes(bytes).is_some_and(|hash| self.add_hash(hash))
+    }
+
+    /// Hash [`HyperLogLog::add_bytes`] adds `bytes` by, or `None` if they
+    /// are over the length limit
+    pub(crate) fn hash_raw_bytes(&self, bytes: &[u8]) -> Option<u64> {
+        if self.max_element_len.is_some_and(|max_len| bytes.len() > max_len) {
+            return None;
+        }
+        Some(self.hasher.hash_bytes(self.seed, bytes))
+    }
+
+    /// Add an element by its precomputed 64-bit hash, bypassing the
+    /// sketch's hasher
+    ///
+    /// The top `precision` bits select the register and the rank is one
+    /// plus the leading zeros of the bits after them, as for every other
+    /// `add`. Lets callers bring their own hash function, or feed exact
+    /// hashes in tests; the hash should be uniformly distributed for the
+    /// estimate to hold. Returns true if a register changed.
+    pub fn add_hash(&mut self, hash: u64) -> bool {
+        let (idx, leading_zeros) = self.index_and_rank(hash);
+
+        // Store max leading zeros for this register
+        self.update_register(idx, leading_zeros)
+    }
+
+    /// Split a 64-bit element hash into its register index and rank
+    ///
+    /// The layout is validated on construction, but the shifts are checked
+    /// anyway so a corrupt one cannot overflow them, and the rank is clamped
+    /// to what a register can hold.
+    #[inline]
+    pub(crate) fn index_and_rank(&self, hash: u64) -> (usize, u8) {
+        // Use first 'precision' bits for register index
+        let idx = hash
+            .checked_shr(64u32.saturating_sub(u32::from(self.precision)))
+            .unwrap_or(0) as usize;
+
+        // Count leading zeros in the next 'rank_bits' bits + 1
+        let window = u64::MAX
+            .checked_shl(64u32.saturating_sub(u32::from(self.rank_bits)))
+            .unwrap_or(0);
+        let remaining = hash.checked_shl(u32::from(self.precision)).unwrap_or(0) & window;
+        let leading_zeros = if remaining == 0 {
+            // The whole window is zero: one past its width
+            self.rank_bits.saturating_add(1)
+        } else {
+            remaining.leading_zeros() as u8 + 1
+        };
+
+        (idx, leading_zeros.min(self.max_register_value()))
+    }
+
+    /// Largest value a register can store: one past the rank window, capped
+    /// at what the register width can represent
+    fn max_register_value(&self) -> u8 {
+        let width_max = match self.packed {
+            Some(_) => (1 << PACKED_WIDTH) - 1,
+            None => u8::MAX,
+        };
+        self.rank_bits.saturating_add(1).min(width_max)
+    }
+
+    /// Add a raw string element (for Redis compatibility)
+    ///
+    /// The element is passed through the normalizer first, if one is set.
+    /// Elements over the [maximum length](HyperLogLog::with_max_element_len)
+    /// are skipped. Returns true if a register changed.
+    pub fn add_str(&mut self, element: &str) -> bool {
+        self.hash_str(element).is_some_and(|hash| self.add_hash(hash))
+    }
+
+    /// Hash [`HyperLogLog::add_str`] adds `element` by, or `None` if it is
+    /// over the length limit
+    pub(crate) fn hash_str(&self, element: &str) -> Option<u64> {
+        if self.max_element_len.is_some_and(|max_len| element.len() > max_len) {
+            return None;
+        }
+
+        let normalized = self.normalizer.as_ref().map(|normalize| (normalize.0)(element));
+        Some(self.hash_element(&normalized.as_deref().unwrap_or(element)))
+    }
+
+    /// Add every line of `reader` via [`HyperLogLog::add_str`]
+    ///
+    /// Lines are read one at a time into a reused buffer, so the input is
+    /// never held in memory as a whole. The `\n` or `\r\n` terminator is
+    /// stripped and a final line without one still counts. Returns the
+    /// number of lines added; input that is not UTF-8 is an error.
+    pub fn add_reader(&mut self, mut reader: impl BufRead) -> io::Result<u64> {
+        let mut line = String::new();
+        let mut lines = 0;
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(lines);
+            }
+
+            let element = line.strip_suffix('\n').unwrap_or(&line);
+            let element = element.strip_suffix('\r').unwrap_or(element);
+            self.add_str(element);
+            lines += 1;
+        }
+    }
+
+    /// Reset every register to zero, keeping the precision, layout and the
+    /// register allocation
+    ///
+    /// Lets one sketch be reused across reporting windows.
+    pub fn clear(&mut self) {
+        self.cached_count.invalidate();
+        if let Some(pairs) = &mut self.sparse {
+            pairs.clear();
+        }
+        if let Some(packed) = &mut self.packed {
+            packed.fill(0);
+        }
+        self.registers.fill(0);
+        self.stats = RegisterStats::empty(self.m);
+    }
+
+    /// Whether no element was ever added, i.e. every register is zero
+    ///
+    /// Stops at the first nonzero register. Not the same as
+    /// `count() == 0`, which also depends on the estimator.
+    pub fn is_empty(&self) -> bool {
+        match (&self.sparse, &self.packed) {
+            (Some(pairs), _) => pairs.is_empty(),
+            (None, Some(packed)) => packed.iter().all(|&byte| byte == 0),
+            (None, None) => self.registers.iter().all(|&val| val == 0),
+        }
+    }
+
+    /// Estimate cardinality
+    ///
+    /// The estimate is scaled by the calibration factor, if one was set.
+    /// It is cached until the sketch next changes, so polling an unchanged
+    /// sketch is cheap.
+    pub fn count(&self) -> u64 {
+        if let Some(count) = self.cached_count.get() {
+            return count;
+        }
+
+        let count = self.count_calibrated(self.calibration);
+        self.cached_count.set(count);
+        count
+    }
+
+    /// Estimate cardinality with a confidence interval, as
+    /// `(lower, estimate, upper)`
+    ///
+    /// The bounds are `estimate * (1 ± z * relative_error())`, rounded
+    /// outwards, with the lower bound clamped at 0. Pass `z = 1.96` for a
+    /// 95% interval.
+    pub fn count_with_confidence(&self, z: f64) -> (u64, u64, u64) {
+        let estimate = self.count();
+        let margin = estimate as f64 * z.abs() * self.relative_error();
+
+        let lower = (estimate as f64 - margin).floor().max(0.0) as u64;
+        let upper = (estimate as f64 + margin).ceil() as u64;
+        (lower, estimate, upper)
+    }
+
+    /// Estimate cardinality scaled by an explicit calibration `factor`
+    ///
+    /// Ignores the stored calibration; `count_calibrated(1.0)` is the
+    /// uncalibrated estimate.
+    pub fn count_calibrated(&self, factor: f64) -> u64 {
+        (self.estimate().0 * factor) as u64
+    }
+
+    /// Estimate cardinality without the small-range linear counting correction
+    ///
+    /// Returns the raw `alpha * m^2 / sum` estimate even while registers are
+    /// still empty, scaled by the calibration factor like `count`. Below
+    /// about `2.5 * m` elements the raw estimate is strongly biased upwards
+    /// (an empty sketch reads as about `0.7 * m`), so prefer
+    /// [`HyperLogLog::count`] unless your pipeline needs a single estimator
+    /// across all ranges.
+    pub fn count_raw(&self) -> u64 {
+        (estimate_from_stats(self.m, self.stats, false).0 * self.calibration) as u64
+    }
+
+    /// Estimate cardinality with the HyperLogLog++ bias correction
+    ///
+    /// Below `5 * m` the raw estimate overshoots by an amount that depends
+    /// only on the precision, so an empirically measured bias (see Heule et
+    /// al., "HyperLogLog in Practice") is subtracted; linear counting is used
+    /// only while it is below a per-precision threshold. This removes the
+    /// error bump [`HyperLogLog::count`] shows between about `2.5 * m` and
+    /// `5 * m`. Scaled by the calibration factor like `count`.
+    pub fn count_hllpp(&self) -> u64 {
+        (estimate_registers_hllpp(&self.dense_registers()) * self.calibration) as u64
+    }
+
+    /// Estimate cardinality with the LogLog-Beta estimator
+    ///
+    /// Replaces the piecewise switch between linear counting and the raw
+    /// estimate in [`HyperLogLog::count`] with a single formula, using a
+    /// polynomial in the number of zero registers fitted per precision (Qin
+    /// et al., "LogLog-Beta and More", 2016). There is no jump at the
+    /// `2.5 * m` threshold and no bias table. It matches `count` below about
+    /// `m` and once every register is set, and is clearly more accurate in
+    /// between. Scaled by the calibration factor like `count`.
+    pub fn count_beta(&self) -> u64 {
+        (estimate_registers_beta(&self.dense_registers()) * self.calibration) as u64
+    }
+
+    /// Estimate the cardinality of this sketch plus `extra` elements
+    ///
+    /// Works on a copy of the registers, so `self` is left untouched. Useful
+    /// for "what-if" projections against a stored sketch.
+    pub fn count_with_extra<T: Hash>(&self, extra: impl IntoIterator<Item = T>) -> u64 {
+        let mut projected = self.clone();
+        for element in extra {
+            projected.add(&element);
+        }
+        projected.count()
+    }
+
+    /// Report how reliable the current [`HyperLogLog::count`] is
+    ///
+    /// `SmallRangeCorrected` means linear counting was used, which is accurate
+    /// but only while some registers are still empty. `Saturated` means the
+    /// sketch has outgrown its precision or register width and should be
+    /// rebuilt with a higher precision.
+    pub fn estimate_quality(&self) -> EstimateQuality {
+        if self.max_register() > self.rank_bits {
+            return EstimateQuality::Saturated;
+        }
+
+        self.estimate().1
+    }
+
+    /// How close the sketch is to [`EstimateQuality::Saturated`], from `0.0`
+    /// to `1.0`
+    ///
+    /// The highest register relative to the largest value the rank window
+    /// can record. Suited to alerting, e.g. on keys above `0.9`.
+    pub fn saturation(&self) -> f64 {
+        (self.max_register() as f64 / (self.rank_bits + 1) as f64).min(1.0)
+    }
+
+    /// Fraction of registers that are nonzero, from `0.0` (empty) to `1.0`
+    pub fn fill_ratio(&self) -> f64 {
+        self.nonzero_registers() as f64 / self.m as f64
+    }
+
+    /// Number of registers that are nonzero, at most
+    /// [`HyperLogLog::num_registers`]
+    pub fn nonzero_registers(&self) -> usize {
+        self.m - self.stats.zeros
+    }
+
+    /// How many registers hold each value: entry `v` counts the registers
+    /// equal to `v`
+    ///
+    /// Covers every value a register can store under the current layout,
+    /// so entries past the largest value present are zero; a register above
+    /// that range, which only corruption produces, extends the result. The
+    /// entries sum to [`HyperLogLog::num_registers`].
+    pub fn histogram(&self) -> Vec<u32> {
+        let mut histogram = vec![0u32; usize::from(self.max_register_value()) + 1];
+        let mut nonzero = 0;
+        for (_, val) in self.nonzero_registers_iter() {
+            let val = usize::from(val);
+            if val >= histogram.len() {
+                histogram.resize(val + 1, 0);
+            }
+            histogram[val] += 1;
+            nonzero += 1;
+        }
+        histogram[0] = (self.m - nonzero) as u32;
+        histogram
+    }
+
+    /// Precision this sketch should be rebuilt at
+    ///
+    /// The current precision while the estimate is trustworthy. Once
+    /// [`HyperLogLog::estimate_quality`] reports `Saturated`, the next
+    /// precision up (capped at the maximum); re-check after rebuilding.
+    pub fn recommended_precision(&self) -> u8 {
+        match self.estimate_quality() {
+            EstimateQuality::Saturated => (self.precision + 1).min(MAX_PRECISION),
+            _ => self.precision,
+        }
+    }
+
+    /// Heap memory allocated for the registers, in bytes
+    ///
+    /// `m` bytes for a dense sketch, the packed array for a packed one and
+    /// the `(index, value)` pairs for a sparse one, counting spare
+    /// capacity. The fixed-size struct itself
+    /// (`size_of::<HyperLogLog>()`) comes on top.
+    pub fn memory_bytes(&self) -> usize {
+        self.registers.capacity()
+            + self.sparse.as_ref().map_or(0, |pairs| pairs.capacity() * SPARSE_ENTRY_BYTES)
+            + self.packed.as_ref().map_or(0, Vec::capacity)
+    }
+
+    /// Headline numbers for lightweight APIs and dashboards
+    pub fn summary(&self) -> HllSummary {
+        HllSummary {
+            precision: self.precision,
+            count: self.count(),
+            fill_ratio: self.fill_ratio(),
+            memory_bytes: self.memory_bytes(),
+            quality: self.estimate_quality(),
+        }
+    }
+
+    /// Estimate cardinality along with the regime the estimator used
+    fn estimate(&self) -> (f64, EstimateQuality) {
+        estimate_from_stats(self.m, self.stats, true)
+    }
+
+    /// Merge another HyperLogLog into this one
+    ///
+    /// Both must share the same seed, precision and rank bits; a layout
+    /// mismatch is reported as [`HllError::InvalidLayout`]. See
+    /// [`HyperLogLog::merge_normalizing`] for mixed layouts.
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), HllError> {
+        self.check_mergeable(other)?;
+        self.merge_registers(other);
+        Ok(())
+    }
+
+    /// Merge many HyperLogLogs into a new one
+    ///
+    /// Every input is validated against the first before anything is
+    /// merged, under the same rules as [`HyperLogLog::merge`]; an empty slice
+    /// is an error. The result takes the first sketch's settings and each
+    /// input is folded in with one sequential pass over its registers.
+    pub fn merge_all(sketches: &[HyperLogLog]) -> Result<HyperLogLog, HllError> {
+        let (first, rest) = sketches
+            .split_first()
+            .ok_or_else(|| HllError::InvalidInput("No HyperLogLogs to merge".to_string()))?;
+        for hll in rest {
+            first.check_mergeable(hll)?;
+        }
+
+        let mut merged = first.clone();
+        for hll in rest {
+            merged.merge_registers(hll);
+        }
+        Ok(merged)
+    }
+
+    /// Merge many HyperLogLogs into a new one, spreading the work across
+    /// rayon's thread pool
+    ///
+    /// Same validation, errors and result as [`HyperLogLog::merge_all`], bit
+    /// for bit, whatever the number of threads: register maxima are combined
+    /// with a parallel tree reduction, and taking a maximum does not care
+    /// about order. Worth it for large fan-in; for a handful of sketches the
+    /// sequential version is cheaper. If any input is sparse, the merge runs
+    /// sequentially, since a dense union would densify a result that
+    /// [`HyperLogLog::merge_all`] keeps sparse.
+    #[cfg(feature = "rayon")]
+    pub fn merge_all_parallel(sketches: &[HyperLogLog]) -> Result<HyperLogLog, HllError> {
+        use rayon::prelude::*;
+
+        if sketches.iter().any(HyperLogLog::is_sparse) {
+            return Self::merge_all(sketches);
+        }
+
+        let (first, rest) = sketches
+            .split_first()
+            .ok_or_else(|| HllError::InvalidInput("No HyperLogLogs to merge".to_string()))?;
+        for hll in rest {
+            first.check_mergeable(hll)?;
+        }
+
+        let max_into = |mut acc: Vec<u8>, registers: &[u8]| {
+            for (a, &b) in acc.iter_mut().zip(registers) {
+                if b > *a {
+                    *a = b;
+                }
+            }
+            acc
+        };
+        let registers = rest
+            .par_iter()
+            .fold(|| vec![0u8; first.m], |acc, hll| max_into(acc, &hll.dense_registers()))
+            .reduce(|| vec![0u8; first.m], |acc, other| max_into(acc, &other));
+
+        // Fold the union of the rest into the first sketch so the result keeps
+        // its representation exactly as the sequential merge would
+        let union = HyperLogLog::from_parts(first.precision, first.rank_bits, registers)?;
+        let mut merged = first.clone();
+        merged.merge_registers(&union);
+        Ok(merged)
+    }
+
+    /// Whether `other` can be merged into `self` without changing layouts
+    fn check_mergeable(&self, other: &HyperLogLog) -> Result<(), HllError> {
+        self.check_seed(other)?;
+
+        if self.precision != other.precision {
+            return Err(HllError::Storage(
+                "Cannot merge HyperLogLogs with different precision".to_string()
+            ));
+        }
+
+        if self.rank_bits != other.rank_bits {
+            return Err(HllError::InvalidLayout(format!(
+                "Cannot merge HyperLogLogs with {} and {} rank bits",
+                self.rank_bits, other.rank_bits
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Register-wise max of `other` into `self`; the layouts must match
+    fn merge_registers(&mut self, other: &HyperLogLog) {
+        if let Some(pairs) = &other.sparse {
+            for &(idx, val) in pairs {
+                self.update_register(idx as usize, val);
+            }
+            return;
+        }
+
+        // Merging a full register array fills a sparse sketch anyway
+        if self.sparse.is_some() {
+            self.densify();
+        }
+
+        if self.packed.is_none() && other.packed.is_none() {
+            self.cached_count.invalidate();
+            for (i, &val) in other.registers.iter().enumerate() {
+                if val > self.registers[i] {
+                    self.stats.replace(self.registers[i], val);
+                    self.registers[i] = val;
+                }
+            }
+            return;
+        }
+
+        for (idx, &val) in other.dense_registers().iter().enumerate() {
+            self.update_register(idx, val);
+        }
+    }
+
+    /// Union of `self` and `other` as a new HyperLogLog, leaving both untouched
+    ///
+    /// Same requirements and errors as [`HyperLogLog::merge`].
+    pub fn union(&self, other: &HyperLogLog) -> Result<HyperLogLog, HllError> {
+        let mut union = self.clone();
+        union.merge(other)?;
+        Ok(union)
+    }
+
+    /// Sketches hashed with different seeds or hash functions map elements
+    /// to unrelated registers and cannot be combined
+    fn check_seed(&self, other: &HyperLogLog) -> Result<(), HllError> {
+        if self.seed != other.seed {
+            return Err(HllError::Storage(format!(
+                "Cannot merge HyperLogLogs with different hash seeds ({} and {})",
+                self.seed, other.seed
+            )));
+        }
+        if self.hasher != other.hasher {
+            return Err(HllError::Storage(format!(
+                "Cannot merge HyperLogLogs with different hash functions ({:?} and {:?})",
+                self.hasher, other.hasher
+            )));
+        }
+        Ok(())
+    }
+
+    /// Estimate how many elements `self` and `other` have in common
+    ///
+    /// Computed by inclusion-exclusion as `|A| + |B| - |A ∪ B|`, with
+    /// negative estimates clamped to zero. The error scales with the sets
+    /// rather than the overlap, so a small overlap between large sets can be
+    /// swamped by it: at precision 14, two sets of a million elements carry
+    /// an error of several thousand. Both sketches must share precision and
+    /// layout.
+    pub fn intersect_count(&self, other: &HyperLogLog) -> Result<u64, HllError> {
+        let union = self.union(other)?;
+
+        let intersection = self.count() as i128 + other.count() as i128 - union.count() as i128;
+        Ok(intersection.max(0) as u64)
+    }
+
+    /// Estimate the Jaccard similarity `|A ∩ B| / |A ∪ B|`, from `0.0` to `1.0`
+    ///
+    /// The intersection is estimated as in [`HyperLogLog::intersect_count`]
+    /// and shares its caveat: similarities of large, barely overlapping sets
+    /// are dominated by estimation error. Two empty sketches give `0.0`.
+    /// Both sketches must share precision and layout.
+    pub fn jaccard(&self, other: &HyperLogLog) -> Result<f64, HllError> {
+        let union = self.union(other)?.count();
+        if union == 0 {
+            return Ok(0.0);
+        }
+
+        let intersection = (self.count() as i128 + other.count() as i128 - union as i128).max(0);
+        Ok((intersection as f64 / union as f64).min(1.0))
+    }
+
+    /// Estimate how many elements are in exactly one of `self` and `other`
+    ///
+    /// Computed as `|A ∪ B| - |A ∩ B|`, with the intersection taken by
+    /// inclusion-exclusion, which simplifies to `2|A ∪ B| - |A| - |B|`. The
+    /// error scales with the union rather than the result, so small
+    /// differences between large sets are imprecise. Negative estimates are
+    /// clamped to zero. Both sketches must share precision and layout.
+    pub fn symmetric_difference_count(&self, other: &HyperLogLog) -> Result<u64, HllError> {
+        let union = self.union(other)?;
+
+        let difference = 2 * union.count() as i128 - self.count() as i128 - other.count() as i128;
+        Ok(difference.max(0) as u64)
+    }
+
+    /// Merge another HyperLogLog whose layout may differ from this one
+    ///
+    /// Both sides are converted to the lower precision with
+    /// [`HyperLogLog::fold_to`] and to the fewer rank bits by capping
+    /// registers. Since both hash elements the same way, the result is
+    /// exactly what adding every element at that common layout would have
+    /// produced; the wider side just loses its extra resolution.
+    pub fn merge_normalizing(&mut self, other: &HyperLogLog) -> Result<(), HllError> {
+        // Check before folding so a failed merge leaves `self` untouched
+        self.check_seed(other)?;
+
+        let precision = self.precision.min(other.precision);
+        if self.precision > precision {
+            *self = self.fold_to(precision)?;
+        }
+        let mut other = if other.precision > precision {
+            other.fold_to(precision)?
+        } else {
+            other.clone()
+        };
+
+        let rank_bits = self.rank_bits.min(other.rank_bits);
+        self.cap_rank_bits(rank_bits);
+        other.cap_rank_bits(rank_bits);
+
+        self.merge(&other)
+    }
+
+    /// Merge many HyperLogLogs of possibly different precisions
+    ///
+    /// Inputs are first merged with others of the same precision, then each
+    /// precision is folded to the lowest one exactly once. The result equals
+    /// merging them one by one with [`HyperLogLog::merge_normalizing`], which
+    /// can fold the accumulator and every higher-precision input separately.
+    pub fn merge_optimized(hlls: &[HyperLogLog]) -> Result<HyperLogLog, HllError> {
+        let mut groups: BTreeMap<u8, HyperLogLog> = BTreeMap::new();
+        for hll in hlls {
+            match groups.get_mut(&hll.precision) {
+                Some(group) => group.merge_normalizing(hll)?,
+                None => {
+                    groups.insert(hll.precision, hll.clone());
+                }
+            }
+        }
+
+        // Ascending precision, so the first group is already at the target
+        let mut groups = groups.into_values();
+        let mut merged = groups
+            .next()
+            .ok_or_else(|| HllError::InvalidInput("No HyperLogLogs to merge".to_string()))?;
+        for group in groups {
+            merged.merge_normalizing(&group)?;
+        }
+
+        Ok(merged)
+    }
+
+    /// Narrow the rank window to `rank_bits`, which must not exceed the
+    /// current width
+    ///
+    /// A zero run that reached past the new window saturates at
+    /// `rank_bits + 1`, as `add` would have recorded it.
+    fn cap_rank_bits(&mut self, rank_bits: u8) {
+        self.cached_count.invalidate();
+        let max_register = rank_bits + 1;
+        for val in &mut self.registers {
+            *val = (*val).min(max_register);
+        }
+        for (_, val) in self.sparse.iter_mut().flatten() {
+            *val = (*val).min(max_register);
+        }
+        if self.packed.is_some() {
+            for idx in 0..self.m {
+                let val = self.get_register(idx);
+                self.set_register(idx, val.min(max_register));
+            }
+        }
+        self.recompute_stats();
+        self.rank_bits = rank_bits;
+    }
+
+    /// Reduce this HyperLogLog to a lower precision
+    ///
+    /// Groups of `2^(precision - target_precision)` registers collapse into
+    /// one. The index bits that are dropped become the leading bits of the
+    /// rank, so the result is exactly what adding the same elements at
+    /// `target_precision` would have produced.
+    ///
+    /// A target below the supported range is [`HllError::InvalidPrecision`];
+    /// one above the current precision is [`HllError::InvalidInput`].
+    pub fn fold_to(&self, target_precision: u8) -> Result<HyperLogLog, HllError> {
+        if target_precision < MIN_PRECISION {
+            return Err(HllError::InvalidPrecision(target_precision));
+        }
+        if target_precision > self.precision {
+            return Err(HllError::InvalidInput(format!(
+                "Cannot fold precision {} up to {}",
+                self.precision, target_precision
+            )));
+        }
+
+        #[cfg(test)]
+        FOLDS.with(|folds| folds.set(folds.get() + 1));
+
+        let shift = self.precision - target_precision;
+        let mut folded = HyperLogLog::with_layout(target_precision, self.rank_bits + shift)?;
+        folded.seed = self.seed;
+        folded.hasher = self.hasher;
+        folded.normalizer = self.normalizer.clone();
+        folded.calibration = self.calibration;
+        folded.max_element_len = self.max_element_len;
+        if self.sparse.is_some() {
+            folded.registers = Vec::new();
+            folded.sparse = Some(Vec::new());
+        } else if self.packed.is_some() {
+            folded.pack();
+        }
+        let low_mask = (1usize << shift) - 1;
+
+        for (idx, &val) in self.dense_registers().iter().enumerate() {
+            if val == 0 {
+                continue;
+            }
+
+            // A set bit among the dropped index bits now ends the zero run early
+            let low = idx & low_mask;
+            let rank = if low == 0 {
+                val + shift
+            } else {
+                (low.leading_zeros() - (usize::BITS - shift as u32)) as u8 + 1
+            };
+
+            folded.update_register(idx >> shift, rank);
+        }
+
+        Ok(folded)
+    }
+
+    /// Get precision
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Number of registers, `2^precision`
+    pub fn num_registers(&self) -> usize {
+        self.m
+    }
+
+    /// Read-only view of all `2^precision` registers, for custom estimators
+    /// and debugging
+    ///
+    /// Borrowed for dense sketches. Sparse and packed sketches have no
+    /// register array, so one is built for the call.
+    pub fn registers(&self) -> Cow<'_, [u8]> {
+        self.dense_registers()
+    }
+
+    /// `(index, value)` of every nonzero register, in index order
+    ///
+    /// Visits only the stored pairs of a sparse sketch, so diffing two
+    /// sketches this way is cheap while they are small.
+    pub fn nonzero_registers_iter(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        // At most one representation is populated; the others are empty
+        let sparse = self.sparse.iter().flatten().map(|&(idx, val)| (idx as usize, val));
+        let packed = self.packed.iter().flat_map(move |packed| {
+            (0..self.m).map(move |idx| (idx, get_packed(packed, PACKED_WIDTH, idx)))
+        });
+        let dense = self.registers.iter().copied().enumerate();
+
+        sparse.chain(packed.chain(dense).filter(|&(_, val)| val != 0))
+    }
+
+    /// Value of register `idx`, or `None` past the last register
+    ///
+    /// Cheap in every representation, unlike indexing
+    /// [`HyperLogLog::registers`] of a sparse or packed sketch.
+    pub fn register(&self, idx: usize) -> Option<u8> {
+        (idx < self.m).then(|| self.get_register(idx))
+    }
+
+    /// Seed of the hash used for elements; 0 unless set with
+    /// [`HyperLogLog::with_seed`]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Hash function used for elements; xxHash64 unless set with
+    /// [`HyperLogLog::with_hasher`]
+    pub fn hasher(&self) -> HasherKind {
+        self.hasher
+    }
+
+    /// Number of hash bits scanned for leading zeros after the register index
+    pub fn rank_bits(&self) -> u8 {
+        self.rank_bits
+    }
+
+    /// Smallest precision whose standard error (`1.04 / sqrt(m)`) is at most
+    /// `target_relative_error`
+    ///
+    /// Targets tighter than the maximum precision can deliver are clamped to
+    /// the maximum precision.
+    pub fn precision_for_error(target_relative_error: f64) -> u8 {
+        min_precision_for_error(target_relative_error).unwrap_or(MAX_PRECISION)
+    }
+
+    /// Theoretical relative standard error of this sketch, `1.04 / sqrt(m)`
+    ///
+    /// About 68% of estimates fall within this fraction of the true count.
+    pub fn relative_error(&self) -> f64 {
+        standard_error(self.precision)
+    }
+
+    /// Theoretical relative standard error at `precision`, `1.04 / sqrt(2^p)`
+    ///
+    /// The precision is not validated, so this also answers for precisions
+    /// outside the supported range.
+    pub fn error_for_precision(precision: u8) -> f64 {
+        standard_error(precision)
+    }
+
+    /// Registers, memory and standard error for every supported precision
+    pub fn precision_table() -> Vec<PrecisionInfo> {
+        (MIN_PRECISION..=MAX_PRECISION)
+            .map(|precision| PrecisionInfo {
+                precision,
+                registers: 1 << precision,
+                memory_bytes: 1 << precision,
+                relative_error: standard_error(precision),
+            })
+            .collect()
+    }
+
+    /// Short hex identifier of the exact sketch state, for cache keys
+    ///
+    /// An xxHash64 of the precision, rank bits, registers and any non-default
+    /// seed or hasher: identical sketches share it, any register change alters it, and
+    /// it is stable across processes and platforms. Normalizer and
+    /// calibration are not part of the state and do not affect it.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = XxHash64::with_seed(0);
+        hasher.write(&[self.precision, self.rank_bits]);
+        // Seed 0 sketches keep the fingerprints they had before seeds existed
+        if self.seed != 0 {
+            hasher.write(&self.seed.to_le_bytes());
+        }
+        if !self.hasher.is_default() {
+            hasher.write(&[self.hasher.to_byte()]);
+        }
+        hasher.write(&self.dense_registers());
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Hash an element with the sketch's hasher and seed
+    ///
+    /// The hash, and how `add` splits it into index and rank, decide the
+    /// register contents and so are part of the serialization contract:
+    /// sketches built by different builds or platforms must stay mergeable.
+    /// `test_golden_registers` pins the result for a fixed set of strings.
+    pub(crate) fn hash_element<T: Hash>(&self, element: &T) -> u64 {
+        let mut hasher = self.hasher.build(self.seed);
+        element.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Sketches are equal when they hold the same registers under the same
+/// precision, layout, seed and hasher
+///
+/// The representation does not matter: a sparse or packed sketch equals its
+/// dense counterpart. Settings that only affect how elements are added or
+/// counted (normalizer, calibration, element length limit) are ignored.
+impl PartialEq for HyperLogLog {
+    fn eq(&self, other: &Self) -> bool {
+        self.precision == other.precision
+            && self.rank_bits == other.rank_bits
+            && self.seed == other.seed
+            && self.hasher == other.hasher
+            && self.dense_registers() == other.dense_registers()
+    }
+}
+
+impl Eq for HyperLogLog {}
+
+/// One-line summary for logs, e.g.
+/// `HyperLogLog(p=14, m=16384, count≈9987, fill=45%)`
+///
+/// `fill` is the percentage of nonzero registers. `Debug` still dumps
+/// every register.
+impl fmt::Display for HyperLogLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "HyperLogLog(p={}, m={}, count≈{}, fill={:.0}%)",
+            self.precision,
+            self.m,
+            self.count(),
+            self.fill_ratio() * 100.0
+        )
+    }
+}
+
+/// Adds every item with [`HyperLogLog::add_all`]
+///
+/// Strings go through `add`, not [`HyperLogLog::add_str`], so the
+/// normalizer and element length limit do not apply.
+impl<T: Hash> Extend<T> for HyperLogLog {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.add_all(iter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+    #[test]
+    fn test_new_valid_precision() {
+        for precision in 4..=16 {
+            let hll = HyperLogLog::new(precision);
+            assert!(hll.is_ok(), "Precision {} should be valid", precision);
+
+            let hll = hll.unwrap();
+            assert_eq!(hll.precision(), precision);
+            assert_eq!(hll.m, 1 << precision);
+            assert_eq!(hll.registers.len(), 1 << precision);
+        }
+    }
+
+    #[test]
+    fn test_new_invalid_precision() {
+        assert!(HyperLogLog::new(3).is_err());
+        assert!(HyperLogLog::new(17).is_err());
+        assert!(HyperLogLog::new(0).is_err());
+        assert!(HyperLogLog::new(255).is_err());
+
+        let err = HyperLogLog::new(2).unwrap_err();
+        assert!(matches!(err, HllError::InvalidPrecision(2)));
+        assert_eq!(err.to_string(), "Invalid precision 2 (must be 4..=16)");
+    }
+
+    #[test]
+    fn test_precision_for_error() {
+        let error = |p: u8| 1.04 / ((1u64 << p) as f64).sqrt();
+
+        let p = HyperLogLog::precision_for_error(0.01);
+        assert!(error(p) <= 0.01, "Precision {} error {} exceeds target", p, error(p));
+        assert!(error(p - 1) > 0.01, "Precision {} is not the smallest", p);
+
+        // Unreachable targets clamp to the valid range
+        assert_eq!(HyperLogLog::precision_for_error(1.0), 4);
+        assert_eq!(HyperLogLog::precision_for_error(0.0001), 16);
+    }
+
+    #[test]
+    fn test_for_error_rate() {
+        assert_eq!(HyperLogLog::for_error_rate(0.01).unwrap().precision(), 14);
+        assert_eq!(HyperLogLog::for_error_rate(0.005).unwrap().precision(), 16);
+        assert_eq!(HyperLogLog::for_error_rate(0.5).unwrap().precision(), MIN_PRECISION);
+
+        // Exactly the error of a precision selects it
+        let exact = HyperLogLog::error_for_precision(12);
+        assert_eq!(HyperLogLog::for_error_rate(exact).unwrap().precision(), 12);
+
+        let mut hll = HyperLogLog::for_error_rate(0.02).unwrap();
+        assert!(hll.relative_error() <= 0.02);
+        hll.add_all(0..1000);
+        assert!((hll.count() as f64 - 1000.0).abs() < 60.0, "Got {}", hll.count());
+
+        for unreachable in [0.004, 0.0, -1.0, f64::NAN] {
+            assert!(
+                matches!(HyperLogLog::for_error_rate(unreachable), Err(HllError::InvalidInput(_))),
+                "Accepted {}",
+                unreachable
+            );
+        }
+    }
+
+    #[test]
+    fn test_relative_error() {
+        // Matches the standard error column of the README table
+        assert!((HyperLogLog::error_for_precision(10) - 0.0325).abs() < 1e-4);
+        assert!((HyperLogLog::error_for_precision(12) - 0.01625).abs() < 1e-4);
+        assert!((HyperLogLog::error_for_precision(14) - 0.008125).abs() < 1e-4);
+        assert!((HyperLogLog::error_for_precision(16) - 0.0040625).abs() < 1e-4);
+
+        let hll = HyperLogLog::new(14).unwrap();
+        assert_eq!(hll.relative_error(), HyperLogLog::error_for_precision(14));
+        assert_eq!(hll.fold_to(10).unwrap().relative_error(), HyperLogLog::error_for_precision(10));
+    }
+
+    #[test]
+    fn test_count_with_confidence() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..10000 {
+            hll.add(&i);
+        }
+
+        let (lower, estimate, upper) = hll.count_with_confidence(1.96);
+        assert_eq!(estimate, hll.count());
+        assert!(lower <= estimate && estimate <= upper);
+        assert!((lower..=upper).contains(&10000), "{}..={} misses the true count", lower, upper);
+
+        let (wide_lower, _, wide_upper) = hll.count_with_confidence(3.0);
+        assert!(wide_lower < lower && wide_upper > upper);
+
+        assert_eq!(HyperLogLog::new(12).unwrap().count_with_confidence(1.96), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_precision_table() {
+        let table = HyperLogLog::precision_table();
+
+        let precisions: Vec<u8> = table.iter().map(|info| info.precision).collect();
+        assert_eq!(precisions, (4..=16).collect::<Vec<u8>>());
+
+        for pair in table.windows(2) {
+            assert!(pair[1].relative_error < pair[0].relative_error);
+        }
+
+        let p14 = table.iter().find(|info| info.precision == 14).unwrap();
+        assert_eq!(p14.registers, 16384);
+        assert_eq!(p14.memory_bytes, 16384);
+    }
+
+    #[test]
+    fn test_add_deduplication() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+
+        // Add same element multiple times
+        for _ in 0..100 {
+            hll.add_str("same_element");
+        }
+
+        let count = hll.count();
+        // Should estimate close to 1
+        assert!(count <= 5, "Count should be close to 1, got {}", count);
+    }
+
+    #[test]
+    fn test_basic_counting_small() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+
+        for i in 0..100 {
+            hll.add(&i);
+        }
+
+        let count = hll.count();
+        let error_rate = ((count as f64 - 100.0) / 100.0).abs();
+
+        // Should be within ~10% for small counts
+        assert!(error_rate < 0.15, "Error rate: {:.2}%", error_rate * 100.0);
+    }
+
+    #[test]
+    fn test_basic_counting_medium() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+
+        for i in 0..10000 {
+            hll.add(&i);
+        }
+
+        let count = hll.count();
+        let error_rate = ((count as f64 - 10000.0) / 10000.0).abs();
+
+        // HyperLogLog should be within ~2% error for precision 14
+        assert!(error_rate < 0.05, "Error rate: {:.2}%", error_rate * 100.0);
+    }
+
+    #[test]
+    fn test_basic_counting_large() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+
+        for i in 0..100000 {
+            hll.add(&i);
+        }
+
+        let count = hll.count();
+        let error_rate = ((count as f64 - 100000.0) / 100000.0).abs();
+
+        // Should be within ~2% for large counts
+        assert!(error_rate < 0.03, "Error rate: {:.2}%", error_rate * 100.0);
+    }
+
+    #[test]
+    #[allow(clippy::manual_range_contains)]
+    fn test_string_elements() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+
+        hll.add_str("user:1");
+        hll.add_str("user:2");
+        hll.add_str("user:3");
+
+        let count = hll.count();
+        assert!(count >= 2 && count <= 5, "Count should be ~3, got {}", count);
+    }
+
+    #[test]
+    fn test_add_reader() {
+        let long_line = "x".repeat(100_000);
+        let input = format!("alice\nbob\r\nalice\n{}\ncarol", long_line);
+
+        let mut hll = HyperLogLog::new(12).unwrap();
+        let lines = hll.add_reader(io::Cursor::new(input)).unwrap();
+        assert_eq!(lines, 5);
+
+        let mut expected = HyperLogLog::new(12).unwrap();
+        for element in ["alice", "bob", &long_line, "carol"] {
+            expected.add_str(element);
+        }
+        assert_eq!(hll.registers, expected.registers);
+        assert_eq!(hll.count(), 4);
+    }
+
+    #[test]
+    fn test_normalizer() {
+        let mut hll = HyperLogLog::new(10)
+            .unwrap()
+            .with_normalizer(|s| s.trim().to_lowercase());
+
+        hll.add_str("  Foo@X.com ");
+        hll.add_str("foo@x.com");
+        hll.add_str("FOO@X.COM");
+
+        assert_eq!(hll.count(), 1);
+
+        // Without a normalizer, the same inputs are distinct
+        let mut plain = HyperLogLog::new(10).unwrap();
+        plain.add_str("  Foo@X.com ");
+        plain.add_str("foo@x.com");
+        plain.add_str("FOO@X.COM");
+
+        assert_eq!(plain.count(), 3);
+    }
+
+    #[test]
+    fn test_max_element_len() {
+        let mut hll = HyperLogLog::new(10).unwrap().with_max_element_len(8);
+        hll.add_str("short");
+        hll.add_str("exactly8");
+        assert_eq!(hll.count(), 2);
+
+        hll.add_str("far too long for this sketch");
+        assert_eq!(hll.count(), 2, "Over-length element should be skipped");
+    }
+
+    #[test]
+    fn test_add_bytes() {
+        let uuid = [
+            0x55, 0x0e, 0x84, 0x00, 0xe2, 0x9b, 0x41, 0xd4, 0xa7, 0x16, 0x44, 0x66, 0x55, 0x44,
+            0x00, 0x00,
+        ];
+
+        let mut hll = HyperLogLog::new(8).unwrap();
+        hll.add_bytes(&uuid);
+        let updated: Vec<(usize, u8)> = hll
+            .registers
+            .iter()
+            .enumerate()
+            .filter(|(_, &val)| val != 0)
+            .map(|(idx, &val)| (idx, val))
+            .collect();
+        // Pinned: the bytes are hashed with plain xxHash64, seed 0
+        assert_eq!(updated, vec![(156, 9)]);
+
+        let mut bytes = HyperLogLog::new(8).unwrap();
+        let mut string = HyperLogLog::new(8).unwrap();
+        bytes.add_bytes(b"foo");
+        string.add_str("foo");
+        assert_ne!(bytes.registers, string.registers, "add_str hashes a terminator too");
+    }
+
+    #[test]
+    fn test_estimate_quality() {
+        let mut sparse = HyperLogLog::new(10).unwrap();
+        for i in 0..10 {
+            sparse.add(&i);
+        }
+        assert_eq!(sparse.estimate_quality(), EstimateQuality::SmallRangeCorrected);
+
+        let mut mid = HyperLogLog::new(10).unwrap();
+        for i in 0..10000 {
+            mid.add(&i);
+        }
+        assert_eq!(mid.estimate_quality(), EstimateQuality::Good);
+
+        // A low-precision sketch with narrow registers fills up quickly
+        let mut full = HyperLogLog::with_layout(4, 6).unwrap();
+        for i in 0..100000 {
+            full.add(&i);
+        }
+        assert_eq!(full.estimate_quality(), EstimateQuality::Saturated);
+
+        // Huge registers within the rank window are taken at face value
+        let huge = HyperLogLog::with_registers_fn(4, |_| 30).unwrap();
+        assert_eq!(huge.estimate_quality(), EstimateQuality::Good);
+        assert_eq!(huge.count(), raw_estimate_registers(&huge.registers) as u64);
+    }
+
+    #[test]
+    fn test_all_zero_remainder_rank() {
+        // Index bits set, everything after them zero: the rank is one past
+        // the window, the largest value a register ever takes
+        for precision in [MIN_PRECISION, 14, MAX_PRECISION] {
+            let hll = HyperLogLog::new(precision).unwrap();
+            let last = (1u64 << precision) - 1;
+            let hash = last << (64 - precision);
+            assert_eq!(hll.index_and_rank(hash), (last as usize, 65 - precision));
+            assert_eq!(hll.index_and_rank(0), (0, 65 - precision));
+            assert_eq!(hll.index_and_rank(1), (0, 64 - precision));
+        }
+
+        // Narrow rank window
+        let narrow = HyperLogLog::with_layout(MIN_PRECISION, 3).unwrap();
+        assert_eq!(narrow.index_and_rank(0), (0, 4));
+        assert_eq!(narrow.index_and_rank(1 << 59), (0, 1));
+
+        // 61 is the largest rank at precision 4 and must fit a packed
+        // register without spilling into its neighbours
+        let mut packed = HyperLogLog::new_packed(MIN_PRECISION).unwrap();
+        packed.add_hash(1 << 60);
+        packed.add_hash(0);
+        packed.add_hash(2 << 60);
+        let registers = packed.dense_registers();
+        assert_eq!(&registers[..4], &[61, 61, 61, 0]);
+        assert!(registers[4..].iter().all(|&val| val == 0));
+
+        // A corrupt layout clamps rather than overflowing
+        let mut bad = HyperLogLog::new_packed(MIN_PRECISION).unwrap();
+        bad.rank_bits = u8::MAX;
+        assert_eq!(bad.index_and_rank(0).1, (1 << PACKED_WIDTH) - 1);
+    }
+
+    #[test]
+    fn test_empty_count_is_zero() {
+        for precision in MIN_PRECISION..=16 {
+            let hll = HyperLogLog::new(precision).unwrap();
+            assert_eq!(hll.count(), 0, "Precision {}", precision);
+            assert_eq!(hll.estimate_quality(), EstimateQuality::SmallRangeCorrected);
+            let registers = vec![0u8; 1 << precision];
+            assert_eq!(estimate_from_registers(precision, &registers).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn test_no_large_range_bias() {
+        // 50M distinct hashes from a splitmix64 sequence, far past the old
+        // 2^32 / 30 large-range threshold
+        let mut hll = HyperLogLog::new(14).unwrap();
+        let mut state = 0u64;
+        for _ in 0..50_000_000u64 {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            hll.add_hash(z ^ (z >> 31));
+        }
+
+        let error = (hll.count() as f64 - 50_000_000.0).abs() / 50_000_000.0;
+        assert!(error < 0.01, "Estimate off by {:.2}%", error * 100.0);
+        assert_eq!(hll.estimate_quality(), EstimateQuality::Good);
+    }
+
+    #[test]
+    fn test_count_cache_invalidation() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        assert_eq!(hll.count(), 0);
+        assert_eq!(hll.cached_count.get(), Some(0));
+
+        for i in 0..1000 {
+            hll.add(&i);
+        }
+        assert_eq!(hll.cached_count.get(), None);
+        let count = hll.count();
+        assert!((count as f64 - 1000.0).abs() < 50.0, "Got {}", count);
+        assert_eq!(hll.cached_count.get(), Some(count));
+        assert_eq!(hll.count(), count);
+
+        // A duplicate changes no register, so the cache survives
+        hll.add(&0);
+        assert_eq!(hll.cached_count.get(), Some(count));
+
+        hll.add_str("more");
+        hll.add_all(1000..2000);
+        let grown = hll.count();
+        assert!((grown as f64 - 2001.0).abs() < 100.0, "Got {}", grown);
+
+        let mut other = HyperLogLog::new(12).unwrap();
+        other.add_all(2000..3000);
+        hll.merge(&other).unwrap();
+        let merged = hll.count();
+        assert!(merged > grown, "Got {} after merging into {}", merged, grown);
+        assert_eq!(merged, HyperLogLog::from_registers(12, hll.registers.clone()).unwrap().count());
+
+        let hll = hll.with_calibration(2.0);
+        assert_eq!(hll.count(), hll.count_calibrated(2.0));
+
+        let mut hll = hll.with_calibration(1.0);
+        hll.clear();
+        assert_eq!(hll.count(), 0);
+
+        // Sparse sketches go through the same paths
+        let mut sparse = HyperLogLog::new_sparse(12).unwrap();
+        assert_eq!(sparse.count(), 0);
+        sparse.add_str("a");
+        assert_eq!(sparse.count(), 1);
+    }
+
+    #[test]
+    fn test_incremental_stats_match_recomputation() {
+        fn check(hll: &HyperLogLog, step: &str) {
+            let registers = hll.dense_registers();
+            assert_eq!(hll.stats, RegisterStats::of(&registers), "Stats drifted after {}", step);
+            assert_eq!(
+                hll.count(),
+                estimate_registers(&registers, true).0 as u64,
+                "Count drifted after {}",
+                step
+            );
+            assert_eq!(hll.count_raw(), estimate_registers(&registers, false).0 as u64);
+        }
+
+        let mut hll = HyperLogLog::new(12).unwrap();
+        check(&hll, "new");
+        for i in 0..500 {
+            hll.add(&i);
+        }
+        check(&hll, "add");
+        hll.add_all(500..3000);
+        hll.add_str("alice");
+        hll.add_bytes(b"bob");
+        check(&hll, "add_all");
+
+        let mut dense = HyperLogLog::new(12).unwrap();
+        dense.add_all(2000..20000);
+        hll.merge(&dense).unwrap();
+        check(&hll, "dense merge");
+
+        let mut sparse = HyperLogLog::new_sparse(12).unwrap();
+        sparse.add_all(100_000..100_050);
+        check(&sparse, "sparse add");
+        hll.merge(&sparse).unwrap();
+        check(&hll, "sparse merge");
+        sparse.merge(&hll).unwrap();
+        check(&sparse, "merge into sparse");
+
+        let mut packed = HyperLogLog::new_packed(12).unwrap();
+        packed.add_all(0..5000);
+        check(&packed, "packed add");
+        packed.merge(&hll).unwrap();
+        check(&packed, "packed merge");
+
+        let mut wide = HyperLogLog::new(14).unwrap();
+        wide.add_all(50_000..60_000);
+        let mut normalized = hll.clone();
+        normalized.merge_normalizing(&wide).unwrap();
+        check(&normalized, "merge_normalizing");
+        check(&wide.fold_to(10).unwrap(), "fold_to");
+
+        check(&HyperLogLog::from_bytes(&packed.to_bytes()).unwrap(), "from_bytes");
+        let json = serde_json::to_string(&sparse).unwrap();
+        check(&serde_json::from_str(&json).unwrap(), "deserialization");
+
+        hll.clear();
+        check(&hll, "clear");
+        assert_eq!(hll.count(), 0);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+        assert_eq!(hll.to_string(), "HyperLogLog(p=14, m=16384, count≈0, fill=0%)");
+
+        hll.add_all(0..10000);
+        let display = hll.to_string();
+        assert!(display.starts_with("HyperLogLog(p=14, m=16384, count≈"), "Got {}", display);
+        assert!(display.contains(&format!("count≈{},", hll.count())), "Got {}", display);
+        assert!(
+            display.ends_with(&format!("fill={:.0}%)", hll.fill_ratio() * 100.0)),
+            "Got {}",
+            display
+        );
+        assert!(format!("{:?}", hll).contains("registers"));
+    }
+
+    #[test]
+    fn test_fill_and_recommended_precision() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        assert_eq!(hll.fill_ratio(), 0.0);
+        assert_eq!(hll.nonzero_registers(), 0);
+        assert_eq!(hll.recommended_precision(), 10);
+
+        for i in 0..100 {
+            hll.add(&i);
+        }
+        assert!(hll.fill_ratio() > 0.0 && hll.fill_ratio() < 0.1);
+        assert_eq!(hll.nonzero_registers(), hll.registers.iter().filter(|&&val| val != 0).count());
+        assert_eq!(hll.fill_ratio(), hll.nonzero_registers() as f64 / 1024.0);
+        assert_eq!(hll.recommended_precision(), 10);
+
+        // A 3-bit rank saturates quickly
+        let mut narrow = HyperLogLog::with_layout(10, 3).unwrap();
+        for i in 0..100000 {
+            narrow.add(&i);
+        }
+        assert_eq!(narrow.fill_ratio(), 1.0);
+        assert_eq!(narrow.recommended_precision(), 11);
+    }
+
+    #[test]
+    fn test_register_sum_is_exact() {
+        // Half the registers contribute 1.0 each; the other half contribute
+        // 2^-40, below half an ulp of the running total
+        let hll = HyperLogLog::with_registers_fn(16, |i| if i % 2 == 0 { 0 } else { 40 }).unwrap();
+
+        // 2^15 + 2^15 * 2^-40 = 2^15 + 2^-25, which a float holds exactly
+        let reference = 32768.0 + 2.0_f64.powi(-25);
+
+        let naive: f64 = hll.registers.iter().map(|&val| 2.0_f64.powi(-(val as i32))).sum();
+        let sum = register_sum(&hll.registers);
+
+        let naive_error = (naive - reference).abs();
+        let error = (sum - reference).abs();
+        assert!(
+            error < naive_error,
+            "Fixed-point error {:e} should beat naive error {:e}",
+            error,
+            naive_error
+        );
+        assert_eq!(sum, reference);
+    }
+
+    #[test]
+    fn test_saturation() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        assert!(hll.saturation() < 0.001, "Got {}", hll.saturation());
+        for i in 0..10000 {
+            hll.add(&i);
+        }
+        assert!(hll.saturation() < 0.5, "Got {}", hll.saturation());
+
+        let mut narrow = HyperLogLog::with_layout(10, 3).unwrap();
+        for i in 0..100000 {
+            narrow.add(&i);
+        }
+        assert_eq!(narrow.saturation(), 1.0);
+        assert_eq!(narrow.estimate_quality(), EstimateQuality::Saturated);
+    }
+
+    #[test]
+    fn test_small_precision_alpha() {
+        // Reference alpha for m = 64 by Simpson's rule over [0, 50]; the
+        // integrand is below 1e-90 past that
+        let m = 64.0;
+        let integrand = |u: f64| ((2.0 + u) / (1.0 + u)).log2().powf(m);
+        let steps = 200_000;
+        let h = 50.0 / steps as f64;
+        let mut integral = integrand(0.0) + integrand(50.0);
+        for i in 1..steps {
+            let weight = if i % 2 == 1 { 4.0 } else { 2.0 };
+            integral += weight * integrand(i as f64 * h);
+        }
+        let exact_alpha = 1.0 / (m * integral * h / 3.0);
+
+        let formula_alpha = 0.7213 / (1.0 + 1.079 / m);
+        let hll = HyperLogLog::with_registers_fn(6, |i| (i % 5 + 3) as u8).unwrap();
+        assert_eq!(alpha_m(hll.m), SMALL_PRECISION_ALPHA[2]);
+        assert!((alpha_m(hll.m) - exact_alpha).abs() < 1e-9);
+
+        // Registers of 3..=7 keep the estimate in the uncorrected range
+        let truth = exact_alpha * m * m / register_sum(&hll.registers);
+        let formula_count = formula_alpha * m * m / register_sum(&hll.registers);
+        let (count, quality) = hll.estimate();
+        assert_eq!(quality, EstimateQuality::Good);
+        assert!(
+            (count - truth).abs() < (formula_count - truth).abs(),
+            "Table count {} should be closer to {} than formula count {}",
+            count,
+            truth,
+            formula_count
+        );
+    }
+
+    #[test]
+    fn test_estimate_from_registers() {
+        let mut hll = HyperLogLog::new(11).unwrap();
+        for i in 0..20000 {
+            hll.add(&i);
+        }
+        let registers = hll.registers.clone();
+
+        let estimate = estimate_from_registers(11, &registers).unwrap();
+        let rebuilt = HyperLogLog::from_registers(11, registers.clone()).unwrap();
+        assert_eq!(estimate, rebuilt.count());
+        assert_eq!(estimate, hll.count());
+
+        assert!(estimate_from_registers(10, &registers).is_err());
+        assert!(estimate_from_registers(3, &registers[..8]).is_err());
+        assert!(estimate_from_registers(4, &[62; 16]).is_err());
+    }
+
+    #[test]
+    fn test_from_registers() {
+        let mut registers = vec![0u8; 1 << 10];
+        registers[3] = 2;
+        registers[700] = 55;
+        let hll = HyperLogLog::from_registers(10, registers.clone()).unwrap();
+        assert_eq!(hll.precision(), 10);
+        assert_eq!(hll.nonzero_registers(), 2);
+        assert_eq!(hll.dense_registers().as_ref(), &registers[..]);
+        assert_eq!(hll.count(), estimate_from_registers(10, &registers).unwrap());
+
+        // Wrong length for the precision
+        assert!(HyperLogLog::from_registers(10, vec![0; 1 << 11]).is_err());
+        assert!(HyperLogLog::from_registers(10, Vec::new()).is_err());
+        assert!(matches!(
+            HyperLogLog::from_registers(3, vec![0; 8]),
+            Err(HllError::InvalidPrecision(3))
+        ));
+
+        // 65 - 10 is the largest rank precision 10 produces
+        registers[700] = 56;
+        assert!(HyperLogLog::from_registers(10, registers).is_err());
+    }
+
+    #[test]
+    fn test_register_accessors() {
+        for mut hll in [
+            HyperLogLog::new(10).unwrap(),
+            HyperLogLog::new_sparse(10).unwrap(),
+            HyperLogLog::new_packed(10).unwrap(),
+        ] {
+            assert_eq!(hll.registers().len(), hll.num_registers());
+            assert!(hll.registers().iter().all(|&val| val == 0));
+
+            hll.add_str("alice");
+            let (idx, rank) = hll.index_and_rank(hll.hash_element(&"alice"));
+            assert_eq!(hll.register(idx), Some(rank));
+            let nonzero: Vec<usize> = (0..hll.num_registers()).filter(|&i| hll.registers()[i] != 0).collect();
+            assert_eq!(nonzero, vec![idx]);
+            assert_eq!(hll.registers()[idx], rank);
+
+            assert!(hll.register(1023).is_some());
+            assert_eq!(hll.register(1024), None);
+        }
+
+        // Dense sketches lend their array
+        let hll = HyperLogLog::new(10).unwrap();
+        assert!(matches!(hll.registers(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_memory_bytes() {
+        let mut dense = HyperLogLog::new(14).unwrap();
+        assert_eq!(dense.memory_bytes(), 16384);
+        dense.add_all(0..1000);
+        assert_eq!(dense.memory_bytes(), 16384);
+
+        let mut sparse = HyperLogLog::new_sparse(14).unwrap();
+        sparse.add_all(0..100);
+        assert!(sparse.is_sparse());
+        assert!(sparse.memory_bytes() >= 100 * SPARSE_ENTRY_BYTES);
+        assert!(sparse.memory_bytes() < 16384 / 4, "Got {}", sparse.memory_bytes());
+
+        // Converting gives the pairs back
+        sparse.densify();
+        assert_eq!(sparse.memory_bytes(), 16384);
+
+        assert_eq!(HyperLogLog::new_packed(14).unwrap().memory_bytes(), 12288);
+        assert_eq!(dense.summary().memory_bytes, dense.memory_bytes());
+    }
+
+    #[test]
+    fn test_add_hash() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+
+        // All ones: the last register, and no leading zeros after the index
+        assert!(hll.add_hash(u64::MAX));
+        assert_eq!(hll.register(4095), Some(1));
+        assert!(!hll.add_hash(u64::MAX));
+
+        // Index 1, then 11 zeros before the first set bit
+        assert!(hll.add_hash(1 << 52 | 1 << 40));
+        assert_eq!(hll.register(1), Some(12));
+
+        // Nothing set after the index: one past the rank window
+        assert!(hll.add_hash(0));
+        assert_eq!(hll.register(0), Some(hll.max_register_value()));
+
+        assert_eq!(hll.nonzero_registers(), 3);
+
+        // The hashers add through the same path
+        let mut via_hasher = HyperLogLog::new(12).unwrap();
+        via_hasher.add_bytes(b"element");
+        let mut via_hash = HyperLogLog::new(12).unwrap();
+        via_hash.add_hash(HasherKind::XxHash64.hash_bytes(0, b"element"));
+        assert_eq!(via_hasher, via_hash);
+    }
+
+    #[test]
+    fn test_histogram() {
+        for mut hll in [
+            HyperLogLog::new(12).unwrap(),
+            HyperLogLog::new_sparse(12).unwrap(),
+            HyperLogLog::new_packed(12).unwrap(),
+        ] {
+            let histogram = hll.histogram();
+            assert_eq!(histogram.len(), usize::from(hll.max_register_value()) + 1);
+            assert_eq!(histogram[0], 4096);
+            assert!(histogram[1..].iter().all(|&n| n == 0));
+
+            hll.add_all(0..5000u32);
+            let histogram = hll.histogram();
+            assert_eq!(histogram.iter().sum::<u32>(), 4096);
+            assert_eq!(histogram[0] as usize, 4096 - hll.nonzero_registers());
+            // About half the nonzero registers hold 1, a quarter 2, ...
+            assert!(histogram[1] > histogram[2] && histogram[2] > histogram[3]);
+            for (val, &n) in histogram.iter().enumerate() {
+                let expected = hll.registers().iter().filter(|&&r| usize::from(r) == val).count();
+                assert_eq!(n as usize, expected, "Wrong count of {}", val);
+            }
+        }
+    }
+
+    #[test]
+    fn test_maybe_contains() {
+        for mut hll in [
+            HyperLogLog::new(12).unwrap(),
+            HyperLogLog::new_sparse(12).unwrap(),
+            HyperLogLog::new_packed(12).unwrap(),
+        ] {
+            assert!(!hll.maybe_contains(&"anything"));
+
+            hll.add_all(0..1000u32);
+            assert!((0..1000u32).all(|i| hll.maybe_contains(&i)));
+
+            // Unseen elements are rejected while most registers are still low
+            let false_positives = (1000..11_000u32).filter(|i| hll.maybe_contains(i)).count();
+            assert!(false_positives < 3000, "Got {}", false_positives);
+        }
+    }
+
+    #[test]
+    fn test_add_reports_change() {
+        for mut hll in [
+            HyperLogLog::new(12).unwrap(),
+            HyperLogLog::new_sparse(12).unwrap(),
+            HyperLogLog::new_packed(12).unwrap(),
+        ] {
+            assert!(hll.add_str("a"));
+            assert!(!hll.add_str("a"));
+            assert!(hll.add_bytes(b"a"));
+            assert!(!hll.add_bytes(b"a"));
+
+            // Every element either changed a register or left the sketch as is
+            let mut changes = 0;
+            for i in 0..10_000 {
+                let before = hll.clone();
+                let changed = hll.add(&i);
+                assert_eq!(changed, hll != before);
+                changes += usize::from(changed);
+            }
+            assert!(changes > 0 && changes < 10_000);
+        }
+
+        let mut hll = HyperLogLog::new(12).unwrap().with_max_element_len(4);
+        assert!(!hll.add_str("too long"));
+        assert!(!hll.add_bytes(b"too long"));
+    }
+
+    #[test]
+    fn test_nonzero_registers_iter() {
+        for mut hll in [
+            HyperLogLog::new(12).unwrap(),
+            HyperLogLog::new_sparse(12).unwrap(),
+            HyperLogLog::new_packed(12).unwrap(),
+        ] {
+            assert_eq!(hll.nonzero_registers_iter().count(), 0);
+
+            for element in ["a", "b", "c"] {
+                hll.add_str(element);
+            }
+            let entries: Vec<(usize, u8)> = hll.nonzero_registers_iter().collect();
+            assert!(!entries.is_empty() && entries.len() <= 3, "Got {:?}", entries);
+            assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            for &(idx, val) in &entries {
+                assert_eq!(hll.register(idx), Some(val));
+            }
+
+            hll.add_all(0..5000);
+            let expected: Vec<(usize, u8)> = hll
+                .registers()
+                .iter()
+                .copied()
+                .enumerate()
+                .filter(|&(_, val)| val != 0)
+                .collect();
+            assert_eq!(hll.nonzero_registers_iter().collect::<Vec<_>>(), expected);
+            assert_eq!(expected.len(), hll.nonzero_registers());
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..5000 {
+            hll.add(&i);
+        }
+
+        let summary = hll.summary();
+        assert_eq!(summary.precision, 12);
+        assert_eq!(summary.count, hll.count());
+        assert!((4500..=5500).contains(&summary.count), "Got {}", summary.count);
+        assert_eq!(summary.memory_bytes, 4096);
+        assert_eq!(summary.fill_ratio, hll.fill_ratio());
+        assert_eq!(summary.quality, hll.estimate_quality());
+    }
+
+    #[test]
+    fn test_count_raw() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        for i in 0..50 {
+            hll.add(&i);
+        }
+
+        // Linear counting tracks the truth; the raw estimate is far above it
+        let count = hll.count();
+        let raw = hll.count_raw();
+        assert!((45..=55).contains(&count), "count() should be ~50, got {}", count);
+        assert!(raw > 500, "Raw estimate should overshoot in the small range, got {}", raw);
+
+        // Once the raw range is reached the two agree
+        for i in 0..20000 {
+            hll.add(&i);
+        }
+        assert_eq!(hll.count_raw(), hll.count());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        for input in GOLDEN_INPUTS {
+            hll.add_str(input);
+        }
+
+        let fingerprint = hll.fingerprint();
+        assert_eq!(fingerprint.len(), 16);
+        assert_eq!(hll.clone().fingerprint(), fingerprint);
+
+        let mut modified = hll.clone();
+        modified.registers[0] += 1;
+        assert_ne!(modified.fingerprint(), fingerprint);
+
+        // Pinned, so the fingerprint stays valid as a persistent cache key
+        assert_eq!(fingerprint, "ea50ded36ba2ebb0");
+    }
+
+    #[test]
+    fn test_calibration() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+        for i in 0..10000 {
+            hll.add(&i);
+        }
+
+        let base = hll.count();
+        assert_eq!(hll.calibration(), 1.0);
+        assert_eq!(hll.count_calibrated(1.0), base);
+
+        let scaled = hll.count_calibrated(1.05);
+        let ratio = scaled as f64 / base as f64;
+        assert!((ratio - 1.05).abs() < 0.001, "Ratio should be ~1.05, got {}", ratio);
+
+        // A stored factor applies to count() automatically
+        let calibrated = hll.with_calibration(1.05);
+        assert_eq!(calibrated.count(), scaled);
+    }
+
+    #[test]
+    fn test_count_with_extra() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..5000 {
+            hll.add(&i);
+        }
+        let before = hll.registers.clone();
+
+        let mut expected = hll.clone();
+        for i in 4000..8000 {
+            expected.add(&i);
+        }
+
+        assert_eq!(hll.count_with_extra(4000..8000), expected.count());
+        assert_eq!(hll.registers, before, "count_with_extra must not mutate self");
+    }
+
+    #[test]
+    fn test_merge_disjoint() {
+        let mut hll1 = HyperLogLog::new(10).unwrap();
+        let mut hll2 = HyperLogLog::new(10).unwrap();
+
+        for i in 0..100 {
+            hll1.add(&i);
+        }
+
+        for i in 100..200 {
+            hll2.add(&i);
+        }
+
+        hll1.merge(&hll2).unwrap();
+        let count = hll1.count();
+
+        // Should estimate around 200
+        assert!(count > 150 && count < 250, "Count should be ~200, got {}", count);
+    }
+
+    #[test]
+    fn test_merge_overlapping() {
+        let mut hll1 = HyperLogLog::new(12).unwrap();
+        let mut hll2 = HyperLogLog::new(12).unwrap();
+
+        // 50% overlap
+        for i in 0..150 {
+            hll1.add(&i);
+        }
+
+        for i in 100..250 {
+            hll2.add(&i);
+        }
+
+        let count1 = hll1.count();
+        let count2 = hll2.count();
+
+        hll1.merge(&hll2).unwrap();
+        let merged_count = hll1.count();
+
+        // Merged should be around 250 (0..250)
+        assert!(
+            merged_count > 200 && merged_count < 300,
+            "Merged count should be ~250, got {}. Individual counts: {}, {}",
+            merged_count,
+            count1,
+            count2
+        );
+    }
+
+    #[test]
+    fn test_merge_precision_mismatch() {
+        let mut hll1 = HyperLogLog::new(10).unwrap();
+        let hll2 = HyperLogLog::new(12).unwrap();
+
+        let result = hll1.merge(&hll2);
+        assert!(result.is_err(), "Should fail to merge different precisions");
+    }
+
+    #[test]
+    fn test_with_seed() {
+        let mut seeded = HyperLogLog::with_seed(10, 42).unwrap();
+        let mut unseeded = HyperLogLog::new(10).unwrap();
+        for i in 0..1000 {
+            seeded.add(&i);
+            unseeded.add(&i);
+        }
+        assert_eq!(seeded.seed(), 42);
+        assert_eq!(unseeded.seed(), 0);
+        assert_ne!(seeded.registers, unseeded.registers);
+
+        let error = (seeded.count() as f64 - 1000.0).abs() / 1000.0;
+        assert!(error < 0.1, "Seeded estimate off by {:.2}%", error * 100.0);
+
+        let err = seeded.merge(&unseeded).unwrap_err();
+        assert!(matches!(err, HllError::Storage(_)), "Unexpected error: {}", err);
+        assert!(err.to_string().contains("seed"), "Unexpected error: {}", err);
+        assert!(seeded.merge_normalizing(&unseeded).is_err());
+
+        let mut same_seed = HyperLogLog::with_seed(10, 42).unwrap();
+        same_seed.merge(&seeded).unwrap();
+        assert_eq!(same_seed.registers, seeded.registers);
+        assert_eq!(seeded.fold_to(8).unwrap().seed(), 42);
+    }
+
+    #[test]
+    fn test_count_hllpp() {
+        assert_eq!(HyperLogLog::new(14).unwrap().count_hllpp(), 0);
+
+        // 100k is in the 2.5m..5m band at p=15, where `count` is biased
+        let n = 100_000;
+        let trials = 20;
+        let (mut classic_error, mut hllpp_error) = (0.0, 0.0);
+        for seed in 0..trials {
+            let mut hll = HyperLogLog::with_seed(15, seed).unwrap();
+            for i in 0..n {
+                hll.add(&i);
+            }
+            classic_error += (hll.count() as f64 - n as f64).abs() / n as f64;
+            hllpp_error += (hll.count_hllpp() as f64 - n as f64).abs() / n as f64;
+        }
+        classic_error /= trials as f64;
+        hllpp_error /= trials as f64;
+
+        assert!(
+            hllpp_error < classic_error,
+            "HLL++ error {:.3}% not below classic {:.3}%",
+            hllpp_error * 100.0,
+            classic_error * 100.0
+        );
+        assert!(hllpp_error < 0.01, "HLL++ error {:.3}%", hllpp_error * 100.0);
+    }
+
+    #[test]
+    fn test_count_beta() {
+        assert_eq!(HyperLogLog::new(14).unwrap().count_beta(), 0);
+
+        // Mean relative error of `count` and `count_beta` at p=14 (m = 16384)
+        let errors = |n: u64, trials: u64| {
+            let (mut classic_error, mut beta_error) = (0.0, 0.0);
+            for seed in 0..trials {
+                let mut hll = HyperLogLog::with_seed(14, seed).unwrap();
+                hll.add_all(0..n);
+                classic_error += (hll.count() as f64 - n as f64).abs() / n as f64;
+                beta_error += (hll.count_beta() as f64 - n as f64).abs() / n as f64;
+            }
+            (classic_error / trials as f64, beta_error / trials as f64)
+        };
+
+        // 1k and 10k are in linear counting range, where `count` is already
+        // near-optimal and beta only has to keep up
+        for n in [1_000, 10_000] {
+            let (classic_error, beta_error) = errors(n, 20);
+            assert!(
+                beta_error < classic_error * 1.25 + 0.001,
+                "At {}: beta error {:.3}% vs classic {:.3}%",
+                n,
+                beta_error * 100.0,
+                classic_error * 100.0
+            );
+        }
+
+        // 50k is past the 2.5m switch to the raw estimate, which is still
+        // biased upwards there: beta wins
+        let (classic_error, beta_error) = errors(50_000, 20);
+        assert!(
+            beta_error < classic_error,
+            "Beta error {:.3}% not below classic {:.3}%",
+            beta_error * 100.0,
+            classic_error * 100.0
+        );
+        assert!(beta_error < 0.01, "Beta error {:.3}%", beta_error * 100.0);
+
+        // At 1M no register is zero, so beta vanishes and both are the raw estimate
+        let mut hll = HyperLogLog::new(14).unwrap();
+        hll.add_all(0..1_000_000u64);
+        assert_eq!(hll.count_beta(), hll.count());
+        let error = (hll.count_beta() as f64 - 1e6).abs() / 1e6;
+        assert!(error < 0.03, "Beta error {:.3}%", error * 100.0);
+    }
+
+    #[test]
+    fn test_bias_tables() {
+        for table in 0..RAW_ESTIMATE_DATA.len() {
+            let raw_data = RAW_ESTIMATE_DATA[table];
+            assert_eq!(raw_data.len(), BIAS_DATA[table].len());
+            assert!(raw_data.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+
+        // An exact table hit averages the hit and its five closest neighbors
+        let expected = BIAS_DATA[0][10..16].iter().sum::<f64>() / 6.0;
+        assert!((estimate_bias(0, RAW_ESTIMATE_DATA[0][13]) - expected).abs() < 1e-9);
+        // Off either end, the outermost entries are used
+        let expected = BIAS_DATA[0][..6].iter().sum::<f64>() / 6.0;
+        assert!((estimate_bias(0, 0.0) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_union() {
+        let mut a = HyperLogLog::new(12).unwrap();
+        let mut b = HyperLogLog::new(12).unwrap();
+        for i in 0..3000 {
+            a.add(&i);
+        }
+        for i in 2000..5000 {
+            b.add(&i);
+        }
+        let (a_registers, b_registers) = (a.registers.clone(), b.registers.clone());
+
+        let merged = {
+            let mut c = a.clone();
+            c.merge(&b).unwrap();
+            c
+        };
+        assert_eq!(a.union(&b).unwrap().count(), merged.count());
+        assert_eq!(a.registers, a_registers);
+        assert_eq!(b.registers, b_registers);
+
+        let p10 = HyperLogLog::new(10).unwrap();
+        let err = a.union(&p10).unwrap_err();
+        assert_eq!(err.to_string(), a.clone().merge(&p10).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_intersect_count() {
+        // Each set shares half of its elements with the other
+        let mut a = HyperLogLog::new(14).unwrap();
+        let mut b = HyperLogLog::new(14).unwrap();
+        for i in 0..20000 {
+            a.add(&i);
+        }
+        for i in 10000..30000 {
+            b.add(&i);
+        }
+
+        let overlap = a.intersect_count(&b).unwrap();
+        let error = (overlap as f64 - 10000.0).abs() / 10000.0;
+        assert!(error < 0.1, "Intersection {} off by {:.2}%", overlap, error * 100.0);
+        assert_eq!(b.intersect_count(&a).unwrap(), overlap);
+
+        let mut disjoint = HyperLogLog::new(14).unwrap();
+        for i in 100000..100100 {
+            disjoint.add(&i);
+        }
+        assert!(a.intersect_count(&disjoint).unwrap() < 200);
+        assert!(a.intersect_count(&HyperLogLog::new(12).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_jaccard() {
+        let mut a = HyperLogLog::new(14).unwrap();
+        let mut b = HyperLogLog::new(14).unwrap();
+        let mut disjoint = HyperLogLog::new(14).unwrap();
+        for i in 0..10000 {
+            a.add(&i);
+            b.add(&i);
+            disjoint.add(&(i + 1_000_000));
+        }
+
+        assert!(a.jaccard(&b).unwrap() > 0.99);
+        assert!(a.jaccard(&disjoint).unwrap() < 0.02);
+
+        let empty = HyperLogLog::new(14).unwrap();
+        assert_eq!(empty.jaccard(&empty).unwrap(), 0.0);
+        assert!(a.jaccard(&HyperLogLog::new(12).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_merge_all() {
+        let sketches: Vec<HyperLogLog> = (0..10)
+            .map(|shard| {
+                let mut hll = HyperLogLog::new(12).unwrap();
+                for i in shard * 1000..(shard + 1) * 1000 + 500 {
+                    hll.add(&i);
+                }
+                hll
+            })
+            .collect();
+
+        let merged = HyperLogLog::merge_all(&sketches).unwrap();
+        let mut expected = sketches[0].clone();
+        for hll in &sketches[1..] {
+            expected.merge(hll).unwrap();
+        }
+        assert_eq!(merged.registers, expected.registers);
+        let error = (merged.count() as f64 - 10500.0).abs() / 10500.0;
+        assert!(error < 0.05, "Merged estimate off by {:.2}%", error * 100.0);
+
+        assert!(matches!(HyperLogLog::merge_all(&[]), Err(HllError::InvalidInput(_))));
+        let mut mixed = sketches.clone();
+        mixed.push(HyperLogLog::new(10).unwrap());
+        assert!(matches!(HyperLogLog::merge_all(&mixed), Err(HllError::Storage(_))));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_merge_all_parallel_matches_sequential() {
+        let sketches: Vec<HyperLogLog> = (0..1000u64)
+            .map(|shard| {
+                let mut hll = HyperLogLog::new(12).unwrap();
+                for i in shard * 50..shard * 50 + 80 {
+                    hll.add(&i);
+                }
+                hll
+            })
+            .collect();
+
+        let expected = HyperLogLog::merge_all(&sketches).unwrap();
+        for threads in [1, 2, 7] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let merged = pool.install(|| HyperLogLog::merge_all_parallel(&sketches)).unwrap();
+            assert_eq!(merged, expected);
+            assert_eq!(merged.to_bytes(), expected.to_bytes());
+        }
+
+        // Sparse inputs stay sparse just as they would sequentially
+        let sparse: Vec<HyperLogLog> = (0..4u64)
+            .map(|shard| {
+                let mut hll = HyperLogLog::new_sparse(14).unwrap();
+                hll.add_all(shard * 10..shard * 10 + 20);
+                hll
+            })
+            .collect();
+        let merged = HyperLogLog::merge_all_parallel(&sparse).unwrap();
+        assert!(merged.is_sparse());
+        assert_eq!(merged.to_bytes(), HyperLogLog::merge_all(&sparse).unwrap().to_bytes());
+
+        assert!(matches!(HyperLogLog::merge_all_parallel(&[]), Err(HllError::InvalidInput(_))));
+        let mut mixed = sketches.clone();
+        mixed.push(HyperLogLog::new(10).unwrap());
+        assert!(matches!(HyperLogLog::merge_all_parallel(&mixed), Err(HllError::Storage(_))));
+    }
+
+    #[test]
+    fn test_symmetric_difference_count() {
+        let mut a = HyperLogLog::new(14).unwrap();
+        let mut b = HyperLogLog::new(14).unwrap();
+        for i in 0..1000 {
+            a.add(&i);
+        }
+        for i in 500..1500 {
+            b.add(&i);
+        }
+
+        let difference = a.symmetric_difference_count(&b).unwrap();
+        assert!((900..=1100).contains(&difference), "Expected ~1000, got {}", difference);
+
+        // Same half overlap past the small-range correction: 100,000 in
+        // exactly one of the sets, within a few standard errors of the union
+        let mut c = HyperLogLog::new(14).unwrap();
+        let mut d = HyperLogLog::new(14).unwrap();
+        c.add_all(0..100_000u32);
+        d.add_all(50_000..150_000u32);
+        let difference = c.symmetric_difference_count(&d).unwrap();
+        assert!(
+            (difference as f64 - 100_000.0).abs() < 5_000.0,
+            "Expected ~100000, got {}",
+            difference
+        );
+        assert_eq!(d.symmetric_difference_count(&c).unwrap(), difference);
+
+        assert_eq!(a.symmetric_difference_count(&a).unwrap(), 0);
+        assert!(a.symmetric_difference_count(&HyperLogLog::new(12).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_merge_same_data() {
+        let mut hll1 = HyperLogLog::new(10).unwrap();
+        let mut hll2 = HyperLogLog::new(10).unwrap();
+
+        // Both see same data
+        for i in 0..100 {
+            hll1.add(&i);
+            hll2.add(&i);
+        }
+
+        let count_before = hll1.count();
+        hll1.merge(&hll2).unwrap();
+        let count_after = hll1.count();
+
+        // Count shouldn't change much (within 10%)
+        let diff = ((count_after as f64 - count_before as f64) / count_before as f64).abs();
+        assert!(diff < 0.1, "Counts should be similar: {} vs {}", count_before, count_after);
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+
+        for i in 0..1000 {
+            hll.add(&i);
+        }
+
+        let hll_clone = hll.clone();
+
+        assert_eq!(hll.precision(), hll_clone.precision());
+        assert_eq!(hll.count(), hll_clone.count());
+        assert_eq!(hll.registers, hll_clone.registers);
+    }
+
+    #[test]
+    fn test_serialization() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+
+        for i in 0..5000 {
+            hll.add(&i);
+        }
+
+        // Serialize
+        let serialized = serde_json::to_string(&hll).unwrap();
+
+        // Deserialize
+        let deserialized: HyperLogLog = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(hll.precision(), deserialized.precision());
+        assert_eq!(hll.count(), deserialized.count());
+        assert_eq!(hll.registers, deserialized.registers);
+    }
+
+    #[test]
+    fn test_custom_layout() {
+        let mut hll = HyperLogLog::with_layout(12, 40).unwrap();
+        assert_eq!(hll.precision(), 12);
+        assert_eq!(hll.rank_bits(), 40);
+
+        for i in 0..10000 {
+            hll.add(&i);
+        }
+
+        let count = hll.count();
+        let error_rate = ((count as f64 - 10000.0) / 10000.0).abs();
+        assert!(error_rate < 0.05, "Error rate: {:.2}%", error_rate * 100.0);
+
+        let serialized = serde_json::to_string(&hll).unwrap();
+        let deserialized: HyperLogLog = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.rank_bits(), 40);
+        assert_eq!(deserialized.registers, hll.registers);
+        assert_eq!(deserialized.count(), count);
+    }
+
+    #[test]
+    fn test_invalid_layout() {
+        assert!(HyperLogLog::with_layout(12, 0).is_err());
+        assert!(HyperLogLog::with_layout(12, 53).is_err());
+        assert!(HyperLogLog::with_layout(3, 20).is_err());
+        assert!(HyperLogLog::with_layout(12, 52).is_ok());
+    }
+
+    #[test]
+    fn test_merge_layout_mismatch() {
+        let mut hll1 = HyperLogLog::with_layout(10, 30).unwrap();
+        let hll2 = HyperLogLog::new(10).unwrap();
+
+        assert!(
+            matches!(hll1.merge(&hll2), Err(HllError::InvalidLayout(_))),
+            "Should fail to merge different layouts"
+        );
+    }
+
+    #[test]
+    fn test_merge_normalizing() {
+        // Matching layouts behave like a plain merge
+        let mut a = HyperLogLog::new(12).unwrap();
+        let mut b = HyperLogLog::new(12).unwrap();
+        for i in 0..3000 {
+            a.add(&i);
+            b.add(&(i + 2000));
+        }
+        let mut plain = a.clone();
+        plain.merge(&b).unwrap();
+        a.merge_normalizing(&b).unwrap();
+        assert_eq!(a.registers, plain.registers);
+
+        // Mixed precision and rank bits meet at the narrowest layout
+        let mut wide = HyperLogLog::new(12).unwrap();
+        let mut narrow = HyperLogLog::with_layout(10, 20).unwrap();
+        let mut direct = HyperLogLog::with_layout(10, 20).unwrap();
+        for i in 0..3000 {
+            wide.add(&i);
+            narrow.add(&(i + 2000));
+            direct.add(&i);
+            direct.add(&(i + 2000));
+        }
+
+        assert!(matches!(wide.clone().merge(&narrow), Err(HllError::Storage(_))));
+        wide.merge_normalizing(&narrow).unwrap();
+        assert_eq!(wide.precision(), 10);
+        assert_eq!(wide.rank_bits(), 20);
+        assert_eq!(wide.registers, direct.registers);
+    }
+
+    #[test]
+    fn test_merge_optimized() {
+        let hlls: Vec<HyperLogLog> = [16, 14, 16, 12, 14, 12, 16]
+            .iter()
+            .enumerate()
+            .map(|(n, &precision)| {
+                let mut hll = HyperLogLog::new(precision).unwrap();
+                for i in (n * 500)..(n * 500 + 1000) {
+                    hll.add(&i);
+                }
+                hll
+            })
+            .collect();
+
+        FOLDS.with(|folds| folds.set(0));
+        let mut naive = hlls[0].clone();
+        for hll in &hlls[1..] {
+            naive.merge_normalizing(hll).unwrap();
+        }
+        let naive_folds = FOLDS.with(|folds| folds.replace(0));
+
+        let optimized = HyperLogLog::merge_optimized(&hlls).unwrap();
+        let optimized_folds = FOLDS.with(|folds| folds.get());
+
+        assert_eq!(optimized.precision(), 12);
+        assert_eq!(optimized.registers, naive.registers);
+        assert_eq!(optimized_folds, 2, "One fold per precision above the minimum");
+        assert!(optimized_folds < naive_folds, "{} vs {} folds", optimized_folds, naive_folds);
+
+        assert!(HyperLogLog::merge_optimized(&[]).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_without_layout() {
+        let json = format!(r#"{{"precision":4,"m":16,"registers":{:?}}}"#, vec![1u8; 16]);
+        let hll: HyperLogLog = serde_json::from_str(&json).unwrap();
+        assert_eq!(hll.rank_bits(), 60);
+
+        let bad_len = format!(r#"{{"precision":4,"m":16,"registers":{:?}}}"#, vec![1u8; 8]);
+        assert!(serde_json::from_str::<HyperLogLog>(&bad_len).is_err());
+    }
+
+    #[test]
+    fn test_serde_register_encodings() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        hll.add_all(0..1000);
+
+        // JSON carries the registers as one base64 string
+        let json = serde_json::to_value(&hll).unwrap();
+        let encoded = json["registers"].as_str().unwrap();
+        assert_eq!(BASE64.decode(encoded).unwrap(), hll.registers);
+        assert_eq!(serde_json::from_value::<HyperLogLog>(json.clone()).unwrap(), hll);
+
+        // The array of numbers written before still loads; it takes at least
+        // two characters per register against base64's four per three
+        let mut array_form = json;
+        array_form["registers"] = serde_json::json!(hll.registers);
+        let array_json = serde_json::to_string(&array_form).unwrap();
+        assert_eq!(serde_json::from_str::<HyperLogLog>(&array_json).unwrap(), hll);
+        assert!(serde_json::to_string(&hll).unwrap().len() * 4 < array_json.len() * 3);
+
+        let bad = r#"{"precision":4,"registers":"not base64!"}"#;
+        let err = serde_json::from_str::<HyperLogLog>(bad).unwrap_err();
+        assert!(err.to_string().contains("invalid base64 registers"), "{}", err);
+
+        // Binary formats get raw bytes, and read the array form too
+        #[cfg(feature = "file-storage")]
+        {
+            let msgpack = rmp_serde::to_vec_named(&hll).unwrap();
+            assert_eq!(rmp_serde::from_slice::<HyperLogLog>(&msgpack).unwrap(), hll);
+            let old = rmp_serde::to_vec_named(&array_form).unwrap();
+            assert_eq!(rmp_serde::from_slice::<HyperLogLog>(&old).unwrap(), hll);
+        }
+        // bincode writes bytes exactly as it wrote the array
+        #[cfg(feature = "bincode")]
+        assert_eq!(
+            bincode::serialize(&RegisterBytes(&hll.registers)).unwrap(),
+            bincode::serialize(&hll.registers).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_serde_without_m() {
+        let mut hll = HyperLogLog::new(4).unwrap();
+        hll.add_str("a");
+        let json = serde_json::to_value(&hll).unwrap();
+        assert!(json.get("m").is_none());
+        assert_eq!(serde_json::from_value::<HyperLogLog>(json).unwrap(), hll);
+
+        let json = format!(r#"{{"precision":4,"registers":{:?}}}"#, vec![1u8; 16]);
+        assert_eq!(serde_json::from_str::<HyperLogLog>(&json).unwrap().num_registers(), 16);
+
+        // The register count must match the precision, with or without m
+        for bad in [
+            format!(r#"{{"precision":4,"registers":{:?}}}"#, vec![1u8; 32]),
+            format!(r#"{{"precision":5,"registers":{:?}}}"#, vec![1u8; 16]),
+            format!(r#"{{"precision":5,"m":16,"registers":{:?}}}"#, vec![1u8; 16]),
+            r#"{"precision":4,"m":32,"registers":[],"sparse":[[1,1]]}"#.to_string(),
+        ] {
+            assert!(serde_json::from_str::<HyperLogLog>(&bad).is_err(), "Accepted {}", bad);
+        }
+    }
+
+    #[test]
+    fn test_fold_matches_direct_precision() {
+        let mut high = HyperLogLog::new(14).unwrap();
+        let mut low = HyperLogLog::new(10).unwrap();
+
+        for i in 0..20000 {
+            high.add(&i);
+            low.add(&i);
+        }
+
+        let folded = high.fold_to(10).unwrap();
+        assert_eq!(folded.precision(), 10);
+        assert_eq!(folded.rank_bits(), low.rank_bits());
+        assert_eq!(folded.registers, low.registers);
+
+        assert!(high.fold_to(15).is_err());
+        assert!(high.fold_to(3).is_err());
+    }
+
+    #[test]
+    fn test_fold_16_to_14_keeps_count() {
+        let mut high = HyperLogLog::new(16).unwrap();
+        high.add_all(0..200_000);
+
+        let folded = high.fold_to(14).unwrap();
+        assert_eq!(folded.precision(), 14);
+        assert_eq!(folded.num_registers(), 1 << 14);
+
+        // Within three standard errors of the lower precision
+        let tolerance = 3.0 * folded.relative_error() * high.count() as f64;
+        let diff = (folded.count() as f64 - high.count() as f64).abs();
+        assert!(diff < tolerance, "Folded {} vs original {}", folded.count(), high.count());
+
+        // And mergeable with a sketch built at precision 14
+        let mut other = HyperLogLog::new(14).unwrap();
+        other.add_all(200_000..250_000);
+        let mut union = folded.clone();
+        union.merge(&other).unwrap();
+        let error = (union.count() as f64 - 250_000.0).abs() / 250_000.0;
+        assert!(error < 3.0 * union.relative_error(), "Union counted {}", union.count());
 
-        let m = 1 << precision;
-        Ok(HyperLogLog {
-            precision,
-            m,
-            registers: vec![0; m],
-        })
+        assert!(matches!(folded.fold_to(16), Err(HllError::InvalidInput(_))));
+        assert!(matches!(folded.fold_to(3), Err(HllError::InvalidPrecision(3))));
     }
 
-    /// Add an element to the HyperLogLog
-    pub fn add<T: Hash>(&mut self, element: &T) {
-        let hash = self.hash_element(element);
+    #[test]
+    fn test_binary_round_trip() {
+        let mut hll = HyperLogLog::with_layout(10, 30).unwrap();
+        for i in 0..5000 {
+            hll.add(&i);
+        }
 
-        // Use first 'precision' bits for register index
-        let idx = (hash >> (64 - self.precision)) as usize;
+        let bytes = hll.to_bytes();
+        assert_eq!(bytes[3], FORMAT_VERSION);
+        assert_eq!(bytes.len(), HEADER_LEN + packed_len(1024, bytes[6]));
 
-        // Count leading zeros in remaining bits + 1
-        let remaining = hash << self.precision;
-        let leading_zeros = if remaining == 0 {
-            64 - self.precision + 1
-        } else {
-            remaining.leading_zeros() as u8 + 1
-        };
+        let decoded = HyperLogLog::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.precision(), 10);
+        assert_eq!(decoded.rank_bits(), 30);
+        assert_eq!(decoded.registers, hll.registers);
+    }
 
-        // Store max leading zeros for this register
-        if leading_zeros > self.registers[idx] {
-            self.registers[idx] = leading_zeros;
+    #[test]
+    fn test_binary_adaptive_width() {
+        // Low cardinality keeps every register below 16
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..200 {
+            hll.add(&i);
         }
+
+        let bytes = hll.to_bytes();
+        assert_eq!(bytes[6], 4);
+        assert_eq!(bytes.len(), HEADER_LEN + 4096 / 2);
+        assert_eq!(HyperLogLog::from_bytes(&bytes).unwrap().registers, hll.registers);
+
+        // Registers of 16 and above need the 6-bit layout
+        let wide = HyperLogLog::with_registers_fn(12, |i| (i % 40) as u8).unwrap();
+        let bytes = wide.to_bytes();
+        assert_eq!(bytes[6], 6);
+        assert_eq!(bytes.len(), HEADER_LEN + 4096 * 6 / 8);
+        assert_eq!(HyperLogLog::from_bytes(&bytes).unwrap().registers, wide.registers);
     }
 
-    /// Add a raw string element (for Redis compatibility)
-    pub fn add_str(&mut self, element: &str) {
-        self.add(&element);
+    #[test]
+    fn test_binary_reads_version_1() {
+        let hll = HyperLogLog::with_registers_fn(10, |i| (i % 7) as u8).unwrap();
+        let mut v1 = b"HLL".to_vec();
+        v1.extend_from_slice(&[1, 10, hll.rank_bits()]);
+        v1.extend_from_slice(&hll.registers);
+
+        assert_eq!(HyperLogLog::from_bytes(&v1).unwrap().registers, hll.registers);
     }
 
-    /// Estimate cardinality
-    pub fn count(&self) -> u64 {
-        let m = self.m as f64;
-
-        // Calculate raw estimate
-        let sum: f64 = self.registers.iter()
-            .map(|&val| 2.0_f64.powi(-(val as i32)))
-            .sum();
-
-        let alpha = self.alpha_m();
-        let raw_estimate = alpha * m * m / sum;
-
-        // Apply bias correction for different ranges
-        if raw_estimate <= 2.5 * m {
-            // Small range correction
-            let zeros = self.registers.iter().filter(|&&x| x == 0).count();
-            if zeros != 0 {
-                return (m * (m / zeros as f64).ln()) as u64;
-            }
-        }
+    #[test]
+    fn test_serde_format_version() {
+        let mut hll = HyperLogLog::new(4).unwrap();
+        hll.add_str("a");
 
-        if raw_estimate <= (1.0 / 30.0) * (1u64 << 32) as f64 {
-            return raw_estimate as u64;
+        let json = serde_json::to_value(&hll).unwrap();
+        assert_eq!(json["version"], 1);
+        assert!(serde_json::to_string(&hll).unwrap().starts_with(r#"{"version":1,"#));
+        assert_eq!(serde_json::from_value::<HyperLogLog>(json).unwrap(), hll);
+
+        // Explicit version 1, and data written before the version existed
+        let registers = [0u8; 16];
+        let v1 = format!(r#"{{"version":1,"precision":4,"m":16,"registers":{:?}}}"#, registers);
+        let unversioned = format!(r#"{{"precision":4,"m":16,"registers":{:?}}}"#, registers);
+        for json in [v1, unversioned] {
+            assert_eq!(serde_json::from_str::<HyperLogLog>(&json).unwrap().precision(), 4);
         }
 
-        // Large range correction
-        (-((1u64 << 32) as f64) * (1.0 - raw_estimate / ((1u64 << 32) as f64)).ln()) as u64
+        let v99 = format!(r#"{{"version":99,"precision":4,"m":16,"registers":{:?}}}"#, registers);
+        let err = serde_json::from_str::<HyperLogLog>(&v99).unwrap_err();
+        assert!(err.to_string().contains("Unsupported serialization format version 99"), "{}", err);
+        let repr: HyperLogLogRepr = serde_json::from_str(&v99).unwrap();
+        assert!(matches!(HyperLogLog::try_from(repr), Err(HllError::Storage(_))));
     }
 
-    /// Merge another HyperLogLog into this one
-    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), crate::error::HllError> {
-        if self.precision != other.precision {
-            return Err(crate::error::HllError::Storage(
-                "Cannot merge HyperLogLogs with different precision".to_string()
-            ));
-        }
+    #[test]
+    fn test_seed_round_trip() {
+        let mut hll = HyperLogLog::with_seed(10, u64::MAX - 7).unwrap();
+        hll.add_str("a");
 
-        for (i, &val) in other.registers.iter().enumerate() {
-            if val > self.registers[i] {
-                self.registers[i] = val;
-            }
-        }
+        let json = serde_json::to_string(&hll).unwrap();
+        assert_eq!(serde_json::from_str::<HyperLogLog>(&json).unwrap().seed(), u64::MAX - 7);
+        assert_eq!(HyperLogLog::from_bytes(&hll.to_bytes()).unwrap().seed(), u64::MAX - 7);
 
-        Ok(())
+        // Sketches written before seeds existed decode with seed 0
+        let json = r#"{"precision":4,"m":16,"registers":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]}"#;
+        assert_eq!(serde_json::from_str::<HyperLogLog>(json).unwrap().seed(), 0);
+        let mut v2 = b"HLL".to_vec();
+        v2.extend_from_slice(&[2, 4, 60, 8]);
+        v2.extend_from_slice(&[0; 16]);
+        assert_eq!(HyperLogLog::from_bytes(&v2).unwrap().seed(), 0);
     }
 
-    /// Get precision
-    pub fn precision(&self) -> u8 {
-        self.precision
+    #[test]
+    fn test_hasher() {
+        let mut xx = HyperLogLog::new(10).unwrap();
+        let mut sip = HyperLogLog::with_hasher(10, HasherKind::SipHash13).unwrap();
+        for i in 0..1000 {
+            xx.add(&i);
+            sip.add(&i);
+        }
+
+        assert_eq!(xx.hasher(), HasherKind::XxHash64);
+        assert_eq!(sip.hasher(), HasherKind::SipHash13);
+        assert_ne!(xx.dense_registers(), sip.dense_registers());
+        let error = (sip.count() as f64 - 1000.0).abs() / 1000.0;
+        assert!(error < 0.1, "Error rate: {:.2}%", error * 100.0);
+
+        let err = xx.merge(&sip).unwrap_err();
+        assert!(err.to_string().contains("hash functions"), "Unexpected error: {}", err);
+        assert!(xx.clone().merge_normalizing(&sip).is_err());
+        assert_ne!(
+            HyperLogLog::new(10).unwrap().fingerprint(),
+            HyperLogLog::with_hasher(10, HasherKind::SipHash13).unwrap().fingerprint()
+        );
+
+        let json = serde_json::to_string(&sip).unwrap();
+        assert_eq!(serde_json::from_str::<HyperLogLog>(&json).unwrap(), sip);
+        assert_eq!(HyperLogLog::from_bytes(&sip.to_bytes()).unwrap(), sip);
+        assert_eq!(sip.fold_to(8).unwrap().hasher(), HasherKind::SipHash13);
+        // Default sketches keep their JSON shape
+        assert!(!serde_json::to_string(&xx).unwrap().contains("hasher"));
+
+        let built = HyperLogLog::builder().precision(10).hasher(HasherKind::SipHash13).build().unwrap();
+        assert_eq!(built.hasher(), HasherKind::SipHash13);
+
+        let mut bytes = sip.to_bytes();
+        bytes[V3_HEADER_LEN] = 0xff;
+        assert!(HyperLogLog::from_bytes(&bytes).is_err());
+
+        // Version 3 data has no hasher byte and decodes as xxHash64
+        let mut v3 = xx.to_bytes();
+        v3[MAGIC.len()] = 3;
+        v3.remove(V3_HEADER_LEN);
+        assert_eq!(HyperLogLog::from_bytes(&v3).unwrap(), xx);
     }
 
-    /// Hash an element using xxHash
-    fn hash_element<T: Hash>(&self, element: &T) -> u64 {
-        let mut hasher = XxHash64::with_seed(0);
-        element.hash(&mut hasher);
-        hasher.finish()
+    #[test]
+    fn test_binary_rejects_unknown_version() {
+        let mut bytes = HyperLogLog::new(10).unwrap().to_bytes();
+        bytes[3] = FORMAT_VERSION + 1;
+
+        let err = HyperLogLog::from_bytes(&bytes).unwrap_err();
+        assert!(
+            err.to_string().contains(&format!("Unsupported format version {}", FORMAT_VERSION + 1)),
+            "Unexpected error: {}",
+            err
+        );
     }
 
-    /// Calculate alpha constant based on m
-    fn alpha_m(&self) -> f64 {
-        match self.m {
-            16 => 0.673,
-            32 => 0.697,
-            64 => 0.709,
-            _ => 0.7213 / (1.0 + 1.079 / self.m as f64),
-        }
+    #[cfg(feature = "file-storage")]
+    #[test]
+    fn test_peek_precision() {
+        let mut hll = HyperLogLog::new(11).unwrap();
+        hll.add_all(0..5000);
+        let bytes = hll.to_bytes();
+
+        // Only the header is read, so the registers may be cut off
+        assert_eq!(HyperLogLog::peek_precision(&bytes[..HEADER_LEN]).unwrap(), 11);
+        assert!(HyperLogLog::peek_precision(&bytes[..HEADER_LEN - 1]).is_err());
+        assert!(HyperLogLog::peek_precision(b"{\"precision\":11}").is_err());
+
+        let mut newer = bytes.clone();
+        newer[3] = FORMAT_VERSION + 1;
+        assert!(HyperLogLog::peek_precision(&newer).is_err());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Dense Redis string holding `user:0` to `user:9999`, built by replaying
+    /// Redis's `PFADD` (MurmurHash64A with seed `0xadc83b19`, low 14 bits as
+    /// the index). Redis's `PFCOUNT` estimator reads 10089 from these
+    /// registers; that is the cached cardinality in the header.
+    const REDIS_DUMP: &[u8] = include_bytes!("../tests/fixtures/redis_dense.hll");
 
     #[test]
-    fn test_new_valid_precision() {
-        for precision in 4..=16 {
-            let hll = HyperLogLog::new(precision);
-            assert!(hll.is_ok(), "Precision {} should be valid", precision);
+    fn test_redis_dense() {
+        let redis_count = u64::from_le_bytes(REDIS_DUMP[8..16].try_into().unwrap());
+        assert_eq!(redis_count, 10089);
 
-            let hll = hll.unwrap();
-            assert_eq!(hll.precision(), precision);
-            assert_eq!(hll.m, 1 << precision);
-            assert_eq!(hll.registers.len(), 1 << precision);
-        }
+        let hll = HyperLogLog::from_redis_dense(REDIS_DUMP).unwrap();
+        assert_eq!(hll.precision(), 14);
+        let error = (hll.count() as f64 - redis_count as f64).abs() / redis_count as f64;
+        assert!(error < 0.01, "Count {} differs from Redis's {}", hll.count(), redis_count);
+
+        let bytes = hll.to_redis_dense().unwrap();
+        assert_eq!(bytes[..8], REDIS_DUMP[..8]);
+        assert_eq!(bytes[15] & 0x80, 0x80, "Cached cardinality should be stale");
+        assert_eq!(bytes[REDIS_HEADER_LEN..], REDIS_DUMP[REDIS_HEADER_LEN..]);
     }
 
     #[test]
-    fn test_new_invalid_precision() {
-        assert!(HyperLogLog::new(3).is_err());
-        assert!(HyperLogLog::new(17).is_err());
-        assert!(HyperLogLog::new(0).is_err());
-        assert!(HyperLogLog::new(255).is_err());
+    fn test_redis_dense_rejects() {
+        assert!(matches!(
+            HyperLogLog::new(12).unwrap().to_redis_dense(),
+            Err(HllError::InvalidInput(_))
+        ));
+        assert!(HyperLogLog::with_layout(14, 32).unwrap().to_redis_dense().is_err());
+
+        let mut sparse = REDIS_DUMP.to_vec();
+        sparse[4] = REDIS_SPARSE;
+        assert!(HyperLogLog::from_redis_dense(&sparse).is_err());
+        assert!(HyperLogLog::from_redis_dense(&REDIS_DUMP[..REDIS_DUMP.len() - 1]).is_err());
+        assert!(HyperLogLog::from_redis_dense(b"HLL").is_err());
     }
 
     #[test]
-    fn test_add_deduplication() {
-        let mut hll = HyperLogLog::new(10).unwrap();
-
-        // Add same element multiple times
-        for _ in 0..100 {
-            hll.add_str("same_element");
-        }
+    fn test_binary_rejects_corrupt_data() {
+        let bytes = HyperLogLog::new(10).unwrap().to_bytes();
 
-        let count = hll.count();
-        // Should estimate close to 1
-        assert!(count <= 5, "Count should be close to 1, got {}", count);
+        assert!(HyperLogLog::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(HyperLogLog::from_bytes(b"HL").is_err());
+        assert!(HyperLogLog::from_bytes(b"{\"precision\":10}").is_err());
     }
 
     #[test]
-    fn test_basic_counting_small() {
-        let mut hll = HyperLogLog::new(14).unwrap();
+    fn test_with_registers_fn() {
+        let hll = HyperLogLog::with_registers_fn(10, |_| 1).unwrap();
+        assert!(hll.registers.iter().all(|&val| val == 1));
 
-        for i in 0..100 {
-            hll.add(&i);
-        }
+        // Every register at 1 gives sum = m / 2, so raw = 2 * alpha * m
+        let m = 1024.0;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        assert_eq!(hll.count(), (2.0 * alpha * m) as u64);
+
+        let pattern = HyperLogLog::with_registers_fn(4, |i| (i % 3) as u8).unwrap();
+        assert_eq!(pattern.registers[..4], [0, 1, 2, 0]);
 
+        assert!(HyperLogLog::with_registers_fn(10, |_| 56).is_err());
+        assert!(HyperLogLog::with_registers_fn(3, |_| 0).is_err());
+    }
+
+    #[test]
+    fn test_empty_count() {
+        let hll = HyperLogLog::new(10).unwrap();
         let count = hll.count();
-        let error_rate = ((count as f64 - 100.0) / 100.0).abs();
 
-        // Should be within ~10% for small counts
-        assert!(error_rate < 0.15, "Error rate: {:.2}%", error_rate * 100.0);
+        // Empty HLL should return 0 or very close to 0
+        assert!(count < 10, "Empty HLL count should be ~0, got {}", count);
     }
 
     #[test]
-    fn test_basic_counting_medium() {
-        let mut hll = HyperLogLog::new(14).unwrap();
+    fn test_is_empty() {
+        let mut hll = HyperLogLog::new(10).unwrap();
+        assert!(hll.is_empty());
+
+        hll.add_str("first");
+        assert!(!hll.is_empty());
+    }
 
+    #[test]
+    fn test_clear() {
+        let mut hll = HyperLogLog::new(12).unwrap();
         for i in 0..10000 {
             hll.add(&i);
         }
+        let allocation = hll.registers.as_ptr();
 
-        let count = hll.count();
-        let error_rate = ((count as f64 - 10000.0) / 10000.0).abs();
-
-        // HyperLogLog should be within ~2% error for precision 14
-        assert!(error_rate < 0.05, "Error rate: {:.2}%", error_rate * 100.0);
+        assert!(!hll.is_empty());
+        hll.clear();
+        assert!(hll.is_empty());
+        assert!(hll.count() < 10, "Cleared HLL count should be ~0, got {}", hll.count());
+        assert_eq!(hll.precision(), 12);
+        assert_eq!(hll.registers.len(), 4096);
+        assert_eq!(hll.registers.as_ptr(), allocation);
     }
 
     #[test]
-    fn test_basic_counting_large() {
-        let mut hll = HyperLogLog::new(14).unwrap();
-
-        for i in 0..100000 {
-            hll.add(&i);
+    fn test_sparse_memory() {
+        let mut sparse = HyperLogLog::new_sparse(16).unwrap();
+        let mut dense = HyperLogLog::new(16).unwrap();
+        for i in 0..50 {
+            sparse.add(&i);
+            dense.add(&i);
         }
 
-        let count = hll.count();
-        let error_rate = ((count as f64 - 100000.0) / 100000.0).abs();
+        assert!(sparse.is_sparse());
+        assert_eq!(sparse.registers.capacity(), 0);
+        let sparse_bytes = sparse.summary().memory_bytes;
+        assert!(
+            sparse_bytes * 100 < dense.summary().memory_bytes,
+            "Sparse sketch uses {} bytes",
+            sparse_bytes
+        );
+        assert_eq!(sparse.count(), dense.count());
+        assert_eq!(sparse.fingerprint(), dense.fingerprint());
 
-        // Should be within ~2% for large counts
-        assert!(error_rate < 0.03, "Error rate: {:.2}%", error_rate * 100.0);
+        sparse.densify();
+        assert!(!sparse.is_sparse());
+        assert_eq!(sparse.registers, dense.registers);
+        assert_eq!(sparse.count(), dense.count());
     }
 
     #[test]
-    fn test_string_elements() {
-        let mut hll = HyperLogLog::new(10).unwrap();
-
-        hll.add_str("user:1");
-        hll.add_str("user:2");
-        hll.add_str("user:3");
+    fn test_sparse_converts_to_dense() {
+        let mut sparse = HyperLogLog::new_sparse(10).unwrap();
+        let mut dense = HyperLogLog::new(10).unwrap();
+        for i in 0..100 {
+            sparse.add(&i);
+            dense.add(&i);
+        }
+        assert!(sparse.is_sparse());
 
-        let count = hll.count();
-        assert!(count >= 2 && count <= 5, "Count should be ~3, got {}", count);
+        // 1024 registers hold at most 128 eight-byte pairs
+        for i in 100..1000 {
+            sparse.add(&i);
+            dense.add(&i);
+        }
+        assert!(!sparse.is_sparse());
+        assert_eq!(sparse.registers, dense.registers);
+        assert_eq!(sparse.count_hllpp(), dense.count_hllpp());
     }
 
     #[test]
-    fn test_merge_disjoint() {
-        let mut hll1 = HyperLogLog::new(10).unwrap();
-        let mut hll2 = HyperLogLog::new(10).unwrap();
+    fn test_sparse_merge_and_fold() {
+        let build = |sparse: bool, range: std::ops::Range<u32>| {
+            let mut hll = if sparse {
+                HyperLogLog::new_sparse(12).unwrap()
+            } else {
+                HyperLogLog::new(12).unwrap()
+            };
+            hll.extend(range);
+            hll
+        };
 
-        for i in 0..100 {
-            hll1.add(&i);
-        }
+        let mut expected = build(false, 0..60);
+        expected.merge(&build(false, 40..100)).unwrap();
 
-        for i in 100..200 {
-            hll2.add(&i);
+        for (left, right) in [(true, true), (true, false), (false, true)] {
+            let mut merged = build(left, 0..60);
+            merged.merge(&build(right, 40..100)).unwrap();
+            assert_eq!(merged.is_sparse(), left && right);
+            assert_eq!(merged.count(), expected.count());
+            assert_eq!(merged.fingerprint(), expected.fingerprint());
         }
 
-        hll1.merge(&hll2).unwrap();
-        let count = hll1.count();
+        let folded = build(true, 0..100).fold_to(10).unwrap();
+        assert!(folded.is_sparse());
+        assert_eq!(folded.fingerprint(), expected.fold_to(10).unwrap().fingerprint());
 
-        // Should estimate around 200
-        assert!(count > 150 && count < 250, "Count should be ~200, got {}", count);
+        let mut cleared = build(true, 0..100);
+        cleared.clear();
+        assert!(cleared.is_empty() && cleared.is_sparse());
     }
 
     #[test]
-    fn test_merge_overlapping() {
-        let mut hll1 = HyperLogLog::new(12).unwrap();
-        let mut hll2 = HyperLogLog::new(12).unwrap();
+    fn test_packed_matches_dense() {
+        let mut packed = HyperLogLog::new_packed(14).unwrap();
+        let mut dense = HyperLogLog::new(14).unwrap();
+        for i in 0..50000 {
+            packed.add(&i);
+            dense.add(&i);
+        }
 
-        // 50% overlap
-        for i in 0..150 {
-            hll1.add(&i);
+        assert!(packed.is_packed());
+        assert_eq!(packed.dense_registers(), dense.registers);
+        assert_eq!(packed.count(), dense.count());
+        assert_eq!(packed.count_hllpp(), dense.count_hllpp());
+        assert_eq!(packed.estimate_quality(), dense.estimate_quality());
+
+        // 16384 registers at 6 bits instead of 8
+        assert_eq!(packed.summary().memory_bytes, 12288);
+        assert_eq!(dense.summary().memory_bytes, 16384);
+
+        let mut other = HyperLogLog::new(14).unwrap();
+        for i in 40000..60000 {
+            other.add(&i);
         }
+        let expected = dense.union(&other).unwrap();
+        assert_eq!(packed.union(&other).unwrap().fingerprint(), expected.fingerprint());
+        assert_eq!(other.union(&packed).unwrap().fingerprint(), expected.fingerprint());
+        assert_eq!(packed.fold_to(10).unwrap().fingerprint(), dense.fold_to(10).unwrap().fingerprint());
+    }
 
-        for i in 100..250 {
-            hll2.add(&i);
+    #[test]
+    fn test_packed_serialization() {
+        let mut hll = HyperLogLog::new_packed(10).unwrap();
+        for i in 0..5000 {
+            hll.add(&i);
         }
 
-        let count1 = hll1.count();
-        let count2 = hll2.count();
+        let json = serde_json::to_string(&hll).unwrap();
+        let decoded: HyperLogLog = serde_json::from_str(&json).unwrap();
+        assert!(decoded.is_packed());
+        assert_eq!(decoded.fingerprint(), hll.fingerprint());
 
-        hll1.merge(&hll2).unwrap();
-        let merged_count = hll1.count();
+        let bytes = hll.to_bytes();
+        assert_eq!(bytes[6], PACKED_WIDTH);
+        assert_eq!(&bytes[HEADER_LEN..], hll.packed.as_deref().unwrap());
+        assert_eq!(HyperLogLog::from_bytes(&bytes).unwrap().fingerprint(), hll.fingerprint());
 
-        // Merged should be around 250 (0..250)
-        assert!(
-            merged_count > 200 && merged_count < 300,
-            "Merged count should be ~250, got {}. Individual counts: {}, {}",
-            merged_count,
-            count1,
-            count2
-        );
+        let short = r#"{"precision":4,"m":16,"registers":[],"packed":[0,0,0]}"#;
+        assert!(serde_json::from_str::<HyperLogLog>(short).is_err());
     }
 
     #[test]
-    fn test_merge_precision_mismatch() {
-        let mut hll1 = HyperLogLog::new(10).unwrap();
-        let hll2 = HyperLogLog::new(12).unwrap();
-
-        let result = hll1.merge(&hll2);
-        assert!(result.is_err(), "Should fail to merge different precisions");
+    fn test_set_packed_overwrites() {
+        let mut packed = pack_registers(&[63, 63, 63, 63], PACKED_WIDTH);
+        set_packed(&mut packed, PACKED_WIDTH, 1, 5);
+        set_packed(&mut packed, PACKED_WIDTH, 2, 0);
+        assert_eq!(unpack_registers(&packed, PACKED_WIDTH, 4), vec![63, 5, 0, 63]);
     }
 
     #[test]
-    fn test_merge_same_data() {
-        let mut hll1 = HyperLogLog::new(10).unwrap();
-        let mut hll2 = HyperLogLog::new(10).unwrap();
-
-        // Both see same data
+    fn test_equality() {
+        let mut dense = HyperLogLog::new(12).unwrap();
+        let mut sparse = HyperLogLog::new_sparse(12).unwrap();
+        let mut packed = HyperLogLog::new_packed(12).unwrap();
         for i in 0..100 {
-            hll1.add(&i);
-            hll2.add(&i);
+            dense.add(&i);
+            sparse.add(&i);
+            packed.add(&i);
         }
 
-        let count_before = hll1.count();
-        hll1.merge(&hll2).unwrap();
-        let count_after = hll1.count();
+        assert!(sparse.is_sparse() && packed.is_packed());
+        assert_eq!(dense, sparse);
+        assert_eq!(dense, packed);
+        assert_eq!(HyperLogLog::from_bytes(&dense.to_bytes()).unwrap(), dense);
 
-        // Count shouldn't change much (within 10%)
-        let diff = ((count_after as f64 - count_before as f64) / count_before as f64).abs();
-        assert!(diff < 0.1, "Counts should be similar: {} vs {}", count_before, count_after);
+        let mut other = dense.clone();
+        other.add(&"one more");
+        assert_ne!(dense, other);
+
+        let empty = HyperLogLog::new(12).unwrap();
+        assert_ne!(empty, HyperLogLog::with_seed(12, 7).unwrap());
+        assert_ne!(empty, HyperLogLog::with_layout(12, 32).unwrap());
+        assert_ne!(empty, HyperLogLog::new(10).unwrap());
     }
 
     #[test]
-    fn test_clone() {
-        let mut hll = HyperLogLog::new(10).unwrap();
+    fn test_extend() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+        hll.extend(0..10000);
 
-        for i in 0..1000 {
-            hll.add(&i);
+        let error = (hll.count() as f64 - 10000.0).abs() / 10000.0;
+        assert!(error < 0.05, "Error rate: {:.2}%", error * 100.0);
+
+        let mut looped = HyperLogLog::new(14).unwrap();
+        for i in 0..10000 {
+            looped.add(&i);
         }
+        assert_eq!(hll, looped);
+        assert_eq!(HyperLogLog::from_iter_with_precision(14, 0..10000).unwrap(), looped);
 
-        let hll_clone = hll.clone();
+        // Extending with strings matches add_str without a normalizer
+        let mut by_str = HyperLogLog::new(10).unwrap();
+        by_str.add_str("a");
+        by_str.add_str("b");
+        assert_eq!(HyperLogLog::from_iter_with_precision(10, ["a", "b"]).unwrap(), by_str);
 
-        assert_eq!(hll.precision(), hll_clone.precision());
-        assert_eq!(hll.count(), hll_clone.count());
-        assert_eq!(hll.registers, hll_clone.registers);
+        assert!(HyperLogLog::from_iter_with_precision(3, 0..10).is_err());
     }
 
     #[test]
-    fn test_serialization() {
-        let mut hll = HyperLogLog::new(12).unwrap();
+    fn test_builder() {
+        let hll = HyperLogLogBuilder::default().precision(12).build().unwrap();
+        assert_eq!(hll.precision(), 12);
+        assert_eq!(hll.seed(), 0);
 
-        for i in 0..5000 {
-            hll.add(&i);
-        }
+        assert_eq!(HyperLogLog::builder().build().unwrap(), HyperLogLog::new(14).unwrap());
+        assert_eq!(
+            HyperLogLog::builder().precision(10).seed(42).build().unwrap(),
+            HyperLogLog::with_seed(10, 42).unwrap()
+        );
 
-        // Serialize
-        let serialized = serde_json::to_string(&hll).unwrap();
+        assert!(matches!(
+            HyperLogLog::builder().precision(17).build(),
+            Err(HllError::InvalidPrecision(17))
+        ));
+    }
 
-        // Deserialize
-        let deserialized: HyperLogLog = serde_json::from_str(&serialized).unwrap();
+    #[test]
+    fn test_add_all_matches_add() {
+        let mut looped = HyperLogLog::new(14).unwrap();
+        for i in 0..1_000_000u32 {
+            looped.add(&i);
+        }
 
-        assert_eq!(hll.precision(), deserialized.precision());
-        assert_eq!(hll.count(), deserialized.count());
-        assert_eq!(hll.registers, deserialized.registers);
+        let mut batched = HyperLogLog::new(14).unwrap();
+        batched.add_all(0..1_000_000u32);
+        assert_eq!(batched, looped);
+        assert_eq!(batched.count(), looped.count());
+
+        // Sparse, packed and narrow layouts take the generic path or the
+        // fast path and must still agree with add
+        for mut hll in [
+            HyperLogLog::new_sparse(12).unwrap(),
+            HyperLogLog::new_packed(12).unwrap(),
+            HyperLogLog::with_layout(12, 8).unwrap(),
+        ] {
+            let mut expected = hll.clone();
+            for i in 0..5000 {
+                expected.add(&i);
+            }
+            hll.add_all(0..5000);
+            assert_eq!(hll, expected);
+        }
     }
 
     #[test]
-    fn test_empty_count() {
-        let hll = HyperLogLog::new(10).unwrap();
-        let count = hll.count();
+    fn test_sparse_serialization() {
+        let mut hll = HyperLogLog::new_sparse(14).unwrap();
+        for i in 0..50 {
+            hll.add(&i);
+        }
 
-        // Empty HLL should return 0 or very close to 0
-        assert!(count < 10, "Empty HLL count should be ~0, got {}", count);
+        let json = serde_json::to_string(&hll).unwrap();
+        let decoded: HyperLogLog = serde_json::from_str(&json).unwrap();
+        assert!(decoded.is_sparse());
+        assert_eq!(decoded.fingerprint(), hll.fingerprint());
+
+        let bytes = hll.to_bytes();
+        assert_eq!(bytes[6], SPARSE_WIDTH);
+        let pairs = hll.sparse.as_ref().unwrap().len();
+        assert_eq!(bytes.len(), HEADER_LEN + pairs * SPARSE_ENCODED_LEN);
+        let decoded = HyperLogLog::from_bytes(&bytes).unwrap();
+        assert!(decoded.is_sparse());
+        assert_eq!(decoded.fingerprint(), hll.fingerprint());
+
+        // Dense sketches keep their existing JSON shape
+        let dense = serde_json::to_value(HyperLogLog::new(4).unwrap()).unwrap();
+        assert!(dense.get("sparse").is_none());
+
+        let unsorted = r#"{"precision":4,"m":16,"registers":[],"sparse":[[3,1],[2,1]]}"#;
+        assert!(serde_json::from_str::<HyperLogLog>(unsorted).is_err());
+        let out_of_range = r#"{"precision":4,"m":16,"registers":[],"sparse":[[16,1]]}"#;
+        assert!(serde_json::from_str::<HyperLogLog>(out_of_range).is_err());
+        assert!(HyperLogLog::from_bytes(&bytes[..bytes.len() - 1]).is_err());
     }
 
     #[test]
+    #[allow(clippy::approx_constant, clippy::manual_range_contains)]
     fn test_different_types() {
         let mut hll = HyperLogLog::new(10).unwrap();
 
@@ -370,4 +4348,88 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_golden_registers() {
+        // Captured once from a known-good build. Services on different
+        // platforms merge each other's sketches, so this must never change
+        // without a format version bump. Strings only: their `Hash` output is
+        // byte-stable, while integers hash in native endianness.
+        #[rustfmt::skip]
+        const EXPECTED: [u8; 64] = [
+            4, 0, 3, 1, 1, 0, 0, 1, 10, 2, 0, 1, 0, 1, 0, 1,
+            3, 1, 0, 5, 1, 2, 1, 4, 1, 0, 5, 0, 1, 4, 0, 1,
+            0, 1, 4, 0, 3, 2, 0, 0, 1, 0, 2, 0, 0, 1, 2, 3,
+            2, 3, 4, 3, 0, 0, 2, 1, 3, 0, 2, 0, 0, 1, 1, 2,
+        ];
+
+        let mut hll = HyperLogLog::new(6).unwrap();
+        for input in GOLDEN_INPUTS {
+            hll.add_str(input);
+        }
+
+        assert_eq!(
+            hll.registers, EXPECTED,
+            "Register layout changed; hashing or bit extraction is no longer compatible"
+        );
+    }
+
+    const GOLDEN_INPUTS: &[&str] = &[
+        "",
+        " ",
+        "a",
+        "b",
+        "A",
+        "hello",
+        "Hello",
+        "hello world",
+        "hello  world",
+        "\t\n",
+        "0",
+        "1",
+        "42",
+        "-1",
+        "3.14159",
+        "18446744073709551615",
+        "user:1",
+        "user:2",
+        "user:1000",
+        "user@example.com",
+        "USER@EXAMPLE.COM",
+        "192.168.0.1",
+        "2001:db8::1",
+        "https://example.com/path?q=1",
+        "/var/log/syslog",
+        "C:\\Windows\\System32",
+        "2024-01-01T00:00:00Z",
+        "550e8400-e29b-41d4-a716-446655440000",
+        "{\"key\": \"value\"}",
+        "<html></html>",
+        "SELECT * FROM t;",
+        "café",
+        "cafe\u{301}",
+        "naïve",
+        "Straße",
+        "日本語",
+        "中文字符",
+        "한국어",
+        "Ελληνικά",
+        "русский",
+        "עברית",
+        "العربية",
+        "हिन्दी",
+        "🎉",
+        "👨‍👩‍👧",
+        "🏳️‍🌈",
+        "\u{0}",
+        "\u{7f}",
+        "\u{feff}bom",
+        "\u{10ffff}",
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "The quick brown fox jumps over the lazy dog",
+        "The quick brown fox jumps over the lazy dog.",
+        "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.",
+        "nano-hyperloglog",
+        "HyperLogLog",
+    ];
 }
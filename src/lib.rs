@@ -13,8 +13,9 @@
 //! - **Fixed memory usage**: Count billions of items with ~16KB (configurable via precision)
 //! - **High accuracy**: Typically within 0.8-2% of true count (depending on precision)
 //! - **Mergeable**: Combine HyperLogLogs from multiple sources with simple union operations
-//! - **Pluggable storage**: File-based or Elasticsearch backends for persistence
-//! - **Redis-compatible API**: Optional HTTP server with PFADD/PFCOUNT/PFMERGE endpoints
+//! - **Pluggable storage**: File-based, Elasticsearch, Redis, SQLite or S3 backends for persistence
+//! - **Redis-compatible API**: Optional HTTP server with PFADD/PFCOUNT/PFMERGE endpoints, and
+//!   an optional RESP server that `redis-cli` and Redis client libraries can talk to
 //! - **Type-safe**: Leverage Rust's type system for compile-time guarantees
 //!
 //! ## Quick Start
@@ -101,16 +102,25 @@
 //!
 //! | Precision | Memory  | Standard Error |
 //! |-----------|---------|----------------|
-//! | 10        | 1 KB    | ±1.625%        |
-//! | 12        | 4 KB    | ±0.813%        |
-//! | 14        | 16 KB   | ±0.406%        |
-//! | 16        | 64 KB   | ±0.203%        |
+//! | 10        | 1 KB    | ±3.25%         |
+//! | 12        | 4 KB    | ±1.63%         |
+//! | 14        | 16 KB   | ±0.81%         |
+//! | 16        | 64 KB   | ±0.41%         |
 //!
 //! ## Feature Flags
 //!
 //! - `file-storage` (default): Enable file-based storage backend
+//! - `bincode`: Enable the bincode file format, `SerializationFormat::Bincode`
 //! - `elasticsearch-storage`: Enable Elasticsearch storage backend
+//! - `redis-storage`: Enable Redis storage backend
+//! - `sqlite-storage`: Enable SQLite storage backend
+//! - `s3-storage`: Enable S3 (and S3-compatible object storage) backend
 //! - `server`: Enable HTTP server with Redis-compatible API
+//! - `resp-server`: Serve PFADD/PFCOUNT/PFMERGE to Redis clients over RESP2
+//! - `metrics`: Export Prometheus/OpenMetrics metrics from the server at `GET /metrics`
+//! - `rayon`: Merge many sketches in parallel with `HyperLogLog::merge_all_parallel`
+//! - `testing`: Assertion helpers for tests of code that embeds HyperLogLogs
+//! - `cli`: The `hll` command for creating, filling, counting and merging sketch files
 //! - `full`: Enable all features
 //!
 //! ## Examples
@@ -124,6 +134,16 @@
 
 pub mod hll;
 pub mod error;
+pub mod hasher;
+pub mod concurrent;
+mod bias;
+mod beta;
+
+#[cfg(any(feature = "server", feature = "resp-server"))]
+mod locks;
+
+#[cfg(feature = "testing")]
+pub mod testing;
 
 #[cfg(feature = "file-storage")]
 pub mod storage;
@@ -131,8 +151,16 @@ pub mod storage;
 #[cfg(feature = "server")]
 pub mod api;
 
-pub use hll::HyperLogLog;
+#[cfg(feature = "resp-server")]
+pub mod resp;
+
+pub use hll::{
+    estimate_from_registers, EstimateQuality, HllSummary, HyperLogLog, HyperLogLogBuilder,
+    PrecisionInfo,
+};
 pub use error::{HllError, Result};
+pub use hasher::{HasherKind, HllHasher, SipHasher, XxHasher};
+pub use concurrent::ConcurrentHyperLogLog;
 
 #[cfg(feature = "file-storage")]
 pub use storage::Storage;
@@ -140,5 +168,17 @@ pub use storage::Storage;
 #[cfg(feature = "file-storage")]
 pub use storage::FileStorage;
 
+#[cfg(feature = "file-storage")]
+pub use storage::MemoryStorage;
+
 #[cfg(feature = "elasticsearch-storage")]
 pub use storage::ElasticsearchStorage;
+
+#[cfg(feature = "redis-storage")]
+pub use storage::RedisStorage;
+
+#[cfg(feature = "s3-storage")]
+pub use storage::S3Storage;
+
+#[cfg(feature = "sqlite-storage")]
+pub use storage::SqliteStorage;
@@ -0,0 +1,76 @@
+use dashmap::DashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// Per-key mutexes serializing read-modify-write handlers
+///
+/// The locks only exist inside this process. Several servers sharing one
+/// backend can still interleave their writes to a key.
+#[derive(Debug, Default)]
+pub(crate) struct KeyLocks {
+    locks: DashMap<String, Arc<Mutex<()>>>,
+}
+
+impl KeyLocks {
+    /// Wait for exclusive access to `key`, held until the guard is dropped
+    pub(crate) async fn lock(self: &Arc<Self>, key: &str) -> KeyGuard {
+        let mutex = self.locks.entry(key.to_string()).or_default().clone();
+
+        KeyGuard {
+            guard: Some(mutex.lock_owned().await),
+            locks: self.clone(),
+            key: key.to_string(),
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.locks.len()
+    }
+}
+
+/// Exclusive access to one key; dropping it releases the lock
+pub(crate) struct KeyGuard {
+    guard: Option<OwnedMutexGuard<()>>,
+    locks: Arc<KeyLocks>,
+    key: String,
+}
+
+impl Drop for KeyGuard {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+
+        // Forget the mutex once nobody holds or waits for it, so the map
+        // doesn't grow with every key ever written. Cloning out of the map
+        // happens under the same shard lock, so the count can't race.
+        self.locks
+            .locks
+            .remove_if(&self.key, |_, mutex| Arc::strong_count(mutex) == 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock_is_exclusive_and_released() {
+        let locks = Arc::new(KeyLocks::default());
+
+        let guard = locks.lock("a").await;
+        let other = locks.lock("b").await;
+        assert_eq!(locks.len(), 2);
+
+        let waiter = {
+            let locks = locks.clone();
+            tokio::spawn(async move { locks.lock("a").await })
+        };
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        drop(guard);
+        drop(waiter.await.unwrap());
+        drop(other);
+        assert_eq!(locks.len(), 0);
+    }
+}
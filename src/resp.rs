@@ -0,0 +1,599 @@
+//! RESP2 server speaking the Redis HyperLogLog commands
+//!
+//! Lets `redis-cli` and Redis client libraries talk to any [`Storage`]
+//! backend. Supported commands are `PFADD`, `PFCOUNT`, `PFMERGE`, `DEL`,
+//! `EXISTS` and `PING`, sent either as RESP multibulk arrays or as inline
+//! commands typed into a terminal.
+//!
+//! ```rust,no_run
+//! use hyperloglog::resp::RespServer;
+//! use hyperloglog::storage::MemoryStorage;
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:6379").await?;
+//! RespServer::new(Arc::new(MemoryStorage::new())).serve(listener).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::hll::{MAX_PRECISION, MIN_PRECISION};
+use crate::locks::KeyLocks;
+use crate::storage::Storage;
+use crate::{HllError, HyperLogLog, Result};
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Longest bulk string accepted, as in Redis
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// Most arguments accepted in one command
+const MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+
+/// Longest inline command or length header accepted
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// A reply to one command
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+}
+
+impl Reply {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Reply::Simple(status) => format!("+{}\r\n", status).into_bytes(),
+            // Error lines end at the first line break
+            Reply::Error(message) => format!("-{}\r\n", message.replace(['\r', '\n'], " ")).into_bytes(),
+            Reply::Integer(value) => format!(":{}\r\n", value).into_bytes(),
+        }
+    }
+
+    fn wrong_arity(command: &str) -> Self {
+        Reply::Error(format!("ERR wrong number of arguments for '{}' command", command))
+    }
+}
+
+impl From<HllError> for Reply {
+    fn from(e: HllError) -> Self {
+        Reply::Error(format!("ERR {}", e))
+    }
+}
+
+/// TCP server answering Redis HyperLogLog commands from a [`Storage`]
+///
+/// Elements that are valid UTF-8 are added with
+/// [`HyperLogLog::add_str`], like the HTTP API, so both front ends agree on
+/// the same key. PFADD and PFMERGE create missing keys at
+/// [`RespServer::DEFAULT_PRECISION`] unless
+/// [`RespServer::with_default_precision`] sets another.
+///
+/// PFADD and PFMERGE load, modify and store their destination, keeping its
+/// expiry. Writes to the same key wait for each other so none of their
+/// updates are lost; [`RespServer::with_locks_from`] extends that to an HTTP
+/// API in the same process. Servers in other processes sharing a backend can
+/// still interleave their writes.
+#[derive(Clone)]
+pub struct RespServer {
+    storage: Arc<dyn Storage>,
+    key_locks: Arc<KeyLocks>,
+    default_precision: u8,
+}
+
+impl RespServer {
+    /// Precision of keys created by PFADD and PFMERGE unless configured, as
+    /// in the HTTP API
+    pub const DEFAULT_PRECISION: u8 = 14;
+
+    /// Serve sketches from `storage`
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage,
+            key_locks: Arc::default(),
+            default_precision: Self::DEFAULT_PRECISION,
+        }
+    }
+
+    /// Create missing keys at `precision` instead of
+    /// [`RespServer::DEFAULT_PRECISION`]
+    pub fn with_default_precision(mut self, precision: u8) -> Result<Self> {
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+            return Err(HllError::InvalidPrecision(precision));
+        }
+        self.default_precision = precision;
+        Ok(self)
+    }
+
+    /// Lock keys with the HTTP API's per-key locks, so writes to one key
+    /// from either front end wait for each other
+    #[cfg(feature = "server")]
+    pub fn with_locks_from(mut self, state: &crate::api::AppState) -> Self {
+        self.key_locks = state.key_locks();
+        self
+    }
+
+    /// Accept connections from `listener` until accepting fails, serving
+    /// each one on its own task
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_connection(stream).await {
+                    tracing::debug!("RESP connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    /// Answer commands from one client until it disconnects
+    ///
+    /// Replies are flushed once every pipelined command already received
+    /// has been answered.
+    async fn handle_connection(&self, stream: TcpStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut replies = Vec::new();
+
+        loop {
+            let args = match read_command(&mut reader).await {
+                Ok(Some(args)) => args,
+                Ok(None) => return Ok(()),
+                Err(HllError::InvalidInput(msg)) => {
+                    // Like Redis, report the protocol error and hang up,
+                    // since the rest of the stream cannot be framed
+                    replies.extend(Reply::Error(format!("ERR Protocol error: {}", msg)).encode());
+                    writer.write_all(&replies).await?;
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+
+            if !args.is_empty() {
+                replies.extend(self.execute(&args).await.encode());
+            }
+
+            if reader.buffer().is_empty() {
+                writer.write_all(&replies).await?;
+                replies.clear();
+            }
+        }
+    }
+
+    /// Run one command; `args` holds the command name and its arguments
+    async fn execute(&self, args: &[Vec<u8>]) -> Reply {
+        let name = String::from_utf8_lossy(&args[0]).to_ascii_lowercase();
+        let args = &args[1..];
+
+        let result = match name.as_str() {
+            "pfadd" if !args.is_empty() => self.pfadd(args).await,
+            "pfcount" if !args.is_empty() => self.pfcount(args).await,
+            "pfmerge" if !args.is_empty() => self.pfmerge(args).await,
+            "del" if !args.is_empty() => self.del(args).await,
+            "exists" if !args.is_empty() => self.exists(args).await,
+            "ping" => match args {
+                [] => Ok(Reply::Simple("PONG")),
+                _ => Ok(Reply::wrong_arity(&name)),
+            },
+            "pfadd" | "pfcount" | "pfmerge" | "del" | "exists" => Ok(Reply::wrong_arity(&name)),
+            _ => Ok(Reply::Error(format!("ERR unknown command '{}'", name))),
+        };
+
+        result.unwrap_or_else(Reply::from)
+    }
+
+    /// PFADD key [element ...] - 1 if the key was created or a register
+    /// changed, else 0
+    async fn pfadd(&self, args: &[Vec<u8>]) -> Result<Reply> {
+        let key = key_str(&args[0])?;
+
+        let _lock = self.key_locks.lock(key).await;
+        let (mut hll, created) = match self.storage.load(key).await {
+            Ok(hll) => (hll, false),
+            Err(HllError::NotFound(_)) => (HyperLogLog::new(self.default_precision)?, true),
+            Err(e) => return Err(e),
+        };
+
+        let mut changed = created;
+        for element in &args[1..] {
+            changed |= match std::str::from_utf8(element) {
+                Ok(element) => hll.add_str(element),
+                Err(_) => hll.add_bytes(element),
+            };
+        }
+
+        if changed {
+            self.storage.store_keep_ttl(key, &hll).await?;
+        }
+        Ok(Reply::Integer(i64::from(changed)))
+    }
+
+    /// PFCOUNT key [key ...] - Estimate of the union; missing keys count as
+    /// empty
+    async fn pfcount(&self, args: &[Vec<u8>]) -> Result<Reply> {
+        let keys = key_strs(args)?;
+        let hlls: Vec<HyperLogLog> = self
+            .storage
+            .load_many_parallel(&keys)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let count = if hlls.is_empty() {
+            0
+        } else {
+            HyperLogLog::merge_all(&hlls)?.count()
+        };
+        Ok(Reply::Integer(count as i64))
+    }
+
+    /// PFMERGE destkey [sourcekey ...] - Store the union of the destination
+    /// and sources in the destination; missing keys count as empty
+    async fn pfmerge(&self, args: &[Vec<u8>]) -> Result<Reply> {
+        let keys = key_strs(args)?;
+
+        let _lock = self.key_locks.lock(keys[0]).await;
+        let hlls: Vec<HyperLogLog> = self
+            .storage
+            .load_many_parallel(&keys)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let merged = if hlls.is_empty() {
+            HyperLogLog::new(self.default_precision)?
+        } else {
+            HyperLogLog::merge_all(&hlls)?
+        };
+        self.storage.store_keep_ttl(keys[0], &merged).await?;
+        Ok(Reply::Simple("OK"))
+    }
+
+    /// DEL key [key ...] - Number of keys that existed and were removed
+    async fn del(&self, args: &[Vec<u8>]) -> Result<Reply> {
+        let mut removed = 0;
+        for key in key_strs(args)? {
+            if self.storage.exists(key).await? {
+                self.storage.delete(key).await?;
+                removed += 1;
+            }
+        }
+        Ok(Reply::Integer(removed))
+    }
+
+    /// EXISTS key [key ...] - Number of the keys that exist, counting
+    /// repeats
+    async fn exists(&self, args: &[Vec<u8>]) -> Result<Reply> {
+        let mut found = 0;
+        for key in key_strs(args)? {
+            if self.storage.exists(key).await? {
+                found += 1;
+            }
+        }
+        Ok(Reply::Integer(found))
+    }
+}
+
+fn key_str(key: &[u8]) -> Result<&str> {
+    std::str::from_utf8(key).map_err(|_| HllError::InvalidKey("keys must be UTF-8".to_string()))
+}
+
+fn key_strs(keys: &[Vec<u8>]) -> Result<Vec<&str>> {
+    keys.iter().map(|key| key_str(key)).collect()
+}
+
+/// Read one command, or `None` once the client has disconnected
+///
+/// Malformed input is an [`HllError::InvalidInput`]. An empty inline line
+/// is an empty command.
+async fn read_command<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = read_line(reader).await? else {
+        return Ok(None);
+    };
+
+    let Some(count) = line.strip_prefix(b"*") else {
+        return Ok(Some(
+            line.split(|byte| byte.is_ascii_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect(),
+        ));
+    };
+
+    // A non-positive count is an empty command
+    let count = parse_len(count, "multibulk length")?;
+    if count > MAX_MULTIBULK_LEN as i64 {
+        return Err(HllError::InvalidInput("invalid multibulk length".to_string()));
+    }
+
+    let mut args = Vec::with_capacity(count.max(0) as usize);
+    for _ in 0..count {
+        let header = read_line(reader)
+            .await?
+            .ok_or_else(|| HllError::InvalidInput("unexpected end of stream".to_string()))?;
+        let len = match header.strip_prefix(b"$") {
+            Some(len) => parse_len(len, "bulk length")?,
+            None => {
+                return Err(HllError::InvalidInput(format!(
+                    "expected '$', got '{}'",
+                    String::from_utf8_lossy(&header[..header.len().min(1)])
+                )))
+            }
+        };
+        if !(0..=MAX_BULK_LEN as i64).contains(&len) {
+            return Err(HllError::InvalidInput("invalid bulk length".to_string()));
+        }
+
+        // Grow with the data actually received rather than trusting the
+        // announced length
+        let mut arg = Vec::new();
+        (&mut *reader).take(len as u64 + 2).read_to_end(&mut arg).await?;
+        if arg.len() < len as usize + 2 {
+            return Err(HllError::InvalidInput("unexpected end of stream".to_string()));
+        }
+        if !arg.ends_with(b"\r\n") {
+            return Err(HllError::InvalidInput("bulk string not terminated by CRLF".to_string()));
+        }
+        arg.truncate(len as usize);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+/// Read a line without its terminator, accepting a bare LF as inline
+/// commands may; `None` at end of stream
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    let read = (&mut *reader).take(MAX_LINE_LEN as u64 + 1).read_until(b'\n', &mut line).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return Err(HllError::InvalidInput(if line.len() > MAX_LINE_LEN {
+            "line too long".to_string()
+        } else {
+            "unexpected end of stream".to_string()
+        }));
+    }
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_len(digits: &[u8], what: &str) -> Result<i64> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(|| HllError::InvalidInput(format!("invalid {}", what)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use redis::AsyncCommands;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    /// Serve a fresh in-memory storage on a free local port
+    async fn start_server() -> (SocketAddr, Arc<MemoryStorage>) {
+        let storage = Arc::new(MemoryStorage::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(RespServer::new(storage.clone()).serve(listener));
+        (addr, storage)
+    }
+
+    async fn parse(input: &[u8]) -> Result<Option<Vec<Vec<u8>>>> {
+        read_command(&mut BufReader::new(input)).await
+    }
+
+    #[tokio::test]
+    async fn test_redis_client() {
+        let (addr, storage) = start_server().await;
+        let client = redis::Client::open(format!("redis://{}", addr)).unwrap();
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+
+        let added: i64 = conn.pfadd("visitors", &["alice", "bob", "carol"]).await.unwrap();
+        assert_eq!(added, 1);
+        let added: i64 = conn.pfadd("visitors", "alice").await.unwrap();
+        assert_eq!(added, 0, "Re-adding an element changes no register");
+        let count: i64 = conn.pfcount("visitors").await.unwrap();
+        assert_eq!(count, 3);
+
+        let elements: Vec<String> = (0..1000).map(|i| format!("user_{}", i)).collect();
+        let _: i64 = conn.pfadd("others", &elements).await.unwrap();
+        let count: i64 = conn.pfcount(&["visitors", "others", "missing"]).await.unwrap();
+        assert!((count - 1003).abs() < 50, "Got {}", count);
+
+        // Same hashing as the HTTP API's PFADD
+        let mut expected = HyperLogLog::new(RespServer::DEFAULT_PRECISION).unwrap();
+        for element in &elements {
+            expected.add_str(element);
+        }
+        assert_eq!(storage.load("others").await.unwrap(), expected);
+
+        let () = conn.pfmerge("union", &["visitors", "others"]).await.unwrap();
+        let merged: i64 = conn.pfcount("union").await.unwrap();
+        assert_eq!(merged, count);
+
+        let exists: i64 = conn.exists(&["union", "missing", "union"]).await.unwrap();
+        assert_eq!(exists, 2);
+        let removed: i64 = conn.del(&["union", "missing"]).await.unwrap();
+        assert_eq!(removed, 1);
+        let missing: i64 = conn.pfcount("union").await.unwrap();
+        assert_eq!(missing, 0);
+
+        let err = redis::cmd("PFADD").query_async::<i64>(&mut conn).await.unwrap_err();
+        assert!(err.to_string().contains("wrong number of arguments"), "Got {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_pfmerge_includes_destination() {
+        let (addr, _storage) = start_server().await;
+        let client = redis::Client::open(format!("redis://{}", addr)).unwrap();
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+
+        let _: i64 = conn.pfadd("dest", "a").await.unwrap();
+        let _: i64 = conn.pfadd("src", "b").await.unwrap();
+        let () = conn.pfmerge("dest", &["src", "missing"]).await.unwrap();
+        let count: i64 = conn.pfcount("dest").await.unwrap();
+        assert_eq!(count, 2);
+
+        // Merging nothing creates an empty key
+        let () = conn.pfmerge("empty", &["missing"]).await.unwrap();
+        let exists: bool = conn.exists("empty").await.unwrap();
+        assert!(exists);
+    }
+
+    #[tokio::test]
+    async fn test_default_precision() {
+        let storage = Arc::new(MemoryStorage::new());
+        let server = RespServer::new(storage.clone()).with_default_precision(10).unwrap();
+        let command = |args: &[&str]| args.iter().map(|arg| arg.as_bytes().to_vec()).collect::<Vec<_>>();
+
+        assert_eq!(server.execute(&command(&["PFADD", "added", "a"])).await, Reply::Integer(1));
+        assert_eq!(server.execute(&command(&["PFMERGE", "merged", "missing"])).await, Reply::Simple("OK"));
+        for key in ["added", "merged"] {
+            assert_eq!(storage.load(key).await.unwrap().precision(), 10);
+        }
+
+        assert!(matches!(
+            RespServer::new(storage).with_default_precision(3),
+            Err(HllError::InvalidPrecision(3))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_writes_keep_ttl() {
+        let storage = Arc::new(MemoryStorage::new());
+        let server = RespServer::new(storage.clone());
+        let command = |args: &[&str]| args.iter().map(|arg| arg.as_bytes().to_vec()).collect::<Vec<_>>();
+        let ttl = Some(Duration::from_secs(100));
+        let hll = HyperLogLog::new(RespServer::DEFAULT_PRECISION).unwrap();
+        storage.store_with_ttl("daily", &hll, ttl).await.unwrap();
+
+        assert_eq!(server.execute(&command(&["PFADD", "daily", "a"])).await, Reply::Integer(1));
+        assert_eq!(server.execute(&command(&["PFMERGE", "daily", "missing"])).await, Reply::Simple("OK"));
+
+        tokio::time::advance(Duration::from_secs(99)).await;
+        assert_eq!(storage.load("daily").await.unwrap().count(), 1);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(!storage.exists("daily").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_writes_lock_only_their_key() {
+        let server = RespServer::new(Arc::new(MemoryStorage::new()));
+        let pfadd = |key: &'static str| {
+            let server = server.clone();
+            let args = vec![b"PFADD".to_vec(), key.as_bytes().to_vec(), b"a".to_vec()];
+            tokio::spawn(async move { server.execute(&args).await })
+        };
+
+        let guard = server.key_locks.lock("busy").await;
+        assert_eq!(pfadd("idle").await.unwrap(), Reply::Integer(1));
+
+        let waiting = pfadd("busy");
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        drop(guard);
+        assert_eq!(waiting.await.unwrap(), Reply::Integer(1));
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_locks_shared_with_http() {
+        let storage = Arc::new(MemoryStorage::new());
+        let state = crate::api::AppState::new(storage.clone());
+        let server = RespServer::new(storage).with_locks_from(&state);
+
+        let guard = state.lock_key("visitors").await;
+        let waiting = tokio::spawn(async move {
+            server.execute(&[b"PFMERGE".to_vec(), b"visitors".to_vec()]).await
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        drop(guard);
+        assert_eq!(waiting.await.unwrap(), Reply::Simple("OK"));
+    }
+
+    #[tokio::test]
+    async fn test_inline_commands() {
+        let (addr, _storage) = start_server().await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // Pipelined, as `redis-cli` in a terminal or `nc` would send them
+        stream
+            .write_all(b"PING\r\npfadd k a b c\r\n\r\nPFCOUNT k\nNOSUCH k\r\n")
+            .await
+            .unwrap();
+        let expected = b"+PONG\r\n:1\r\n:3\r\n-ERR unknown command 'nosuch'\r\n";
+        let mut replies = vec![0; expected.len()];
+        stream.read_exact(&mut replies).await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&replies), String::from_utf8_lossy(expected));
+    }
+
+    #[tokio::test]
+    async fn test_protocol_error_closes_connection() {
+        let (addr, _storage) = start_server().await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream.write_all(b"*1\r\n+PING\r\n").await.unwrap();
+        let mut replies = Vec::new();
+        stream.read_to_end(&mut replies).await.unwrap();
+        assert_eq!(replies, b"-ERR Protocol error: expected '$', got '+'\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_read_command() {
+        assert_eq!(
+            parse(b"*3\r\n$5\r\nPFADD\r\n$1\r\nk\r\n$4\r\na\r\nb\r\n").await.unwrap(),
+            Some(vec![b"PFADD".to_vec(), b"k".to_vec(), b"a\r\nb".to_vec()])
+        );
+        assert_eq!(parse(b"*0\r\n").await.unwrap(), Some(vec![]));
+        assert_eq!(parse(b"  PFCOUNT   a  b \n").await.unwrap(), Some(vec![b"PFCOUNT".to_vec(), b"a".to_vec(), b"b".to_vec()]));
+        assert_eq!(parse(b"").await.unwrap(), None);
+
+        for bad in [
+            &b"*x\r\n"[..],
+            b"*1\r\n$-1\r\n",
+            b"*1\r\n$3\r\nabcd\r\n",
+            b"*2\r\n$1\r\na\r\n",
+            b"*1\r\n$3\r\nab",
+            b"PING",
+        ] {
+            assert!(
+                matches!(parse(bad).await, Err(HllError::InvalidInput(_))),
+                "Accepted {:?}",
+                String::from_utf8_lossy(bad)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_line_too_long() {
+        let mut input = vec![b'a'; MAX_LINE_LEN + 10];
+        input.extend(b"\r\n");
+        assert!(matches!(parse(&input).await, Err(HllError::InvalidInput(msg)) if msg == "line too long"));
+    }
+
+    #[test]
+    fn test_reply_encoding() {
+        assert_eq!(Reply::Simple("OK").encode(), b"+OK\r\n");
+        assert_eq!(Reply::Integer(-3).encode(), b":-3\r\n");
+        assert_eq!(Reply::Error("ERR a\r\nb".to_string()).encode(), b"-ERR a  b\r\n");
+        assert_eq!(
+            Reply::from(HllError::InvalidPrecision(2)).encode(),
+            b"-ERR Invalid precision 2 (must be 4..=16)\r\n"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{HllError, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+/// Leading bytes of a gzip stream
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Leading bytes of a zstd frame
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression backends apply to serialized HyperLogLogs
+///
+/// Compressed data is recognized by its magic bytes when read back, whatever
+/// compression the reader is configured with, so switching compression
+/// leaves existing data readable. No [`SerializationFormat`] output starts
+/// with either magic.
+///
+/// [`SerializationFormat`]: crate::storage::SerializationFormat
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Store the serialized bytes as-is
+    #[default]
+    None,
+    /// gzip at the default level
+    Gzip,
+    /// zstd at the default level
+    Zstd,
+}
+
+impl Compression {
+    /// Compress serialized data
+    pub fn compress(self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(bytes),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&bytes)?;
+                Ok(encoder.finish()?)
+            }
+            Compression::Zstd => Ok(zstd::encode_all(bytes.as_slice(), 0)?),
+        }
+    }
+
+    /// Undo [`Compression::compress`], detecting the compression from the
+    /// magic bytes; anything else is returned unchanged
+    pub fn decompress(bytes: &[u8]) -> Result<Cow<'_, [u8]>> {
+        if bytes.starts_with(GZIP_MAGIC) {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(bytes)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| HllError::Storage(format!("gzip decode error: {}", e)))?;
+            return Ok(Cow::Owned(decompressed));
+        }
+
+        if bytes.starts_with(ZSTD_MAGIC) {
+            return zstd::decode_all(bytes)
+                .map(Cow::Owned)
+                .map_err(|e| HllError::Storage(format!("zstd decode error: {}", e)));
+        }
+
+        Ok(Cow::Borrowed(bytes))
+    }
+}
@@ -2,57 +2,502 @@ use crate::{HyperLogLog, Result, HllError};
 use crate::storage::Storage;
 use async_trait::async_trait;
 use elasticsearch::{
-    Elasticsearch, http::transport::Transport, DeleteParts, GetParts, IndexParts, SearchParts,
+    http::{
+        response::Response,
+        transport::{MultiNodeConnectionPool, SingleNodeConnectionPool, TransportBuilder},
+        Url,
+    },
+    ClearScrollParts, DeleteParts, Elasticsearch, GetParts, IndexParts, MgetParts, ScrollParts,
+    SearchParts, UpdateParts,
 };
+use futures::stream::{self, BoxStream, TryStreamExt};
 use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Painless script folding `params.registers` into the stored document
+/// with a register-wise max. Runs server-side, so concurrent merges into
+/// the same key never overwrite each other. An expired document is
+/// replaced instead, dropping its expiry.
+const MERGE_SCRIPT: &str = r#"
+    def expiresAt = ctx._source.expires_at;
+    if (expiresAt != null && expiresAt <= params.now) {
+        ctx._source.remove('expires_at');
+        ctx._source.hll = params.hll;
+        ctx._source.precision = params.precision;
+    } else {
+        def target = ctx._source.hll;
+        if (target == null) {
+            throw new IllegalArgumentException('Document has no structured registers; store it again before merging');
+        }
+        if (target.precision != params.precision) {
+            throw new IllegalArgumentException('Cannot merge HyperLogLogs with different precision');
+        }
+        def rankBits = target.rank_bits == null ? 64 - target.precision : target.rank_bits;
+        if (rankBits != params.rank_bits) {
+            throw new IllegalArgumentException('Cannot merge HyperLogLogs with different register layouts');
+        }
+        def seed = target.seed == null ? 0L : target.seed;
+        if (seed != params.seed) {
+            throw new IllegalArgumentException('Cannot merge HyperLogLogs with different hash seeds');
+        }
+        def hasher = target.hasher == null ? 'XxHash64' : target.hasher;
+        if (hasher != params.hasher) {
+            throw new IllegalArgumentException('Cannot merge HyperLogLogs with different hash functions');
+        }
+        def registers = target.registers;
+        for (int i = 0; i < params.registers.size(); ++i) {
+            if (params.registers[i] > registers[i]) {
+                registers[i] = params.registers[i];
+            }
+        }
+    }
+"#;
+
+/// Painless script replacing the stored sketch with `params.hll` while
+/// keeping the document's expiry, unless it has already passed
+const REPLACE_SCRIPT: &str = r#"
+    def expiresAt = ctx._source.expires_at;
+    if (expiresAt != null && expiresAt <= params.now) {
+        ctx._source.remove('expires_at');
+    }
+    ctx._source.hll = params.hll;
+    ctx._source.precision = params.precision;
+"#;
+
+/// Number of times Elasticsearch retries a scripted update after a version conflict
+const RETRY_ON_CONFLICT: i64 = 16;
+
+/// Keys fetched per request when scrolling through an index
+const KEYS_PAGE_SIZE: usize = 1000;
+
+/// How long Elasticsearch keeps a scroll open between pages
+const SCROLL_KEEP_ALIVE: &str = "1m";
+
+/// Progress of [`ElasticsearchStorage::scroll_keys`]
+enum ScrollState {
+    /// No request sent yet; holds the query to scroll through
+    Start(Value),
+    /// More pages may follow under this scroll id
+    Next(String),
+    /// The last page was returned
+    Done,
+}
 
 /// Elasticsearch-based storage backend for HyperLogLog structures
+///
+/// Each document keeps the HyperLogLog as a structured `hll` object so that
+/// [`Storage::merge_into`] can update registers in place with a script, so
+/// sparse and packed sketches are stored in the dense form. Documents
+/// written by older versions (a serialized `hll_data` string) are still
+/// readable.
+///
+/// Elasticsearch has no per-document TTL, so a key stored with one gets an
+/// `expires_at` field in Unix milliseconds. Reads and searches treat expired
+/// documents as missing; they stay in the index until overwritten or deleted.
 #[derive(Clone)]
 pub struct ElasticsearchStorage {
     client: Elasticsearch,
     index_name: String,
 }
 
+/// Builder for an [`ElasticsearchStorage`] spread over one or more nodes
+///
+/// With several nodes, requests are distributed round-robin over a shared
+/// connection pool instead of queuing on a single transport.
+#[derive(Debug, Clone)]
+pub struct ElasticsearchStorageBuilder {
+    index_name: String,
+    nodes: Vec<String>,
+    timeout: Option<Duration>,
+    reseed_frequency: Option<Duration>,
+}
+
+impl ElasticsearchStorageBuilder {
+    /// Add a node URL to the pool
+    pub fn node(mut self, url: impl Into<String>) -> Self {
+        self.nodes.push(url.into());
+        self
+    }
+
+    /// Add several node URLs to the pool
+    pub fn nodes<I, S>(mut self, urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.nodes.extend(urls.into_iter().map(Into::into));
+        self
+    }
+
+    /// Fail requests that take longer than `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Refresh the node list from the cluster every `frequency`, so nodes
+    /// that left are dropped and new ones picked up. Multi-node pools only.
+    pub fn reseed_frequency(mut self, frequency: Duration) -> Self {
+        self.reseed_frequency = Some(frequency);
+        self
+    }
+
+    /// Connect to the configured nodes, or `http://localhost:9200` if none
+    pub fn build(self) -> Result<ElasticsearchStorage> {
+        let mut urls = self
+            .nodes
+            .iter()
+            .map(|node| {
+                Url::parse(node)
+                    .map_err(|e| HllError::Storage(format!("Invalid node URL {}: {}", node, e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut builder = match urls.len() {
+            0 => TransportBuilder::new(SingleNodeConnectionPool::new(
+                Url::parse("http://localhost:9200").expect("valid default URL"),
+            )),
+            1 => TransportBuilder::new(SingleNodeConnectionPool::new(urls.remove(0))),
+            _ => TransportBuilder::new(MultiNodeConnectionPool::round_robin(
+                urls,
+                self.reseed_frequency,
+            )),
+        };
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        let transport = builder
+            .build()
+            .map_err(|e| HllError::Storage(format!("Failed to create transport: {}", e)))?;
+
+        Ok(ElasticsearchStorage {
+            client: Elasticsearch::new(transport),
+            index_name: self.index_name,
+        })
+    }
+}
+
 impl ElasticsearchStorage {
     /// Create a new ElasticsearchStorage with default connection
     pub fn new(index_name: impl Into<String>) -> Result<Self> {
-        let transport = Transport::single_node("http://localhost:9200")
-            .map_err(|e| HllError::Storage(format!("Failed to create transport: {}", e)))?;
+        Self::builder(index_name).build()
+    }
 
-        let client = Elasticsearch::new(transport);
+    /// Create a new ElasticsearchStorage with custom URL
+    pub fn with_url(url: &str, index_name: impl Into<String>) -> Result<Self> {
+        Self::builder(index_name).node(url).build()
+    }
 
-        Ok(Self {
-            client,
+    /// Start configuring a storage backend, e.g. with several nodes
+    pub fn builder(index_name: impl Into<String>) -> ElasticsearchStorageBuilder {
+        ElasticsearchStorageBuilder {
             index_name: index_name.into(),
-        })
+            nodes: Vec::new(),
+            timeout: None,
+            reseed_frequency: None,
+        }
     }
 
-    /// Create a new ElasticsearchStorage with custom URL
-    pub fn with_url(url: &str, index_name: impl Into<String>) -> Result<Self> {
-        let transport = Transport::single_node(url)
-            .map_err(|e| HllError::Storage(format!("Failed to create transport: {}", e)))?;
+    /// Return the keys of all documents matching `query`
+    ///
+    /// Pages through the index with [`ElasticsearchStorage::scroll_keys`]; a
+    /// single search would stop at the 10000-hit result window.
+    async fn search_keys(&self, query: Value) -> Result<Vec<String>> {
+        self.scroll_keys(query).try_collect().await
+    }
 
-        let client = Elasticsearch::new(transport);
+    /// Stream the keys of all documents matching `query`, fetching
+    /// [`KEYS_PAGE_SIZE`] at a time with the scroll API
+    ///
+    /// The scroll is cleared after the last page. If the stream is dropped
+    /// early it expires on its own after [`SCROLL_KEEP_ALIVE`].
+    fn scroll_keys(&self, query: Value) -> BoxStream<'_, Result<String>> {
+        let pages = stream::try_unfold(ScrollState::Start(query), move |state| async move {
+            let body = match state {
+                ScrollState::Done => return Ok(None),
+                ScrollState::Start(query) => {
+                    let response = self
+                        .client
+                        .search(SearchParts::Index(&[&self.index_name]))
+                        .scroll(SCROLL_KEEP_ALIVE)
+                        .body(json!({
+                            "query": live_documents(query),
+                            "_source": ["key"],
+                            "sort": ["_doc"],
+                            "size": KEYS_PAGE_SIZE,
+                            "track_total_hits": true
+                        }))
+                        .send()
+                        .await;
+                    let body = search_body(response).await?;
 
-        Ok(Self {
-            client,
-            index_name: index_name.into(),
-        })
+                    let total = body["hits"]["total"]["value"].as_u64().unwrap_or(0);
+                    if total > KEYS_PAGE_SIZE as u64 {
+                        tracing::debug!(
+                            "Listing {} keys of index {} over {} pages",
+                            total,
+                            self.index_name,
+                            total.div_ceil(KEYS_PAGE_SIZE as u64)
+                        );
+                    }
+                    body
+                }
+                ScrollState::Next(scroll_id) => {
+                    let response = self
+                        .client
+                        .scroll(ScrollParts::None)
+                        .body(json!({ "scroll": SCROLL_KEEP_ALIVE, "scroll_id": scroll_id }))
+                        .send()
+                        .await;
+                    search_body(response).await?
+                }
+            };
+
+            let keys = keys_from_hits(&body)?;
+            let scroll_id = body["_scroll_id"].as_str().map(String::from);
+            let next = match scroll_id {
+                // A short page is the last one
+                Some(scroll_id) if keys.len() < KEYS_PAGE_SIZE => {
+                    self.clear_scroll(scroll_id).await;
+                    ScrollState::Done
+                }
+                Some(scroll_id) => ScrollState::Next(scroll_id),
+                None => ScrollState::Done,
+            };
+
+            if keys.is_empty() {
+                return Ok(None);
+            }
+            Ok::<_, HllError>(Some((keys, next)))
+        });
+
+        Box::pin(
+            pages
+                .map_ok(|keys| stream::iter(keys.into_iter().map(Ok)))
+                .try_flatten(),
+        )
+    }
+
+    /// Release a scroll's server-side resources; failures are only logged,
+    /// since the scroll expires anyway
+    async fn clear_scroll(&self, scroll_id: String) {
+        let result = self
+            .client
+            .clear_scroll(ClearScrollParts::None)
+            .body(json!({ "scroll_id": [scroll_id] }))
+            .send()
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to clear scroll: {}", e);
+        }
+    }
+
+    /// Fold `hll` into the document at `key` with [`MERGE_SCRIPT`], creating
+    /// it if absent. With `return_source`, the response body includes the
+    /// merged document under `get._source`.
+    async fn scripted_merge(
+        &self,
+        key: &str,
+        hll: &HyperLogLog,
+        return_source: bool,
+    ) -> Result<Value> {
+        let hll_value = hll_document(hll)?;
+        let source = if return_source { "true" } else { "false" };
+
+        let response = self
+            .client
+            .update(UpdateParts::IndexId(&self.index_name, key))
+            .retry_on_conflict(RETRY_ON_CONFLICT)
+            ._source(&[source])
+            .body(json!({
+                "script": {
+                    "source": MERGE_SCRIPT,
+                    "lang": "painless",
+                    "params": {
+                        "precision": hll.precision(),
+                        "rank_bits": hll.rank_bits(),
+                        "seed": hll.seed(),
+                        "hasher": hll.hasher(),
+                        "registers": hll_value["registers"],
+                        "hll": hll_value,
+                        "now": unix_millis_now(),
+                    }
+                },
+                "upsert": {
+                    "key": key,
+                    "hll": hll_value,
+                    "precision": hll.precision(),
+                }
+            }))
+            .send()
+            .await
+            .map_err(|e| HllError::Storage(format!("Failed to merge: {}", e)))?;
+
+        if !response.status_code().is_success() {
+            return Err(HllError::Storage(format!(
+                "Elasticsearch returned status: {}",
+                response.status_code()
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| HllError::Storage(format!("Failed to parse response: {}", e)))
+    }
+}
+
+/// `hll` as a document's `hll` object: dense, with the registers as an
+/// array of numbers rather than JSON's usual base64, so [`MERGE_SCRIPT`]
+/// can index into them
+fn hll_document(hll: &HyperLogLog) -> Result<Value> {
+    let dense = dense(hll);
+    let mut document = serde_json::to_value(&*dense)?;
+    document["registers"] = json!(*dense.registers());
+    Ok(document)
+}
+
+/// `hll` in the dense representation
+fn dense(hll: &HyperLogLog) -> Cow<'_, HyperLogLog> {
+    if !hll.is_sparse() && !hll.is_packed() {
+        return Cow::Borrowed(hll);
     }
+
+    let mut dense = hll.clone();
+    dense.densify();
+    Cow::Owned(dense)
+}
+
+/// Restrict `query` to documents that have not expired
+fn live_documents(query: Value) -> Value {
+    json!({
+        "bool": {
+            "filter": [query],
+            "must_not": [{ "range": { "expires_at": { "lte": unix_millis_now() } } }]
+        }
+    })
+}
+
+/// Check a search or scroll response and parse its body
+async fn search_body(response: std::result::Result<Response, elasticsearch::Error>) -> Result<Value> {
+    let response = response.map_err(|e| HllError::Storage(format!("Failed to list keys: {}", e)))?;
+
+    if !response.status_code().is_success() {
+        return Err(HllError::Storage(format!(
+            "Elasticsearch returned status: {}",
+            response.status_code()
+        )));
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| HllError::Storage(format!("Failed to parse response: {}", e)))
+}
+
+/// Keys of the documents in a search response
+fn keys_from_hits(body: &Value) -> Result<Vec<String>> {
+    let hits = body["hits"]["hits"]
+        .as_array()
+        .ok_or_else(|| HllError::Storage("Invalid response format".to_string()))?;
+
+    Ok(hits
+        .iter()
+        .filter_map(|hit| hit["_source"]["key"].as_str())
+        .map(String::from)
+        .collect())
+}
+
+fn unix_millis_now() -> u64 {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    u64::try_from(millis).unwrap_or(u64::MAX)
+}
+
+/// Whether a document's `_source` carries an expiry that has passed
+fn is_expired(source: &Value) -> bool {
+    match source["expires_at"].as_u64() {
+        Some(expires_at) => expires_at <= unix_millis_now(),
+        None => false,
+    }
+}
+
+/// Decode the HyperLogLog held in a document's `_source`
+fn hll_from_source(source: &Value) -> Result<HyperLogLog> {
+    if !source["hll"].is_null() {
+        return Ok(serde_json::from_value(source["hll"].clone())?);
+    }
+
+    // Documents stored before the structured layout keep a JSON string
+    let hll_data = source["hll_data"]
+        .as_str()
+        .ok_or_else(|| HllError::Storage("Missing hll field".to_string()))?;
+
+    let hll: HyperLogLog = serde_json::from_str(hll_data)?;
+    Ok(hll)
 }
 
 #[async_trait]
 impl Storage for ElasticsearchStorage {
     async fn store(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
-        let serialized = serde_json::to_string(hll)?;
+        self.store_with_ttl(key, hll, None).await
+    }
+
+    async fn store_with_ttl(&self, key: &str, hll: &HyperLogLog, ttl: Option<Duration>) -> Result<()> {
+        let mut body = json!({
+            "key": key,
+            "hll": hll_document(hll)?,
+            "precision": hll.precision(),
+        });
+        if let Some(ttl) = ttl {
+            let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+            body["expires_at"] = json!(unix_millis_now().saturating_add(ttl));
+        }
 
         let response = self
             .client
             .index(IndexParts::IndexId(&self.index_name, key))
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| HllError::Storage(format!("Failed to store: {}", e)))?;
+
+        if !response.status_code().is_success() {
+            return Err(HllError::Storage(format!(
+                "Elasticsearch returned status: {}",
+                response.status_code()
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn store_keep_ttl(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        let hll_value = hll_document(hll)?;
+
+        let response = self
+            .client
+            .update(UpdateParts::IndexId(&self.index_name, key))
+            .retry_on_conflict(RETRY_ON_CONFLICT)
             .body(json!({
-                "key": key,
-                "hll_data": serialized,
-                "precision": hll.precision(),
+                "script": {
+                    "source": REPLACE_SCRIPT,
+                    "lang": "painless",
+                    "params": {
+                        "hll": hll_value,
+                        "precision": hll.precision(),
+                        "now": unix_millis_now(),
+                    }
+                },
+                "upsert": {
+                    "key": key,
+                    "hll": hll_value,
+                    "precision": hll.precision(),
+                }
             }))
             .send()
             .await
@@ -91,12 +536,60 @@ impl Storage for ElasticsearchStorage {
             .await
             .map_err(|e| HllError::Storage(format!("Failed to parse response: {}", e)))?;
 
-        let hll_data = body["_source"]["hll_data"]
-            .as_str()
-            .ok_or_else(|| HllError::Storage("Missing hll_data field".to_string()))?;
+        if is_expired(&body["_source"]) {
+            return Err(HllError::NotFound(key.to_string()));
+        }
+        hll_from_source(&body["_source"])
+    }
+
+    async fn load_many_parallel(&self, keys: &[&str]) -> Result<Vec<Option<HyperLogLog>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let response = self
+            .client
+            .mget(MgetParts::Index(&self.index_name))
+            .body(json!({ "ids": keys }))
+            .send()
+            .await
+            .map_err(|e| HllError::Storage(format!("Failed to load: {}", e)))?;
+
+        if !response.status_code().is_success() {
+            return Err(HllError::Storage(format!(
+                "Elasticsearch returned status: {}",
+                response.status_code()
+            )));
+        }
+
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| HllError::Storage(format!("Failed to parse response: {}", e)))?;
+
+        let docs = body["docs"]
+            .as_array()
+            .ok_or_else(|| HllError::Storage("Invalid response format".to_string()))?;
+
+        // mget returns documents in request order, with `found: false` for misses
+        docs.iter()
+            .map(|doc| match doc["found"].as_bool() {
+                Some(true) if !is_expired(&doc["_source"]) => {
+                    hll_from_source(&doc["_source"]).map(Some)
+                }
+                _ => Ok(None),
+            })
+            .collect()
+    }
+
+    async fn merge_into(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        self.scripted_merge(key, hll, false).await?;
+        Ok(())
+    }
 
-        let hll: HyperLogLog = serde_json::from_str(hll_data)?;
-        Ok(hll)
+    async fn merge_into_counting(&self, key: &str, hll: &HyperLogLog) -> Result<u64> {
+        let body = self.scripted_merge(key, hll, true).await?;
+        Ok(hll_from_source(&body["get"]["_source"])?.count())
     }
 
     async fn delete(&self, key: &str) -> Result<()> {
@@ -121,50 +614,212 @@ impl Storage for ElasticsearchStorage {
         let response = self
             .client
             .get(GetParts::IndexId(&self.index_name, key))
+            ._source_includes(&["expires_at"])
             .send()
             .await
             .map_err(|e| HllError::Storage(format!("Failed to check existence: {}", e)))?;
 
-        Ok(response.status_code().is_success())
+        if !response.status_code().is_success() {
+            return Ok(false);
+        }
+
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| HllError::Storage(format!("Failed to parse response: {}", e)))?;
+
+        Ok(!is_expired(&body["_source"]))
     }
 
     async fn list_keys(&self) -> Result<Vec<String>> {
-        let response = self
+        self.search_keys(json!({ "match_all": {} })).await
+    }
+
+    fn list_keys_stream(&self) -> BoxStream<'_, Result<String>> {
+        self.scroll_keys(json!({ "match_all": {} }))
+    }
+
+    async fn list_keys_by_precision(&self, precision: u8) -> Result<Vec<String>> {
+        self.search_keys(json!({ "term": { "precision": precision } })).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Start a fake node that answers every request with an empty 404 and
+    /// counts how many it served
+    async fn mock_node() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    // One request per connection is enough for these tests
+                    while let Ok(n) = socket.read(&mut buf).await {
+                        if n == 0 {
+                            return;
+                        }
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        let _ = socket
+                            .write_all(
+                                b"HTTP/1.1 404 Not Found\r\n\
+                                  content-type: application/json\r\n\
+                                  x-elastic-product: Elasticsearch\r\n\
+                                  content-length: 2\r\n\r\n{}",
+                            )
+                            .await;
+                    }
+                });
+            }
+        });
+
+        (url, hits)
+    }
+
+    #[tokio::test]
+    async fn test_requests_spread_across_nodes() {
+        let (first_url, first_hits) = mock_node().await;
+        let (second_url, second_hits) = mock_node().await;
+
+        let storage = ElasticsearchStorage::builder("hll_test_pool")
+            .nodes([first_url, second_url])
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+
+        for i in 0..8 {
+            assert!(!storage.exists(&format!("key{}", i)).await.unwrap());
+        }
+
+        let first = first_hits.load(Ordering::SeqCst);
+        let second = second_hits.load(Ordering::SeqCst);
+        assert_eq!(first + second, 8);
+        assert!(first > 0 && second > 0, "Requests not spread: {} vs {}", first, second);
+    }
+
+    #[test]
+    fn test_hll_document_keeps_register_array() {
+        let mut hll = HyperLogLog::new_sparse(4).unwrap();
+        hll.add_str("a");
+
+        let document = hll_document(&hll).unwrap();
+        let registers = document["registers"].as_array().unwrap();
+        assert_eq!(registers.len(), 16);
+        assert!(document.get("sparse").is_none());
+        assert_eq!(hll_from_source(&json!({ "hll": document })).unwrap(), hll);
+    }
+
+    /// Connects to the cluster named by `ELASTICSEARCH_TEST_URL`, or returns
+    /// `None` so live tests are skipped when no cluster is available
+    fn test_storage(index_name: &str) -> Option<ElasticsearchStorage> {
+        let url = std::env::var("ELASTICSEARCH_TEST_URL").ok()?;
+        Some(ElasticsearchStorage::with_url(&url, index_name).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_merge_into() {
+        let Some(storage) = test_storage("hll_test_merge_into") else {
+            return;
+        };
+        let storage = Arc::new(storage);
+        storage.delete("merged").await.unwrap();
+
+        let mut expected = HyperLogLog::new(12).unwrap();
+        let mut tasks = Vec::new();
+
+        for worker in 0..8 {
+            let mut hll = HyperLogLog::new(12).unwrap();
+            for i in 0..1000 {
+                hll.add(&(worker * 1000 + i));
+            }
+            expected.merge(&hll).unwrap();
+
+            let storage = storage.clone();
+            tasks.push(tokio::spawn(async move {
+                storage.merge_into("merged", &hll).await
+            }));
+        }
+
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let loaded = storage.load("merged").await.unwrap();
+        assert_eq!(loaded.count(), expected.count(), "Concurrent merges lost updates");
+
+        storage.delete("merged").await.unwrap();
+    }
+
+    /// Store `total` empty sketches as `key_0`, `key_1`, ... and make them
+    /// visible to searches
+    async fn store_keys(storage: &ElasticsearchStorage, total: usize) {
+        use futures::stream::StreamExt;
+
+        let hll = HyperLogLog::new(4).unwrap();
+        stream::iter(0..total)
+            .map(|i| {
+                let hll = &hll;
+                async move { storage.store(&format!("key_{}", i), hll).await }
+            })
+            .buffer_unordered(32)
+            .try_collect::<()>()
+            .await
+            .unwrap();
+        storage
             .client
-            .search(SearchParts::Index(&[&self.index_name]))
-            .body(json!({
-                "query": {
-                    "match_all": {}
-                },
-                "_source": ["key"],
-                "size": 10000
-            }))
+            .indices()
+            .refresh(elasticsearch::indices::IndicesRefreshParts::Index(&[&storage.index_name]))
             .send()
             .await
-            .map_err(|e| HllError::Storage(format!("Failed to list keys: {}", e)))?;
+            .unwrap();
+    }
 
-        if !response.status_code().is_success() {
-            return Err(HllError::Storage(format!(
-                "Elasticsearch returned status: {}",
-                response.status_code()
-            )));
-        }
+    #[tokio::test]
+    async fn test_list_keys_stream_past_10000() {
+        let Some(storage) = test_storage("hll_test_list_keys_stream") else {
+            return;
+        };
+        // More than one search can return, and not a multiple of the page size
+        let total = 10_500;
+        store_keys(&storage, total).await;
 
-        let body: Value = response
-            .json()
-            .await
-            .map_err(|e| HllError::Storage(format!("Failed to parse response: {}", e)))?;
+        let mut keys: Vec<String> = storage.list_keys_stream().try_collect().await.unwrap();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), total);
 
-        let hits = body["hits"]["hits"]
-            .as_array()
-            .ok_or_else(|| HllError::Storage("Invalid response format".to_string()))?;
+        for key in keys {
+            storage.delete(&key).await.unwrap();
+        }
+    }
 
-        let keys = hits
-            .iter()
-            .filter_map(|hit| hit["_source"]["key"].as_str())
-            .map(String::from)
-            .collect();
+    #[tokio::test]
+    async fn test_list_keys_past_10000() {
+        let Some(storage) = test_storage("hll_test_list_keys") else {
+            return;
+        };
+        store_keys(&storage, 12_000).await;
+
+        let keys = storage.list_keys().await.unwrap();
+        assert_eq!(keys.len(), 12_000);
+        assert_eq!(storage.list_keys_by_precision(4).await.unwrap().len(), 12_000);
 
-        Ok(keys)
+        for key in keys {
+            storage.delete(&key).await.unwrap();
+        }
     }
 }
@@ -1,47 +1,150 @@
 use crate::{HyperLogLog, Result, HllError};
-use crate::storage::Storage;
+use crate::storage::{Compression, SerializationFormat, Storage};
 use async_trait::async_trait;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::fs;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 /// File-based storage backend for HyperLogLog structures
+///
+/// Each key is a `<key>.hll` file. A key stored with a TTL also gets a
+/// `<key>.ttl` sidecar holding its expiry as Unix milliseconds; expired keys
+/// are removed the next time they are loaded, checked or listed.
 #[derive(Debug, Clone)]
 pub struct FileStorage {
     base_path: PathBuf,
+    format: SerializationFormat,
+    compression: Compression,
 }
 
 impl FileStorage {
-    /// Create a new FileStorage with the given base directory
+    /// Create a new FileStorage with the given base directory, storing JSON
     pub async fn new(base_path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_format(base_path, SerializationFormat::Json).await
+    }
+
+    /// Create a new FileStorage that writes files in `format`
+    ///
+    /// Files are read back in whatever format they were written in, detected
+    /// from their first byte, so switching formats leaves existing files
+    /// readable.
+    pub async fn with_format(base_path: impl AsRef<Path>, format: SerializationFormat) -> Result<Self> {
+        Self::with_options(base_path, format, Compression::None).await
+    }
+
+    /// Create a new FileStorage that compresses the JSON files it writes
+    ///
+    /// Files are decompressed according to their magic bytes, so files
+    /// written uncompressed or with another compression still load.
+    pub async fn with_compression(base_path: impl AsRef<Path>, compression: Compression) -> Result<Self> {
+        Self::with_options(base_path, SerializationFormat::Json, compression).await
+    }
+
+    /// Create a new FileStorage with both the format and the compression set
+    pub async fn with_options(
+        base_path: impl AsRef<Path>,
+        format: SerializationFormat,
+        compression: Compression,
+    ) -> Result<Self> {
         let base_path = base_path.as_ref().to_path_buf();
         fs::create_dir_all(&base_path).await?;
 
-        Ok(Self { base_path })
+        Ok(Self { base_path, format, compression })
+    }
+
+    /// Serialization format newly written files use
+    pub fn format(&self) -> SerializationFormat {
+        self.format
+    }
+
+    /// Compression applied to newly written files
+    pub fn compression(&self) -> Compression {
+        self.compression
     }
 
     fn key_to_path(&self, key: &str) -> PathBuf {
         self.base_path.join(format!("{}.hll", key))
     }
+
+    fn key_to_ttl_path(&self, key: &str) -> PathBuf {
+        self.base_path.join(format!("{}.ttl", key))
+    }
+
+    async fn write_file(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        let mut file = fs::File::create(path).await?;
+        file.write_all(contents).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Delete `key` if its expiry has passed, returning whether it had
+    async fn purge_if_expired(&self, key: &str) -> Result<bool> {
+        let contents = match fs::read_to_string(self.key_to_ttl_path(key)).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+
+        let expires_at: u128 = contents.trim().parse().map_err(|e| {
+            HllError::Storage(format!("Invalid expiry for key {}: {}", key, e))
+        })?;
+        if unix_millis(SystemTime::now()) < expires_at {
+            return Ok(false);
+        }
+
+        self.delete(key).await?;
+        Ok(true)
+    }
+
+    /// Read the precision of a stored HyperLogLog without building it
+    async fn peek_precision(&self, key: &str) -> Result<u8> {
+        let contents = fs::read(self.key_to_path(key)).await?;
+        let contents = Compression::decompress(&contents)?;
+        self.format_of(&contents).decode_precision(&contents)
+    }
+
+    /// Format `contents` were written in, falling back to the configured one
+    fn format_of(&self, contents: &[u8]) -> SerializationFormat {
+        SerializationFormat::detect(contents).unwrap_or(self.format)
+    }
 }
 
 #[async_trait]
 impl Storage for FileStorage {
     async fn store(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
-        let path = self.key_to_path(key);
-        let serialized = serde_json::to_vec(hll)?;
+        self.store_with_ttl(key, hll, None).await
+    }
 
-        let mut file = fs::File::create(&path).await?;
-        file.write_all(&serialized).await?;
-        file.flush().await?;
+    async fn store_with_ttl(&self, key: &str, hll: &HyperLogLog, ttl: Option<Duration>) -> Result<()> {
+        let serialized = self.compression.compress(self.format.encode(hll)?)?;
+        self.write_file(&self.key_to_path(key), &serialized).await?;
+
+        let ttl_path = self.key_to_ttl_path(key);
+        match ttl {
+            Some(ttl) => {
+                // An expiry past the end of time never comes
+                let expires_at = SystemTime::now().checked_add(ttl).map_or(u128::MAX, unix_millis);
+                self.write_file(&ttl_path, expires_at.to_string().as_bytes()).await?;
+            }
+            None => remove_if_exists(&ttl_path).await?,
+        }
 
         Ok(())
     }
 
+    async fn store_keep_ttl(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        // A past expiry must not carry over to the new sketch
+        self.purge_if_expired(key).await?;
+
+        let serialized = self.compression.compress(self.format.encode(hll)?)?;
+        self.write_file(&self.key_to_path(key), &serialized).await
+    }
+
     async fn load(&self, key: &str) -> Result<HyperLogLog> {
         let path = self.key_to_path(key);
 
-        if !path.exists() {
+        if !path.exists() || self.purge_if_expired(key).await? {
             return Err(HllError::NotFound(key.to_string()));
         }
 
@@ -49,8 +152,8 @@ impl Storage for FileStorage {
         let mut contents = Vec::new();
         file.read_to_end(&mut contents).await?;
 
-        let hll = serde_json::from_slice(&contents)?;
-        Ok(hll)
+        let contents = Compression::decompress(&contents)?;
+        self.format.decode_detected(&contents)
     }
 
     async fn delete(&self, key: &str) -> Result<()> {
@@ -59,13 +162,14 @@ impl Storage for FileStorage {
         if path.exists() {
             fs::remove_file(&path).await?;
         }
+        remove_if_exists(&self.key_to_ttl_path(key)).await?;
 
         Ok(())
     }
 
     async fn exists(&self, key: &str) -> Result<bool> {
         let path = self.key_to_path(key);
-        Ok(path.exists())
+        Ok(path.exists() && !self.purge_if_expired(key).await?)
     }
 
     async fn list_keys(&self) -> Result<Vec<String>> {
@@ -78,7 +182,9 @@ impl Storage for FileStorage {
                 if ext == "hll" {
                     if let Some(stem) = path.file_stem() {
                         if let Some(key) = stem.to_str() {
-                            keys.push(key.to_string());
+                            if !self.purge_if_expired(key).await? {
+                                keys.push(key.to_string());
+                            }
                         }
                     }
                 }
@@ -87,6 +193,27 @@ impl Storage for FileStorage {
 
         Ok(keys)
     }
+
+    async fn list_keys_by_precision(&self, precision: u8) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for key in self.list_keys().await? {
+            if self.peek_precision(&key).await? == precision {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+async fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
 }
 
 #[cfg(test)]
@@ -113,4 +240,293 @@ mod tests {
 
         let _ = fs::remove_dir_all(&temp_dir).await;
     }
+
+    #[tokio::test]
+    async fn test_list_keys_by_precision() {
+        let temp_dir = std::env::temp_dir().join("hll_test_by_precision");
+        let _ = fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+
+        for (key, precision) in [("a", 10), ("b", 12), ("c", 10), ("d", 12)] {
+            let mut hll = HyperLogLog::new(precision).unwrap();
+            hll.add_str(key);
+            storage.store(key, &hll).await.unwrap();
+        }
+
+        let mut keys = storage.list_keys_by_precision(10).await.unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "c"]);
+
+        assert!(storage.list_keys_by_precision(14).await.unwrap().is_empty());
+
+        let _ = fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_serialization_formats() {
+        let temp_dir = std::env::temp_dir().join("hll_test_formats");
+        let _ = fs::remove_dir_all(&temp_dir).await;
+
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..1000 {
+            hll.add(&i);
+        }
+
+        #[allow(unused_mut)]
+        let mut formats = vec![
+            SerializationFormat::Json,
+            SerializationFormat::Binary,
+            SerializationFormat::MessagePack,
+        ];
+        #[cfg(feature = "bincode")]
+        formats.push(SerializationFormat::Bincode);
+
+        let json = FileStorage::new(temp_dir.join("mixed")).await.unwrap();
+        for format in formats {
+            let dir = temp_dir.join(format!("{:?}", format));
+            let storage = FileStorage::with_format(&dir, format).await.unwrap();
+            storage.store("key", &hll).await.unwrap();
+
+            let loaded = storage.load("key").await.unwrap();
+            assert_eq!(loaded.to_bytes(), hll.to_bytes(), "{:?} round-trip changed registers", format);
+            assert_eq!(storage.list_keys_by_precision(12).await.unwrap(), vec!["key"]);
+
+            // The format is detected from the file, not taken from the storage
+            let contents = fs::read(dir.join("key.hll")).await.unwrap();
+            assert_eq!(SerializationFormat::detect(&contents), Some(format));
+            let key = format!("{:?}", format);
+            fs::write(temp_dir.join("mixed").join(format!("{}.hll", key)), &contents).await.unwrap();
+            assert_eq!(json.load(&key).await.unwrap().to_bytes(), hll.to_bytes());
+            assert!(json.list_keys_by_precision(12).await.unwrap().contains(&key));
+        }
+
+        let _ = fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[cfg(feature = "bincode")]
+    #[tokio::test]
+    async fn test_bincode_beats_json() {
+        let temp_dir = std::env::temp_dir().join("hll_test_bincode");
+        let _ = fs::remove_dir_all(&temp_dir).await;
+
+        let mut hll = HyperLogLog::new(16).unwrap();
+        for i in 0..100_000 {
+            hll.add(&i);
+        }
+
+        let json = FileStorage::new(temp_dir.join("json")).await.unwrap();
+        let bincode = FileStorage::with_format(temp_dir.join("bincode"), SerializationFormat::Bincode)
+            .await
+            .unwrap();
+        json.store("key", &hll).await.unwrap();
+        bincode.store("key", &hll).await.unwrap();
+
+        let json_len = fs::metadata(temp_dir.join("json/key.hll")).await.unwrap().len();
+        let bincode_len = fs::metadata(temp_dir.join("bincode/key.hll")).await.unwrap().len();
+        assert!(bincode_len < json_len, "bincode is {} bytes, JSON {}", bincode_len, json_len);
+
+        // Best of a few rounds of encoding and decoding, to ride out noise
+        let time = |format: SerializationFormat| {
+            (0..5)
+                .map(|_| {
+                    let start = std::time::Instant::now();
+                    let bytes = format.encode(&hll).unwrap();
+                    format.decode(&bytes).unwrap();
+                    start.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+        let (json_time, bincode_time) = (time(SerializationFormat::Json), time(SerializationFormat::Bincode));
+        assert!(bincode_time < json_time, "bincode took {:?}, JSON {:?}", bincode_time, json_time);
+
+        let _ = fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let temp_dir = std::env::temp_dir().join("hll_test_compression");
+        let _ = fs::remove_dir_all(&temp_dir).await;
+
+        let mut hll = HyperLogLog::new(14).unwrap();
+        for i in 0..2000 {
+            hll.add(&i);
+        }
+
+        let plain = FileStorage::new(temp_dir.join("none")).await.unwrap();
+        plain.store("key", &hll).await.unwrap();
+        let plain_len = fs::metadata(temp_dir.join("none/key.hll")).await.unwrap().len();
+
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let dir = temp_dir.join(format!("{:?}", compression));
+            let storage = FileStorage::with_compression(&dir, compression).await.unwrap();
+            storage.store("key", &hll).await.unwrap();
+
+            let loaded = storage.load("key").await.unwrap();
+            assert_eq!(loaded.to_bytes(), hll.to_bytes(), "{:?} round-trip changed registers", compression);
+            assert_eq!(storage.list_keys_by_precision(14).await.unwrap(), vec!["key"]);
+
+            let len = fs::metadata(dir.join("key.hll")).await.unwrap().len();
+            assert!(len * 4 < plain_len, "{:?} file is {} bytes, uncompressed {}", compression, len, plain_len);
+
+            // Files written before compression was enabled still load
+            fs::copy(temp_dir.join("none/key.hll"), dir.join("old.hll")).await.unwrap();
+            assert_eq!(storage.load("old").await.unwrap().to_bytes(), hll.to_bytes());
+        }
+
+        let _ = fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_verify_flags_corrupt_key() {
+        let temp_dir = std::env::temp_dir().join("hll_test_verify");
+        let _ = fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+
+        for key in ["a", "b"] {
+            let mut hll = HyperLogLog::new(10).unwrap();
+            hll.add_str(key);
+            storage.store(key, &hll).await.unwrap();
+        }
+
+        // Parses, but has too few registers for its precision
+        fs::write(
+            temp_dir.join("truncated.hll"),
+            r#"{"precision":10,"m":1024,"registers":[0,1,2]}"#,
+        )
+        .await
+        .unwrap();
+
+        let report = storage.verify().await.unwrap();
+        assert!(!report.is_healthy());
+        assert_eq!(report.corrupt.len(), 1);
+        assert_eq!(report.corrupt[0].0, "truncated");
+
+        let mut healthy = report.healthy;
+        healthy.sort();
+        assert_eq!(healthy, vec!["a", "b"]);
+
+        let _ = fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_rollup() {
+        let temp_dir = std::env::temp_dir().join("hll_test_rollup");
+        let _ = fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+
+        let mut union = HyperLogLog::new(12).unwrap();
+        for (n, precision) in [(0, 14), (1, 14), (2, 12)] {
+            let mut hll = HyperLogLog::new(precision).unwrap();
+            for i in (n * 1000)..(n * 1000 + 1500) {
+                hll.add(&i);
+                union.add(&i);
+            }
+            storage.store(&format!("minute_{}", n), &hll).await.unwrap();
+        }
+
+        let sources = ["minute_0", "minute_1", "missing", "minute_2"];
+        assert!(storage.rollup(&sources, "hour", false).await.is_err());
+
+        let count = storage.rollup(&sources, "hour", true).await.unwrap();
+        assert_eq!(count, union.count());
+
+        let stored = storage.load("hour").await.unwrap();
+        assert_eq!(stored.precision(), 12);
+        assert_eq!(stored.count(), union.count());
+
+        let _ = fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_load_many_parallel() {
+        let temp_dir = std::env::temp_dir().join("hll_test_load_many");
+        let _ = fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+
+        for (key, precision) in [("a", 10), ("b", 12), ("c", 14)] {
+            storage.store(key, &HyperLogLog::new(precision).unwrap()).await.unwrap();
+        }
+
+        let loaded = storage
+            .load_many_parallel(&["c", "missing", "a", "b"])
+            .await
+            .unwrap();
+
+        let precisions: Vec<Option<u8>> =
+            loaded.iter().map(|hll| hll.as_ref().map(|hll| hll.precision())).collect();
+        assert_eq!(precisions, vec![Some(14), None, Some(10), Some(12)]);
+
+        let _ = fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_merge_into_counting() {
+        let temp_dir = std::env::temp_dir().join("hll_test_merge_counting");
+        let _ = fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+
+        let mut union = HyperLogLog::new(12).unwrap();
+        let mut last_count = 0;
+
+        for batch in 0..5 {
+            let mut hll = HyperLogLog::new(12).unwrap();
+            for i in (batch * 500)..(batch * 500 + 800) {
+                hll.add(&i);
+                union.add(&i);
+            }
+
+            let count = storage.merge_into_counting("total", &hll).await.unwrap();
+            assert!(count >= last_count, "Count went down: {} -> {}", last_count, count);
+            last_count = count;
+        }
+
+        assert_eq!(last_count, union.count());
+        assert_eq!(storage.load("total").await.unwrap().count(), union.count());
+
+        let _ = fs::remove_dir_all(&temp_dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_store_with_ttl() {
+        let temp_dir = std::env::temp_dir().join("hll_test_ttl");
+        let _ = fs::remove_dir_all(&temp_dir).await;
+        let storage = FileStorage::new(&temp_dir).await.unwrap();
+
+        let mut hll = HyperLogLog::new(10).unwrap();
+        hll.add_str("visitor");
+
+        let short = Some(Duration::from_millis(20));
+        storage.store_with_ttl("short", &hll, short).await.unwrap();
+        storage.store_with_ttl("long", &hll, Some(Duration::from_secs(3600))).await.unwrap();
+        storage.store_with_ttl("huge", &hll, Some(Duration::from_secs(u64::MAX))).await.unwrap();
+        // A plain store clears the expiry set before it
+        storage.store_with_ttl("cleared", &hll, short).await.unwrap();
+        storage.store("cleared", &hll).await.unwrap();
+        // A rewrite that keeps the TTL does not
+        storage.store_with_ttl("kept", &hll, short).await.unwrap();
+        storage.store_keep_ttl("kept", &hll).await.unwrap();
+
+        assert!(storage.exists("short").await.unwrap());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(!storage.exists("short").await.unwrap());
+        assert!(matches!(storage.load("short").await, Err(HllError::NotFound(_))));
+        assert_eq!(storage.load("long").await.unwrap().to_bytes(), hll.to_bytes());
+
+        let mut keys = storage.list_keys().await.unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["cleared", "huge", "long"]);
+
+        // Expired keys are removed from disk, sidecar included
+        assert!(!temp_dir.join("short.hll").exists());
+        assert!(!temp_dir.join("short.ttl").exists());
+
+        // An expired key is rewritten without its past expiry
+        storage.store_keep_ttl("kept", &hll).await.unwrap();
+        assert!(storage.exists("kept").await.unwrap());
+        assert!(!temp_dir.join("kept.ttl").exists());
+
+        let _ = fs::remove_dir_all(&temp_dir).await;
+    }
 }
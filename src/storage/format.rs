@@ -0,0 +1,143 @@
+#[cfg(feature = "bincode")]
+use crate::hll::{HyperLogLogRepr, SERDE_FORMAT_VERSION};
+use crate::{HllError, HyperLogLog, Result};
+use serde::Deserialize;
+
+/// Leading byte of the binary format's `HLL` magic
+const BINARY_MARKER: u8 = b'H';
+
+/// Leading byte of [`SerializationFormat::Bincode`] data; bincode itself has
+/// no header
+#[cfg(feature = "bincode")]
+const BINCODE_MARKER: u8 = 0xb1;
+
+/// Just the precision of a serialized HyperLogLog; the register array is
+/// skipped rather than deserialized
+#[derive(Deserialize)]
+struct PrecisionHeader {
+    precision: u8,
+}
+
+/// Leading fields of bincode data; bincode reads them by position and
+/// leaves the rest of the input alone
+#[cfg(feature = "bincode")]
+#[derive(Deserialize)]
+struct BincodeHeader {
+    version: u8,
+    precision: u8,
+}
+
+/// Encoding backends use to persist HyperLogLogs
+///
+/// Chosen once when a backend is constructed, with
+/// [`FileStorage::with_format`](super::FileStorage::with_format),
+/// [`MemoryStorage::with_format`](super::MemoryStorage::with_format) or the
+/// `with_format` of the Redis, SQLite and S3 backends. Every format
+/// round-trips the precision, register layout and registers. Each format's
+/// output is recognizable from its first byte, so
+/// [`SerializationFormat::detect`] can tell them apart when reading data
+/// back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SerializationFormat {
+    /// Human-readable JSON, the historical default
+    #[default]
+    Json,
+    /// The compact format of [`HyperLogLog::to_bytes`]
+    Binary,
+    /// MessagePack, with field names so the precision can be read on its own
+    MessagePack,
+    /// bincode behind a one-byte marker: the smallest and fastest of the
+    /// serde formats, but not self-describing
+    #[cfg(feature = "bincode")]
+    Bincode,
+}
+
+impl SerializationFormat {
+    /// Serialize `hll` in this format
+    pub fn encode(self, hll: &HyperLogLog) -> Result<Vec<u8>> {
+        match self {
+            SerializationFormat::Json => Ok(serde_json::to_vec(hll)?),
+            SerializationFormat::Binary => Ok(hll.to_bytes()),
+            SerializationFormat::MessagePack => rmp_serde::to_vec_named(hll)
+                .map_err(|e| HllError::Storage(format!("MessagePack encode error: {}", e))),
+            #[cfg(feature = "bincode")]
+            SerializationFormat::Bincode => {
+                let mut bytes = vec![BINCODE_MARKER];
+                bincode::serialize_into(&mut bytes, &HyperLogLogRepr::from(hll))
+                    .map_err(|e| HllError::Storage(format!("bincode encode error: {}", e)))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Deserialize a HyperLogLog written by [`SerializationFormat::encode`]
+    pub fn decode(self, bytes: &[u8]) -> Result<HyperLogLog> {
+        match self {
+            SerializationFormat::Json => Ok(serde_json::from_slice(bytes)?),
+            SerializationFormat::Binary => HyperLogLog::from_bytes(bytes),
+            SerializationFormat::MessagePack => rmp_serde::from_slice(bytes)
+                .map_err(|e| HllError::Storage(format!("MessagePack decode error: {}", e))),
+            #[cfg(feature = "bincode")]
+            SerializationFormat::Bincode => {
+                let body = bytes
+                    .strip_prefix(&[BINCODE_MARKER])
+                    .ok_or_else(|| HllError::Storage("Missing bincode marker byte".to_string()))?;
+                let repr: HyperLogLogRepr = bincode::deserialize(body)
+                    .map_err(|e| HllError::Storage(format!("bincode decode error: {}", e)))?;
+                HyperLogLog::try_from(repr)
+            }
+        }
+    }
+
+    /// Deserialize data in the format it was written in, detected from its
+    /// first byte, falling back to this format
+    ///
+    /// Backends load with this, so switching formats leaves data written
+    /// before the switch readable.
+    pub(crate) fn decode_detected(self, bytes: &[u8]) -> Result<HyperLogLog> {
+        Self::detect(bytes).unwrap_or(self).decode(bytes)
+    }
+
+    /// Format of serialized data, recognized by its first byte
+    ///
+    /// JSON objects open with `{`, the binary format with its `HLL` magic and
+    /// MessagePack with a map header; bincode data carries a marker byte.
+    /// `None` if the data matches none of them.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        match bytes.iter().find(|byte| !byte.is_ascii_whitespace())? {
+            b'{' => Some(SerializationFormat::Json),
+            &BINARY_MARKER => Some(SerializationFormat::Binary),
+            0x80..=0x8f | 0xde | 0xdf => Some(SerializationFormat::MessagePack),
+            #[cfg(feature = "bincode")]
+            &BINCODE_MARKER => Some(SerializationFormat::Bincode),
+            _ => None,
+        }
+    }
+
+    /// Read just the precision from serialized data, without building the
+    /// HyperLogLog
+    pub(crate) fn decode_precision(self, bytes: &[u8]) -> Result<u8> {
+        let header: PrecisionHeader = match self {
+            SerializationFormat::Json => serde_json::from_slice(bytes)?,
+            SerializationFormat::Binary => return HyperLogLog::peek_precision(bytes),
+            #[cfg(feature = "bincode")]
+            SerializationFormat::Bincode => {
+                let body = bytes
+                    .strip_prefix(&[BINCODE_MARKER])
+                    .ok_or_else(|| HllError::Storage("Missing bincode marker byte".to_string()))?;
+                let header: BincodeHeader = bincode::deserialize(body)
+                    .map_err(|e| HllError::Storage(format!("bincode decode error: {}", e)))?;
+                if header.version != SERDE_FORMAT_VERSION {
+                    return Err(HllError::Storage(format!(
+                        "Unsupported serialization format version {} (expected {})",
+                        header.version, SERDE_FORMAT_VERSION
+                    )));
+                }
+                return Ok(header.precision);
+            }
+            SerializationFormat::MessagePack => rmp_serde::from_slice(bytes)
+                .map_err(|e| HllError::Storage(format!("MessagePack decode error: {}", e)))?,
+        };
+        Ok(header.precision)
+    }
+}
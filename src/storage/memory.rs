@@ -0,0 +1,304 @@
+use crate::storage::{SerializationFormat, Storage};
+use crate::{HllError, HyperLogLog, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::Instant;
+
+/// In-memory storage backend for HyperLogLog structures
+///
+/// Nothing is persisted: the sketches live in a map and are gone when the
+/// storage is dropped. Useful for tests and for ephemeral deployments.
+/// Expiry follows the Tokio clock, so tests can pause and advance it.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: RwLock<HashMap<String, Entry>>,
+    format: Option<SerializationFormat>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    hll: HyperLogLog,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_live(&self, now: Instant) -> bool {
+        match self.expires_at {
+            Some(expires_at) => now < expires_at,
+            None => true,
+        }
+    }
+}
+
+impl MemoryStorage {
+    /// Create an empty MemoryStorage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty MemoryStorage that passes every sketch it stores
+    /// through `format`
+    ///
+    /// Loads return exactly what a persistent backend writing `format`
+    /// would, which makes this a cheap stand-in for one in tests.
+    pub fn with_format(format: SerializationFormat) -> Self {
+        Self {
+            format: Some(format),
+            ..Self::default()
+        }
+    }
+
+    /// Serialization format sketches pass through, if any
+    pub fn format(&self) -> Option<SerializationFormat> {
+        self.format
+    }
+
+    /// Replace `hll` with what it reads back as from the configured format
+    fn round_trip(&self, hll: &mut HyperLogLog) -> Result<()> {
+        if let Some(format) = self.format {
+            *hll = format.decode(&format.encode(hll)?)?;
+        }
+        Ok(())
+    }
+
+    /// Number of stored HyperLogLogs that have not expired
+    pub async fn len(&self) -> usize {
+        let now = Instant::now();
+        self.entries
+            .read()
+            .await
+            .values()
+            .filter(|entry| entry.is_live(now))
+            .count()
+    }
+
+    /// Whether nothing unexpired is stored
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Drop expired entries so their memory is released
+    ///
+    /// Expired entries are already invisible to every other method; this
+    /// only reclaims the space they hold.
+    pub async fn purge_expired(&self) {
+        let now = Instant::now();
+        self.entries.write().await.retain(|_, entry| entry.is_live(now));
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn store(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        self.store_with_ttl(key, hll, None).await
+    }
+
+    async fn store_with_ttl(&self, key: &str, hll: &HyperLogLog, ttl: Option<Duration>) -> Result<()> {
+        let mut hll = hll.clone();
+        self.round_trip(&mut hll)?;
+        let entry = Entry {
+            hll,
+            // An expiry past the end of time never comes
+            expires_at: ttl.and_then(|ttl| Instant::now().checked_add(ttl)),
+        };
+        self.entries.write().await.insert(key.to_string(), entry);
+        Ok(())
+    }
+
+    async fn store_keep_ttl(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        let mut hll = hll.clone();
+        self.round_trip(&mut hll)?;
+
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+        let expires_at = entries
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .and_then(|entry| entry.expires_at);
+        entries.insert(key.to_string(), Entry { hll, expires_at });
+        Ok(())
+    }
+
+    async fn load(&self, key: &str) -> Result<HyperLogLog> {
+        let now = Instant::now();
+        self.entries
+            .read()
+            .await
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| entry.hll.clone())
+            .ok_or_else(|| HllError::NotFound(key.to_string()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.entries.write().await.remove(key);
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        let now = Instant::now();
+        Ok(self
+            .entries
+            .read()
+            .await
+            .get(key)
+            .is_some_and(|entry| entry.is_live(now)))
+    }
+
+    async fn list_keys(&self) -> Result<Vec<String>> {
+        let now = Instant::now();
+        Ok(self
+            .entries
+            .read()
+            .await
+            .iter()
+            .filter(|(_, entry)| entry.is_live(now))
+            .map(|(key, _)| key.clone())
+            .collect())
+    }
+
+    async fn merge_into_counting(&self, key: &str, hll: &HyperLogLog) -> Result<u64> {
+        // Merge under the write lock so concurrent callers never lose updates
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+
+        match entries.get_mut(key).filter(|entry| entry.is_live(now)) {
+            Some(existing) => {
+                existing.hll.merge(hll)?;
+                self.round_trip(&mut existing.hll)?;
+                Ok(existing.hll.count())
+            }
+            None => {
+                let mut hll = hll.clone();
+                self.round_trip(&mut hll)?;
+                let count = hll.count();
+                let entry = Entry {
+                    hll,
+                    expires_at: None,
+                };
+                entries.insert(key.to_string(), entry);
+                Ok(count)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_storage() {
+        let storage = MemoryStorage::new();
+
+        let mut hll = HyperLogLog::new(10).unwrap();
+        hll.add_str("test1");
+        hll.add_str("test2");
+
+        storage.store("test_key", &hll).await.unwrap();
+        assert!(storage.exists("test_key").await.unwrap());
+
+        let loaded = storage.load("test_key").await.unwrap();
+        assert_eq!(loaded.precision(), hll.precision());
+        assert_eq!(loaded.to_bytes(), hll.to_bytes());
+
+        storage.delete("test_key").await.unwrap();
+        assert!(!storage.exists("test_key").await.unwrap());
+        assert!(storage.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn test_serialization_formats() {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        hll.add_all(0..1000);
+
+        #[allow(unused_mut)]
+        let mut formats = vec![
+            SerializationFormat::Json,
+            SerializationFormat::Binary,
+            SerializationFormat::MessagePack,
+        ];
+        #[cfg(feature = "bincode")]
+        formats.push(SerializationFormat::Bincode);
+
+        for format in formats {
+            let storage = MemoryStorage::with_format(format);
+            assert_eq!(storage.format(), Some(format));
+            storage.store("key", &hll).await.unwrap();
+            assert_eq!(storage.load("key").await.unwrap(), hll, "{:?} round-trip changed registers", format);
+
+            storage.merge_into("key", &hll).await.unwrap();
+            assert_eq!(storage.load("key").await.unwrap(), hll);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_key() {
+        let storage = MemoryStorage::new();
+
+        assert!(matches!(
+            storage.load("missing").await,
+            Err(HllError::NotFound(key)) if key == "missing"
+        ));
+        // Deleting a missing key is not an error, same as FileStorage
+        storage.delete("missing").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_list_keys_and_merge() {
+        let storage = MemoryStorage::new();
+
+        let mut a = HyperLogLog::new(12).unwrap();
+        let mut b = HyperLogLog::new(12).unwrap();
+        for i in 0..500 {
+            a.add(&i);
+            b.add(&(i + 250));
+        }
+
+        storage.store("a", &a).await.unwrap();
+        storage.merge_into("b", &b).await.unwrap();
+        let count = storage.merge_into_counting("b", &a).await.unwrap();
+
+        let mut keys = storage.list_keys().await.unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(storage.len().await, 2);
+
+        let mut expected = a.clone();
+        expected.merge(&b).unwrap();
+        assert_eq!(count, expected.count());
+        assert_eq!(storage.load("b").await.unwrap().to_bytes(), expected.to_bytes());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_store_with_ttl() {
+        let storage = MemoryStorage::new();
+        let hll = HyperLogLog::new(10).unwrap();
+
+        storage.store_with_ttl("short", &hll, Some(Duration::from_secs(60))).await.unwrap();
+        storage.store_with_ttl("long", &hll, Some(Duration::from_secs(3600))).await.unwrap();
+        storage.store("forever", &hll).await.unwrap();
+        storage.store_with_ttl("huge", &hll, Some(Duration::from_secs(u64::MAX))).await.unwrap();
+        assert_eq!(storage.len().await, 4);
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+
+        assert!(!storage.exists("short").await.unwrap());
+        assert!(matches!(storage.load("short").await, Err(HllError::NotFound(_))));
+        let mut keys = storage.list_keys().await.unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["forever", "huge", "long"]);
+
+        // Merging into an expired key starts over without the expiry
+        storage.merge_into("short", &hll).await.unwrap();
+        tokio::time::advance(Duration::from_secs(3600)).await;
+        let mut keys = storage.list_keys().await.unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["forever", "huge", "short"]);
+
+        storage.purge_expired().await;
+        assert_eq!(storage.entries.read().await.len(), 3);
+    }
+}
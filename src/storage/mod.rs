@@ -1,20 +1,70 @@
+mod compression;
 mod file;
+mod format;
+mod memory;
 
 #[cfg(feature = "elasticsearch-storage")]
 mod elasticsearch;
 
+#[cfg(feature = "redis-storage")]
+mod redis;
+
+#[cfg(feature = "s3-storage")]
+mod s3;
+
+#[cfg(feature = "sqlite-storage")]
+mod sqlite;
+
+pub use compression::Compression;
 pub use file::FileStorage;
+pub use format::SerializationFormat;
+pub use memory::MemoryStorage;
 
 #[cfg(feature = "elasticsearch-storage")]
-pub use elasticsearch::ElasticsearchStorage;
+pub use elasticsearch::{ElasticsearchStorage, ElasticsearchStorageBuilder};
+
+#[cfg(feature = "redis-storage")]
+pub use self::redis::{RedisStorage, DEFAULT_KEY_PREFIX};
+
+#[cfg(feature = "s3-storage")]
+pub use s3::S3Storage;
+
+#[cfg(feature = "sqlite-storage")]
+pub use sqlite::SqliteStorage;
 
-use crate::{HyperLogLog, Result};
+use crate::{HllError, HyperLogLog, Result};
 use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+/// Maximum number of loads [`Storage::load_many_parallel`] keeps in flight
+pub const LOAD_CONCURRENCY: usize = 16;
+
+/// Outcome of [`Storage::verify`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifyReport {
+    /// Keys that loaded and passed validation
+    pub healthy: Vec<String>,
+    /// Keys that failed to load, with the reason
+    pub corrupt: Vec<(String, String)>,
+}
+
+impl VerifyReport {
+    /// Whether every key checked out
+    pub fn is_healthy(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
 
 /// Storage backend for HyperLogLog structures
 #[async_trait]
 pub trait Storage: Send + Sync {
     /// Store a HyperLogLog with given key
+    ///
+    /// Like Redis `SET`, this clears any expiry set on the key earlier.
     async fn store(&self, key: &str, hll: &HyperLogLog) -> Result<()>;
 
     /// Load a HyperLogLog by key
@@ -28,4 +78,255 @@ pub trait Storage: Send + Sync {
 
     /// List all keys (for debugging/admin purposes)
     async fn list_keys(&self) -> Result<Vec<String>>;
+
+    /// Stream all keys instead of collecting them first
+    ///
+    /// The default implementation streams the result of
+    /// [`Storage::list_keys`]; backends that can page through their keys
+    /// override it to fetch them lazily.
+    fn list_keys_stream(&self) -> BoxStream<'_, Result<String>> {
+        stream::once(self.list_keys())
+            .map_ok(|keys| stream::iter(keys.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Store a HyperLogLog that expires `ttl` from now
+    ///
+    /// Once expired, a key behaves as if deleted: `load` returns
+    /// [`HllError::NotFound`], `exists` returns false and `list_keys` skips
+    /// it. A `ttl` of `None` is a plain [`Storage::store`], and a `ttl` too
+    /// long to represent as an expiry time keeps the key indefinitely. The
+    /// default implementation rejects TTLs; backends that can expire keys
+    /// override it.
+    async fn store_with_ttl(&self, key: &str, hll: &HyperLogLog, ttl: Option<Duration>) -> Result<()> {
+        match ttl {
+            None => self.store(key, hll).await,
+            Some(_) => Err(HllError::InvalidInput(
+                "This storage backend does not support TTLs".to_string(),
+            )),
+        }
+    }
+
+    /// Store a HyperLogLog, keeping the expiry of the key it replaces
+    ///
+    /// For rewrites of a stored sketch, such as a merge or fold, which
+    /// [`Storage::store`] would turn into a key that never expires. A missing
+    /// or expired key is stored without an expiry. The default implementation
+    /// is a plain store, which is all backends without TTLs need.
+    async fn store_keep_ttl(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        self.store(key, hll).await
+    }
+
+    /// Load several keys concurrently, preserving the order of `keys`
+    ///
+    /// Missing keys come back as `None`. The default implementation issues
+    /// up to [`LOAD_CONCURRENCY`] loads at a time; backends with a native
+    /// multi-get should override it.
+    async fn load_many_parallel(&self, keys: &[&str]) -> Result<Vec<Option<HyperLogLog>>> {
+        // Build the futures up front; mapping inside the stream trips the
+        // `Send` check that `async_trait` needs
+        let loads: Vec<_> = keys
+            .iter()
+            .map(|key| async move {
+                match self.load(key).await {
+                    Ok(hll) => Ok(Some(hll)),
+                    Err(HllError::NotFound(_)) => Ok(None),
+                    Err(e) => Err(e),
+                }
+            })
+            .collect();
+
+        stream::iter(loads)
+            .buffered(LOAD_CONCURRENCY)
+            .try_collect()
+            .await
+    }
+
+    /// List the keys whose HyperLogLog has the given precision
+    ///
+    /// The default implementation loads every key; backends override it to
+    /// read just the stored precision metadata.
+    async fn list_keys_by_precision(&self, precision: u8) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for key in self.list_keys().await? {
+            if self.load(&key).await?.precision() == precision {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Merge `hll` into the HyperLogLog stored at `key`, creating it if absent
+    ///
+    /// The default implementation is a plain load-merge-store, so concurrent
+    /// callers can lose updates. Backends that can fold registers server-side
+    /// should override this to make the merge atomic.
+    async fn merge_into(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        self.merge_into_counting(key, hll).await?;
+        Ok(())
+    }
+
+    /// Merge `hll` into the HyperLogLog stored at `key` and return the
+    /// estimated count of the result
+    ///
+    /// Same atomicity caveats as [`Storage::merge_into`]; backends that merge
+    /// server-side should return the merged state from the same operation.
+    async fn merge_into_counting(&self, key: &str, hll: &HyperLogLog) -> Result<u64> {
+        let merged = match self.load(key).await {
+            Ok(mut existing) => {
+                existing.merge(hll)?;
+                existing
+            }
+            Err(HllError::NotFound(_)) => hll.clone(),
+            Err(e) => return Err(e),
+        };
+
+        self.store_keep_ttl(key, &merged).await?;
+        Ok(merged.count())
+    }
+
+    /// Merge the HyperLogLogs at `sources` into `dest` and return the new count
+    ///
+    /// Sources may use different precisions or layouts; the result is
+    /// normalized to the narrowest one with
+    /// [`HyperLogLog::merge_normalizing`]. Only one source is held in memory
+    /// besides the accumulator. Missing sources are skipped when
+    /// `skip_missing` is set and are an error otherwise.
+    async fn rollup(&self, sources: &[&str], dest: &str, skip_missing: bool) -> Result<u64> {
+        let mut merged: Option<HyperLogLog> = None;
+
+        for key in sources {
+            let hll = match self.load(key).await {
+                Ok(hll) => hll,
+                Err(HllError::NotFound(_)) if skip_missing => continue,
+                Err(e) => return Err(e),
+            };
+
+            match merged.as_mut() {
+                None => merged = Some(hll),
+                Some(acc) => acc.merge_normalizing(&hll)?,
+            }
+        }
+
+        let merged =
+            merged.ok_or_else(|| HllError::InvalidKey("No source keys found".to_string()))?;
+        self.store(dest, &merged).await?;
+
+        Ok(merged.count())
+    }
+
+    /// Load and validate every key, collecting the corrupt ones
+    ///
+    /// Loading checks the register count, precision range and register
+    /// bounds. Keys are checked one at a time and a bad key does not stop the
+    /// scan. Keys deleted while the scan runs are left out of the report.
+    async fn verify(&self) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+
+        for key in self.list_keys().await? {
+            match self.load(&key).await {
+                Ok(_) => report.healthy.push(key),
+                Err(HllError::NotFound(_)) => {}
+                Err(e) => report.corrupt.push((key, e.to_string())),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Persist any buffered writes to the underlying store
+    ///
+    /// Write-through backends have nothing to do here, so the default is a no-op.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Spawn a background task that calls [`Storage::flush`] every `interval`
+///
+/// Flush errors are logged and the loop keeps going. Abort the returned
+/// handle to stop the task, e.g. on server shutdown.
+pub fn spawn_flusher(storage: Arc<dyn Storage>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // The first tick completes immediately; skip it so the first flush
+        // happens one full interval after startup
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            if let Err(e) = storage.flush().await {
+                tracing::warn!("Background flush failed: {}", e);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct FlushCounter {
+        flushes: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Storage for FlushCounter {
+        async fn store(&self, _key: &str, _hll: &HyperLogLog) -> Result<()> {
+            Ok(())
+        }
+
+        async fn load(&self, key: &str) -> Result<HyperLogLog> {
+            Err(HllError::NotFound(key.to_string()))
+        }
+
+        async fn delete(&self, _key: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn exists(&self, _key: &str) -> Result<bool> {
+            Ok(false)
+        }
+
+        async fn list_keys(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn flush(&self) -> Result<()> {
+            self.flushes.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_list_keys_stream() {
+        let storage = MemoryStorage::new();
+        for key in ["a", "b", "c"] {
+            storage.store(key, &HyperLogLog::new(4).unwrap()).await.unwrap();
+        }
+
+        let mut keys: Vec<String> = storage.list_keys_stream().try_collect().await.unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+        assert_eq!(FlushCounter::default().list_keys_stream().count().await, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_spawn_flusher_calls_flush() {
+        let storage = Arc::new(FlushCounter::default());
+        let handle = spawn_flusher(storage.clone(), Duration::from_millis(10));
+
+        tokio::time::sleep(Duration::from_millis(35)).await;
+        handle.abort();
+
+        assert!(
+            storage.flushes.load(Ordering::SeqCst) >= 1,
+            "Flusher should have called flush at least once"
+        );
+    }
 }
@@ -0,0 +1,284 @@
+use crate::storage::{SerializationFormat, Storage};
+use crate::{HllError, HyperLogLog, Result};
+use async_trait::async_trait;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::{AsyncCommands, RedisError, SetExpiry, SetOptions};
+use std::time::Duration;
+
+/// Prefix [`RedisStorage::new`] puts in front of every key
+pub const DEFAULT_KEY_PREFIX: &str = "hll:";
+
+/// Keys requested per `SCAN` round trip
+const SCAN_COUNT: usize = 1000;
+
+/// Connection attempts retried before giving up
+const CONNECT_RETRIES: usize = 3;
+
+/// Longest wait between connection attempts, in milliseconds
+const CONNECT_MAX_DELAY_MS: u64 = 1000;
+
+/// Redis-based storage backend for HyperLogLog structures
+///
+/// Each HyperLogLog is a plain string value holding its compact binary
+/// encoding ([`HyperLogLog::to_bytes`]), or another
+/// [`SerializationFormat`] set with [`RedisStorage::with_format`], under the
+/// namespaced key `prefix + key`. Keys are listed with `SCAN`, so listing never blocks the
+/// server the way `KEYS` would.
+#[derive(Clone)]
+pub struct RedisStorage {
+    connection: ConnectionManager,
+    prefix: String,
+    format: SerializationFormat,
+}
+
+impl RedisStorage {
+    /// Connect to the Redis server at `url`, e.g. `redis://localhost:6379`,
+    /// using [`DEFAULT_KEY_PREFIX`]
+    pub async fn new(url: &str) -> Result<Self> {
+        Self::with_prefix(url, DEFAULT_KEY_PREFIX).await
+    }
+
+    /// Connect to the Redis server at `url`, namespacing keys with `prefix`
+    ///
+    /// The connection is re-established automatically if it drops. A server
+    /// that cannot be reached fails after a few quick retries; the redis
+    /// crate's default backoff would keep retrying for minutes.
+    pub async fn with_prefix(url: &str, prefix: impl Into<String>) -> Result<Self> {
+        let client = redis::Client::open(url).map_err(storage_error)?;
+        let config = ConnectionManagerConfig::new()
+            .set_factor(2)
+            .set_number_of_retries(CONNECT_RETRIES)
+            .set_max_delay(CONNECT_MAX_DELAY_MS);
+        let connection = ConnectionManager::new_with_config(client, config)
+            .await
+            .map_err(storage_error)?;
+
+        Ok(Self {
+            connection,
+            prefix: prefix.into(),
+            format: SerializationFormat::Binary,
+        })
+    }
+
+    /// Write values in `format` instead of the binary format
+    ///
+    /// Values are read back in whatever format they were written in.
+    pub fn with_format(mut self, format: SerializationFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn redis_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+fn storage_error(e: RedisError) -> HllError {
+    HllError::Storage(format!("Redis error: {}", e))
+}
+
+/// Escape glob metacharacters so `prefix` matches literally in `SCAN MATCH`
+fn escape_glob(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[async_trait]
+impl Storage for RedisStorage {
+    async fn store(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        let mut connection = self.connection.clone();
+        connection
+            .set::<_, _, ()>(self.redis_key(key), self.format.encode(hll)?)
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn store_with_ttl(&self, key: &str, hll: &HyperLogLog, ttl: Option<Duration>) -> Result<()> {
+        let Some(ttl) = ttl else {
+            return self.store(key, hll).await;
+        };
+
+        // PSETEX rejects a zero expiry, so round up to the first millisecond
+        let millis = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1);
+        let mut connection = self.connection.clone();
+        connection
+            .pset_ex::<_, _, ()>(self.redis_key(key), self.format.encode(hll)?, millis)
+            .await
+            .map_err(storage_error)
+    }
+
+    /// Needs Redis 6.0 or later for `SET ... KEEPTTL`
+    async fn store_keep_ttl(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        let options = SetOptions::default().with_expiration(SetExpiry::KEEPTTL);
+        let mut connection = self.connection.clone();
+        connection
+            .set_options::<_, _, ()>(self.redis_key(key), self.format.encode(hll)?, options)
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn load(&self, key: &str) -> Result<HyperLogLog> {
+        let mut connection = self.connection.clone();
+        let bytes: Option<Vec<u8>> = connection
+            .get(self.redis_key(key))
+            .await
+            .map_err(storage_error)?;
+
+        match bytes {
+            Some(bytes) => self.format.decode_detected(&bytes),
+            None => Err(HllError::NotFound(key.to_string())),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let mut connection = self.connection.clone();
+        connection
+            .del::<_, ()>(self.redis_key(key))
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        let mut connection = self.connection.clone();
+        connection
+            .exists(self.redis_key(key))
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn list_keys(&self) -> Result<Vec<String>> {
+        let mut connection = self.connection.clone();
+        let pattern = format!("{}*", escape_glob(&self.prefix));
+        let mut keys = Vec::new();
+        let mut cursor: u64 = 0;
+
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut connection)
+                .await
+                .map_err(storage_error)?;
+
+            keys.extend(
+                batch
+                    .iter()
+                    .filter_map(|key| key.strip_prefix(&self.prefix))
+                    .map(String::from),
+            );
+
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        // SCAN may return a key more than once
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Connect to the Redis named by `HLL_TEST_REDIS_URL`, or `None` to skip
+    async fn test_storage(name: &str) -> Option<RedisStorage> {
+        let url = std::env::var("HLL_TEST_REDIS_URL").ok()?;
+        let storage = RedisStorage::with_prefix(&url, format!("hll-test:{}:", name))
+            .await
+            .unwrap();
+
+        for key in storage.list_keys().await.unwrap() {
+            storage.delete(&key).await.unwrap();
+        }
+        Some(storage)
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage() {
+        let Some(storage) = test_storage("round_trip").await else {
+            return;
+        };
+
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..1000 {
+            hll.add(&i);
+        }
+
+        storage.store("visitors", &hll).await.unwrap();
+        assert!(storage.exists("visitors").await.unwrap());
+        assert_eq!(storage.load("visitors").await.unwrap().to_bytes(), hll.to_bytes());
+        assert_eq!(storage.list_keys().await.unwrap(), vec!["visitors"]);
+
+        storage.delete("visitors").await.unwrap();
+        assert!(!storage.exists("visitors").await.unwrap());
+        assert!(matches!(
+            storage.load("visitors").await,
+            Err(HllError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_redis_store_with_ttl() {
+        let Some(storage) = test_storage("ttl").await else {
+            return;
+        };
+
+        let hll = HyperLogLog::new(10).unwrap();
+        storage.store_with_ttl("short", &hll, Some(Duration::from_millis(50))).await.unwrap();
+        storage.store_with_ttl("long", &hll, Some(Duration::from_secs(3600))).await.unwrap();
+        assert!(storage.exists("short").await.unwrap());
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(!storage.exists("short").await.unwrap());
+        assert_eq!(storage.list_keys().await.unwrap(), vec!["long"]);
+        storage.delete("long").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redis_list_keys_scans_prefix() {
+        let Some(storage) = test_storage("scan").await else {
+            return;
+        };
+
+        for i in 0..2500 {
+            storage.store(&format!("key_{}", i), &HyperLogLog::new(4).unwrap()).await.unwrap();
+        }
+
+        let keys = storage.list_keys().await.unwrap();
+        assert_eq!(keys.len(), 2500);
+        assert!(keys.iter().all(|key| key.starts_with("key_")));
+
+        for key in keys {
+            storage.delete(&key).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_escape_glob() {
+        assert_eq!(escape_glob("hll:"), "hll:");
+        assert_eq!(escape_glob("a*b?[c]\\"), "a\\*b\\?\\[c\\]\\\\");
+    }
+
+    #[tokio::test]
+    async fn test_connection_error() {
+        // Nothing listens on the discard port
+        assert!(matches!(
+            RedisStorage::new("redis://127.0.0.1:9/").await,
+            Err(HllError::Storage(_))
+        ));
+    }
+}
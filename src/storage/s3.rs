@@ -0,0 +1,276 @@
+use crate::storage::{SerializationFormat, Storage};
+use crate::{HllError, HyperLogLog, Result};
+use async_trait::async_trait;
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client;
+
+/// Suffix of every object holding a HyperLogLog
+const OBJECT_SUFFIX: &str = ".hll";
+
+/// S3 (or S3-compatible object storage) backend for HyperLogLog structures
+///
+/// Each HyperLogLog is the object `{prefix}/{key}.hll` in one bucket, holding
+/// its compact binary encoding ([`HyperLogLog::to_bytes`]) or the
+/// [`SerializationFormat`] set with [`S3Storage::with_format`]. Nothing is kept
+/// locally, so any number of stateless workers can share a bucket; as with
+/// the other backends, concurrent writers of the same key race.
+#[derive(Clone)]
+pub struct S3Storage {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    format: SerializationFormat,
+}
+
+impl S3Storage {
+    /// Store sketches in `bucket` under `prefix`, configuring the client from
+    /// the environment
+    ///
+    /// Credentials, region and endpoint are resolved the way the AWS CLI
+    /// does: `AWS_*` environment variables, shared config files, then
+    /// instance metadata. Set `AWS_ENDPOINT_URL` to use MinIO or another
+    /// S3-compatible service.
+    pub async fn new(bucket: impl Into<String>, prefix: impl Into<String>) -> Result<Self> {
+        let config = aws_config::load_from_env().await;
+        Ok(Self::from_client(Client::new(&config), bucket, prefix))
+    }
+
+    /// Store sketches in `bucket` under `prefix` with an existing client
+    ///
+    /// Leading and trailing slashes of `prefix` are ignored; an empty prefix
+    /// puts objects at the root of the bucket.
+    pub fn from_client(client: Client, bucket: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+            prefix: prefix.into().trim_matches('/').to_string(),
+            format: SerializationFormat::Binary,
+        }
+    }
+
+    /// Write objects in `format` instead of the binary format
+    ///
+    /// Objects are read back in whatever format they were written in.
+    pub fn with_format(mut self, format: SerializationFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Object key prefix every sketch lives under, including its slash
+    fn list_prefix(&self) -> String {
+        if self.prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", self.prefix)
+        }
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        format!("{}{}{}", self.list_prefix(), key, OBJECT_SUFFIX)
+    }
+
+    /// Inverse of [`S3Storage::object_key`]; `None` for objects that do not
+    /// hold a sketch
+    fn key_from_object<'a>(&self, object_key: &'a str, list_prefix: &str) -> Option<&'a str> {
+        object_key.strip_prefix(list_prefix)?.strip_suffix(OBJECT_SUFFIX)
+    }
+}
+
+fn storage_error(e: impl std::error::Error) -> HllError {
+    HllError::Storage(format!("S3 error: {}", DisplayErrorContext(e)))
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn store(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .body(ByteStream::from(self.format.encode(hll)?))
+            .send()
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn load(&self, key: &str) -> Result<HyperLogLog> {
+        let output = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => {
+                return Err(HllError::NotFound(key.to_string()));
+            }
+            Err(e) => return Err(storage_error(e)),
+        };
+
+        let bytes = output.body.collect().await.map_err(storage_error)?.into_bytes();
+        self.format.decode_detected(&bytes)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        // Deleting a missing object succeeds, as for the other backends
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    async fn list_keys(&self) -> Result<Vec<String>> {
+        let list_prefix = self.list_prefix();
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&list_prefix)
+            .into_paginator()
+            .send();
+
+        let mut keys = Vec::new();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(storage_error)?;
+            keys.extend(
+                page.contents()
+                    .iter()
+                    .filter_map(|object| object.key())
+                    .filter_map(|object_key| self.key_from_object(object_key, &list_prefix))
+                    .map(String::from),
+            );
+        }
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+
+    /// Client for the S3-compatible endpoint at `endpoint`, path-style as
+    /// MinIO and localstack expect
+    fn client(endpoint: &str, credentials: Credentials) -> Client {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .endpoint_url(endpoint)
+            .credentials_provider(credentials)
+            .force_path_style(true)
+            .build();
+        Client::from_conf(config)
+    }
+
+    /// Storage in the bucket named by `HLL_TEST_S3_BUCKET` at the endpoint
+    /// named by `HLL_TEST_S3_ENDPOINT`, e.g. a local MinIO, or `None` to
+    /// skip. Credentials come from `AWS_ACCESS_KEY_ID` and
+    /// `AWS_SECRET_ACCESS_KEY`.
+    async fn test_storage(name: &str) -> Option<S3Storage> {
+        let endpoint = std::env::var("HLL_TEST_S3_ENDPOINT").ok()?;
+        let bucket = std::env::var("HLL_TEST_S3_BUCKET").ok()?;
+        let credentials = Credentials::new(
+            std::env::var("AWS_ACCESS_KEY_ID").unwrap_or_default(),
+            std::env::var("AWS_SECRET_ACCESS_KEY").unwrap_or_default(),
+            None,
+            None,
+            "test",
+        );
+        let storage = S3Storage::from_client(client(&endpoint, credentials), bucket, format!("hll-test/{}", name));
+
+        for key in storage.list_keys().await.unwrap() {
+            storage.delete(&key).await.unwrap();
+        }
+        Some(storage)
+    }
+
+    #[tokio::test]
+    async fn test_s3_storage() {
+        let Some(storage) = test_storage("round_trip").await else {
+            return;
+        };
+
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..1000 {
+            hll.add(&i);
+        }
+
+        storage.store("visitors", &hll).await.unwrap();
+        assert!(storage.exists("visitors").await.unwrap());
+        assert_eq!(storage.load("visitors").await.unwrap().to_bytes(), hll.to_bytes());
+        assert_eq!(storage.list_keys().await.unwrap(), vec!["visitors"]);
+
+        storage.delete("visitors").await.unwrap();
+        assert!(!storage.exists("visitors").await.unwrap());
+        assert!(matches!(
+            storage.load("visitors").await,
+            Err(HllError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_s3_list_keys_paginates() {
+        let Some(storage) = test_storage("paginate").await else {
+            return;
+        };
+
+        // ListObjectsV2 returns at most 1000 objects per page
+        for i in 0..1500 {
+            storage.store(&format!("key_{}", i), &HyperLogLog::new(4).unwrap()).await.unwrap();
+        }
+
+        let keys = storage.list_keys().await.unwrap();
+        assert_eq!(keys.len(), 1500);
+        assert!(keys.iter().all(|key| key.starts_with("key_")));
+
+        for key in keys {
+            storage.delete(&key).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_object_keys() {
+        let client = client("http://127.0.0.1:9", Credentials::new("id", "secret", None, None, "test"));
+
+        let storage = S3Storage::from_client(client.clone(), "bucket", "/sketches/daily/");
+        assert_eq!(storage.object_key("visitors"), "sketches/daily/visitors.hll");
+        let prefix = storage.list_prefix();
+        assert_eq!(storage.key_from_object("sketches/daily/visitors.hll", &prefix), Some("visitors"));
+        assert_eq!(storage.key_from_object("sketches/daily/notes.txt", &prefix), None);
+
+        let root = S3Storage::from_client(client, "bucket", "");
+        assert_eq!(root.object_key("visitors"), "visitors.hll");
+    }
+
+    #[tokio::test]
+    async fn test_connection_error() {
+        // Nothing listens on the discard port
+        let client = client("http://127.0.0.1:9", Credentials::new("id", "secret", None, None, "test"));
+        let storage = S3Storage::from_client(client, "bucket", "hll");
+        assert!(matches!(storage.load("key").await, Err(HllError::Storage(_))));
+        assert!(matches!(storage.exists("key").await, Err(HllError::Storage(_))));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::storage::{SerializationFormat, Storage};
+use crate::{HllError, HyperLogLog, Result};
+use async_trait::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use std::path::Path;
+
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS hll (
+    key TEXT PRIMARY KEY,
+    precision INTEGER NOT NULL,
+    data BLOB NOT NULL
+)";
+
+/// SQLite-based storage backend for HyperLogLog structures
+///
+/// All sketches live in one database file, in a table
+/// `hll(key TEXT PRIMARY KEY, precision INTEGER, data BLOB)`. The blob is the
+/// compact binary encoding ([`HyperLogLog::to_bytes`]) unless
+/// [`SqliteStorage::with_format`] picks another [`SerializationFormat`]; the
+/// precision column lets [`Storage::list_keys_by_precision`] filter without
+/// decoding blobs.
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    pool: SqlitePool,
+    format: SerializationFormat,
+}
+
+impl SqliteStorage {
+    /// Open the database at `path`, creating the file and table if needed
+    pub async fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await.map_err(storage_error)?;
+
+        sqlx::query(CREATE_TABLE)
+            .execute(&pool)
+            .await
+            .map_err(storage_error)?;
+
+        Ok(Self {
+            pool,
+            format: SerializationFormat::Binary,
+        })
+    }
+
+    /// Write blobs in `format` instead of the binary format
+    ///
+    /// Blobs are read back in whatever format they were written in.
+    pub fn with_format(mut self, format: SerializationFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+fn storage_error(e: sqlx::Error) -> HllError {
+    HllError::Storage(format!("SQLite error: {}", e))
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn store(&self, key: &str, hll: &HyperLogLog) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO hll (key, precision, data) VALUES (?, ?, ?)")
+            .bind(key)
+            .bind(hll.precision())
+            .bind(self.format.encode(hll)?)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    async fn load(&self, key: &str) -> Result<HyperLogLog> {
+        let data: Option<Vec<u8>> = sqlx::query_scalar("SELECT data FROM hll WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        match data {
+            Some(data) => self.format.decode_detected(&data),
+            None => Err(HllError::NotFound(key.to_string())),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM hll WHERE key = ?")
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        let found: Option<i64> = sqlx::query_scalar("SELECT 1 FROM hll WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        Ok(found.is_some())
+    }
+
+    async fn list_keys(&self) -> Result<Vec<String>> {
+        sqlx::query_scalar("SELECT key FROM hll")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn list_keys_by_precision(&self, precision: u8) -> Result<Vec<String>> {
+        sqlx::query_scalar("SELECT key FROM hll WHERE precision = ?")
+            .bind(precision)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(storage_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_storage(name: &str) -> SqliteStorage {
+        let path = std::env::temp_dir().join(format!("hll_test_{}.db", name));
+        let _ = std::fs::remove_file(&path);
+        SqliteStorage::new(&path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_storage() {
+        let storage = test_storage("sqlite").await;
+
+        let mut hll = HyperLogLog::new(10).unwrap();
+        hll.add_str("test1");
+        hll.add_str("test2");
+
+        storage.store("test_key", &hll).await.unwrap();
+        assert!(storage.exists("test_key").await.unwrap());
+
+        let loaded = storage.load("test_key").await.unwrap();
+        assert_eq!(loaded.to_bytes(), hll.to_bytes());
+        assert_eq!(storage.list_keys().await.unwrap(), vec!["test_key"]);
+
+        storage.delete("test_key").await.unwrap();
+        assert!(!storage.exists("test_key").await.unwrap());
+        assert!(matches!(
+            storage.load("test_key").await,
+            Err(HllError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_overwrite() {
+        let storage = test_storage("sqlite_overwrite").await;
+
+        let mut hll = HyperLogLog::new(10).unwrap();
+        hll.add_str("first");
+        storage.store("key", &hll).await.unwrap();
+
+        let mut replacement = HyperLogLog::new(12).unwrap();
+        for i in 0..1000 {
+            replacement.add(&i);
+        }
+        storage.store("key", &replacement).await.unwrap();
+
+        let loaded = storage.load("key").await.unwrap();
+        assert_eq!(loaded.to_bytes(), replacement.to_bytes());
+        assert_eq!(storage.list_keys().await.unwrap(), vec!["key"]);
+        assert!(storage.list_keys_by_precision(10).await.unwrap().is_empty());
+        assert_eq!(storage.list_keys_by_precision(12).await.unwrap(), vec!["key"]);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_formats() {
+        let binary = test_storage("sqlite_formats").await;
+        let mut hll = HyperLogLog::new(12).unwrap();
+        hll.add_all(0..1000);
+        binary.store("binary", &hll).await.unwrap();
+
+        // Switching formats keeps blobs written in the old one readable
+        let json = binary.clone().with_format(SerializationFormat::Json);
+        json.store("json", &hll).await.unwrap();
+        for key in ["binary", "json"] {
+            assert_eq!(json.load(key).await.unwrap(), hll);
+            assert_eq!(binary.load(key).await.unwrap(), hll);
+        }
+        assert_eq!(json.list_keys_by_precision(12).await.unwrap().len(), 2);
+    }
+}
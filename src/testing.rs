@@ -0,0 +1,54 @@
+use crate::HyperLogLog;
+
+/// Assert that re-adding elements already in `hll` leaves its count stable
+///
+/// Records the count, re-adds the `usize` values `0..duplicates` (the way
+/// most test sketches are populated) and panics if the count moved by more
+/// than `tolerance`, relative to the recorded count. Values that were never
+/// added count as new elements, so populate `hll` with at least that range.
+pub fn assert_count_stable(hll: &mut HyperLogLog, duplicates: usize, tolerance: f64) {
+    let before = hll.count();
+
+    for i in 0..duplicates {
+        hll.add(&i);
+    }
+
+    let after = hll.count();
+    let drift = (after as f64 - before as f64).abs() / (before as f64).max(1.0);
+    assert!(
+        drift <= tolerance,
+        "Count moved from {} to {} after re-adding {} duplicates ({:.2}% > {:.2}%)",
+        before,
+        after,
+        duplicates,
+        drift * 100.0,
+        tolerance * 100.0
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn populated(n: usize) -> HyperLogLog {
+        let mut hll = HyperLogLog::new(12).unwrap();
+        for i in 0..n {
+            hll.add(&i);
+        }
+        hll
+    }
+
+    #[test]
+    fn test_duplicates_keep_count_stable() {
+        let mut hll = populated(10000);
+        assert_count_stable(&mut hll, 5000, 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "after re-adding 2000 duplicates")]
+    fn test_new_elements_exceed_tolerance() {
+        // Only 0..100 are present, so most of the "duplicates" are new
+        let mut hll = populated(100);
+        assert_count_stable(&mut hll, 2000, 0.05);
+    }
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "cli")]
+
+use assert_cmd::Command;
+use hyperloglog::HyperLogLog;
+use std::path::PathBuf;
+
+/// Empty scratch directory for one test
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn hll() -> Command {
+    Command::cargo_bin("hll").unwrap()
+}
+
+fn ids(range: std::ops::Range<u32>) -> String {
+    range.map(|i| format!("user_{}\n", i)).collect()
+}
+
+fn count(files: &[&PathBuf]) -> u64 {
+    let output = hll().arg("count").args(files).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap().trim().parse().unwrap()
+}
+
+#[test]
+fn test_add_and_count() {
+    let dir = scratch_dir("hll_cli_add_count");
+    let file = dir.join("visitors.hll");
+
+    hll().args(["create", "--precision", "12"]).arg(&file).assert().success();
+    assert_eq!(count(&[&file]), 0);
+
+    hll().arg("add").arg(&file).write_stdin(ids(0..1000)).assert().success();
+    // Lines already in the sketch do not add to the count
+    hll().arg("add").arg(&file).write_stdin(ids(500..1500)).assert().success();
+
+    let mut expected = HyperLogLog::new(12).unwrap();
+    for i in 0..1500 {
+        expected.add_str(&format!("user_{}", i));
+    }
+    let stored = HyperLogLog::from_bytes(&std::fs::read(&file).unwrap()).unwrap();
+    assert_eq!(stored, expected);
+    assert_eq!(count(&[&file]), expected.count());
+
+    // Creating over an existing file fails and keeps it
+    hll().arg("create").arg(&file).assert().failure();
+    assert_eq!(count(&[&file]), expected.count());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_merge() {
+    let dir = scratch_dir("hll_cli_merge");
+    let [a, b, union] = ["a.hll", "b.hll", "union.hll"].map(|name| dir.join(name));
+
+    for (file, range) in [(&a, 0..600), (&b, 400..1000)] {
+        hll().arg("create").arg(file).assert().success();
+        hll().arg("add").arg(file).write_stdin(ids(range)).assert().success();
+    }
+
+    hll().arg("merge").arg(&a).arg(&b).arg("-o").arg(&union).assert().success();
+
+    let merged = count(&[&union]);
+    assert!((merged as f64 - 1000.0).abs() < 30.0, "Got {}", merged);
+    // Counting several files counts their union without writing it
+    assert_eq!(count(&[&a, &b]), merged);
+
+    let small = dir.join("small.hll");
+    hll().args(["create", "-p", "10"]).arg(&small).assert().success();
+    hll().arg("merge").arg(&a).arg(&small).arg("-o").arg(&union).assert().failure();
+    hll().arg("count").arg(dir.join("missing.hll")).assert().failure();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}